@@ -92,6 +92,7 @@ impl EmailImporterAgent {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
@@ -125,6 +125,7 @@ impl FileWatcherAgent {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
@@ -155,6 +156,7 @@ impl FileWatcherAgent {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
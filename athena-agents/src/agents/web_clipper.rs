@@ -78,6 +78,7 @@ impl WebClipperAgent {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
@@ -115,6 +116,7 @@ impl WebClipperAgent {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
@@ -1,10 +1,20 @@
 use athena_core::system::AthenaSystem;
-use athena_graph::entity::{Edge, Entity, NodeId};
-use athena_graph::query::GraphPattern;
+use athena_graph::aggregate::{AggregateQuery, AggregateResult, Facet, GroupBy, Metric};
+use athena_graph::entity::{Edge, Entity, GraphUpdate, NodeId};
+use athena_graph::error::GraphError;
+use athena_graph::named_graph::{GraphInfo, GraphTransfer, TransferResult};
+use athena_graph::query::{Direction, FilterExpr, GraphPattern, OrderBy};
+use athena_graph::query_lang::{TextQuery, TextQueryResult};
+use athena_graph::schema::{EntityType, SchemaViolation};
+use athena_graph::search::SearchHit;
+use athena_graph::timeline::{BucketSize, HistogramBucket, TimeQuery, TimelineEvent};
+use athena_graph::traversal::{ExpandQuery, GraphPath, Neighborhood, PathQuery, ShortestPathQuery};
+use athena_graph::vector::VectorHit;
+use athena_graph::version::{AsOf, Checkpoint, CheckpointVerification, NodeRevision, VersionId};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -32,13 +42,52 @@ pub async fn health_handler() -> Json<HealthResponse> {
 #[derive(Serialize)]
 pub struct NodeListResponse {
     pub nodes: Vec<Entity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
-pub async fn list_nodes(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json<NodeListResponse>, StatusCode> {
+#[derive(Deserialize)]
+pub struct ListNodesParams {
+    pub limit: Option<usize>,
+    /// Comma-separated sort keys, each `name`, `name:asc` or `name:desc`.
+    pub order_by: Option<String>,
+    /// Comma-separated properties to return.
+    pub fields: Option<String>,
+    pub cursor: Option<String>,
+    /// Comma-separated named graphs; all graphs when absent.
+    pub graph: Option<String>,
+}
+
+/// Splits a comma-separated query parameter, dropping empty items.
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+fn graph_scope(graph: Option<&str>) -> Vec<String> {
+    graph.map(|graphs| split_list(graphs).map(str::to_string).collect()).unwrap_or_default()
+}
+
+pub async fn list_nodes(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Query(params): Query<ListNodesParams>,
+) -> Result<Json<NodeListResponse>, StatusCode> {
+    let order_by = params
+        .order_by
+        .as_deref()
+        .map(|spec| split_list(spec).map(OrderBy::parse).collect::<anyhow::Result<Vec<_>>>())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .unwrap_or_default();
     let pattern = GraphPattern {
-        node_filters: vec![],
-        edge_filters: vec![],
-        limit: Some(100),
+        limit: Some(params.limit.unwrap_or(100)),
+        order_by,
+        fields: params
+            .fields
+            .as_deref()
+            .map(|fields| split_list(fields).map(str::to_string).collect()),
+        cursor: params.cursor,
+        graphs: graph_scope(params.graph.as_deref()),
+        ..Default::default()
     };
 
     let result = handlers
@@ -46,10 +95,11 @@ pub async fn list_nodes(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json
         .graph_engine
         .query(&pattern)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| query_error_status(&e))?;
 
     Ok(Json(NodeListResponse {
         nodes: result.nodes,
+        next_cursor: result.next_cursor,
     }))
 }
 
@@ -57,6 +107,8 @@ pub async fn list_nodes(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json
 pub struct CreateNodeRequest {
     pub label: String,
     pub properties: Option<std::collections::HashMap<String, athena_graph::entity::PropertyValue>>,
+    /// Named graph to create the node in; the default graph when absent.
+    pub graph: Option<String>,
 }
 
 pub async fn create_node(
@@ -70,26 +122,39 @@ pub async fn create_node(
         created_at: chrono::Utc::now().timestamp(),
         updated_at: chrono::Utc::now().timestamp(),
         version: 1,
+        graph: request.graph,
     };
 
-    handlers
+    let entity = handlers
         .system
         .graph_engine
-        .put_node(entity.clone())
+        .put_node(entity)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| write_error_status(&e))?;
 
     Ok(Json(entity))
 }
 
-pub async fn get_node(
+#[derive(Deserialize)]
+pub struct UpdateNodeRequest {
+    pub label: String,
+    pub properties: Option<std::collections::HashMap<String, athena_graph::entity::PropertyValue>>,
+    /// Version the client last read; the update is rejected with 409 if the node changed since.
+    /// Without it the update overwrites whatever is stored.
+    pub version: Option<u64>,
+    /// Named graph to move the node to; the node stays in its graph when absent.
+    pub graph: Option<String>,
+}
+
+pub async fn update_node(
     State(handlers): State<Arc<ApiHandlers>>,
     Path(id): Path<String>,
+    Json(request): Json<UpdateNodeRequest>,
 ) -> Result<Json<Entity>, StatusCode> {
     let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
     let node_id = NodeId::from_uuid(uuid);
 
-    let node = handlers
+    let existing = handlers
         .system
         .graph_engine
         .get_node(&node_id)
@@ -97,9 +162,113 @@ pub async fn get_node(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
+    let entity = Entity {
+        label: request.label,
+        properties: request.properties.unwrap_or_default(),
+        version: request.version.unwrap_or(existing.version),
+        graph: request.graph.or(existing.graph),
+        ..existing
+    };
+
+    let entity = handlers
+        .system
+        .graph_engine
+        .put_node(entity)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(entity))
+}
+
+#[derive(Deserialize)]
+pub struct AsOfParams {
+    pub version: Option<u64>,
+    pub timestamp: Option<i64>,
+}
+
+impl AsOfParams {
+    fn as_of(&self) -> Option<AsOf> {
+        match (self.version, self.timestamp) {
+            (Some(version), _) => Some(AsOf::Version(VersionId(version))),
+            (None, Some(timestamp)) => Some(AsOf::Timestamp(timestamp)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Current state of a node, or its state at `?version=` / `?timestamp=` when given.
+pub async fn get_node(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+    Query(params): Query<AsOfParams>,
+) -> Result<Json<Entity>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let node_id = NodeId::from_uuid(uuid);
+
+    let engine = &handlers.system.graph_engine;
+    let node = match params.as_of() {
+        Some(at) => engine.get_node_as_of(&node_id, at).await,
+        None => engine.get_node(&node_id).await,
+    }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
     Ok(Json(node))
 }
 
+#[derive(Serialize)]
+pub struct NodeHistoryResponse {
+    pub revisions: Vec<NodeRevision>,
+}
+
+pub async fn node_history(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+) -> Result<Json<NodeHistoryResponse>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let revisions = handlers
+        .system
+        .graph_engine
+        .node_history(&NodeId::from_uuid(uuid))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(NodeHistoryResponse { revisions }))
+}
+
+#[derive(Deserialize)]
+pub struct RestoreNodeRequest {
+    pub version: u64,
+}
+
+pub async fn restore_node(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+    Json(request): Json<RestoreNodeRequest>,
+) -> Result<Json<Entity>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let node_id = NodeId::from_uuid(uuid);
+    let version = VersionId(request.version);
+
+    let engine = &handlers.system.graph_engine;
+    if engine
+        .get_node_as_of(&node_id, AsOf::Version(version))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_none()
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let entity = engine
+        .restore_node(&node_id, version)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(entity))
+}
+
 pub async fn delete_node(
     State(handlers): State<Arc<ApiHandlers>>,
     Path(id): Path<String>,
@@ -112,32 +281,92 @@ pub async fn delete_node(
         .graph_engine
         .delete_node(&node_id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| write_error_status(&e))?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[derive(Deserialize)]
+pub struct NeighborsParams {
+    pub direction: Option<Direction>,
+    pub label: Option<String>,
+}
+
+pub async fn node_neighbors(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+    Query(params): Query<NeighborsParams>,
+) -> Result<Json<NodeListResponse>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let node_id = NodeId::from_uuid(uuid);
+
+    let nodes = handlers
+        .system
+        .graph_engine
+        .neighbors(
+            &node_id,
+            params.direction.unwrap_or(Direction::Both),
+            params.label.as_deref(),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(NodeListResponse {
+        nodes,
+        next_cursor: None,
+    }))
+}
+
+pub async fn node_edges(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+) -> Result<Json<EdgeListResponse>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let node_id = NodeId::from_uuid(uuid);
+
+    let edges = handlers
+        .system
+        .graph_engine
+        .edges_of(&node_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EdgeListResponse { edges, next_cursor: None }))
+}
+
 #[derive(Serialize)]
 pub struct EdgeListResponse {
     pub edges: Vec<Edge>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
-pub async fn list_edges(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json<EdgeListResponse>, StatusCode> {
-    let pattern = GraphPattern {
-        node_filters: vec![],
-        edge_filters: vec![],
-        limit: Some(100),
-    };
+#[derive(Deserialize)]
+pub struct ListEdgesParams {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    /// Comma-separated named graphs; all graphs when absent.
+    pub graph: Option<String>,
+}
 
-    let result = handlers
+pub async fn list_edges(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Query(params): Query<ListEdgesParams>,
+) -> Result<Json<EdgeListResponse>, StatusCode> {
+    let page = handlers
         .system
         .graph_engine
-        .query(&pattern)
+        .list_edges(
+            &graph_scope(params.graph.as_deref()),
+            params.limit.unwrap_or(100),
+            params.cursor.as_deref(),
+        )
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| query_error_status(&e))?;
 
     Ok(Json(EdgeListResponse {
-        edges: result.edges,
+        edges: page.edges,
+        next_cursor: page.next_cursor,
     }))
 }
 
@@ -146,6 +375,8 @@ pub struct CreateEdgeRequest {
     pub from: String,
     pub to: String,
     pub label: String,
+    /// Named graph to create the edge in; the default graph when absent.
+    pub graph: Option<String>,
 }
 
 pub async fn create_edge(
@@ -163,42 +394,699 @@ pub async fn create_edge(
         properties: std::collections::HashMap::new(),
         created_at: chrono::Utc::now().timestamp(),
         version: 1,
+        graph: request.graph,
     };
 
-    handlers
+    let edge = handlers
         .system
         .graph_engine
-        .put_edge(edge.clone())
+        .put_edge(edge)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| write_error_status(&e))?;
 
     Ok(Json(edge))
 }
 
+#[derive(Serialize)]
+pub struct UpdateResponse {
+    pub version: VersionId,
+}
+
+/// Applies a `GraphUpdate` atomically: either every change is written or none is.
+pub async fn apply_update(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(update): Json<GraphUpdate>,
+) -> Result<Json<UpdateResponse>, StatusCode> {
+    let version = handlers
+        .system
+        .graph_engine
+        .update(&update)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(UpdateResponse { version }))
+}
+
+pub async fn create_checkpoint(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json<Checkpoint>, StatusCode> {
+    let checkpoint = handlers
+        .system
+        .graph_engine
+        .checkpoint()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(checkpoint))
+}
+
+#[derive(Serialize)]
+pub struct CheckpointListResponse {
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+pub async fn list_checkpoints(
+    State(handlers): State<Arc<ApiHandlers>>,
+) -> Result<Json<CheckpointListResponse>, StatusCode> {
+    let checkpoints = handlers
+        .system
+        .graph_engine
+        .list_checkpoints()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(CheckpointListResponse { checkpoints }))
+}
+
+pub async fn verify_checkpoint(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+) -> Result<Json<CheckpointVerification>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let verification = handlers
+        .system
+        .graph_engine
+        .verify_checkpoint(&uuid)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(verification))
+}
+
+pub async fn list_snapshots(
+    State(handlers): State<Arc<ApiHandlers>>,
+) -> Result<Json<CheckpointListResponse>, StatusCode> {
+    let checkpoints = handlers
+        .system
+        .graph_engine
+        .list_snapshots()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(CheckpointListResponse { checkpoints }))
+}
+
+/// Rolls the whole graph back to a snapshot; the rollback is committed as a new version.
+pub async fn restore_snapshot(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+) -> Result<Json<UpdateResponse>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let version = handlers
+        .system
+        .graph_engine
+        .restore_snapshot(&uuid)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(UpdateResponse { version }))
+}
+
+#[derive(Deserialize)]
+pub struct PruneSnapshotsRequest {
+    pub keep: usize,
+}
+
+pub async fn prune_snapshots(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(request): Json<PruneSnapshotsRequest>,
+) -> Result<Json<CheckpointListResponse>, StatusCode> {
+    let checkpoints = handlers
+        .system
+        .graph_engine
+        .prune_snapshots(request.keep)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(CheckpointListResponse { checkpoints }))
+}
+
+pub async fn dangling_edges(
+    State(handlers): State<Arc<ApiHandlers>>,
+) -> Result<Json<EdgeListResponse>, StatusCode> {
+    let edges = handlers
+        .system
+        .graph_engine
+        .dangling_edges()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EdgeListResponse { edges, next_cursor: None }))
+}
+
+#[derive(Serialize)]
+pub struct SchemaListResponse {
+    pub schemas: Vec<EntityType>,
+}
+
+pub async fn list_schemas(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json<SchemaListResponse>, StatusCode> {
+    let schemas = handlers
+        .system
+        .graph_engine
+        .schemas()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SchemaListResponse { schemas }))
+}
+
+pub async fn put_schema(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(schema): Json<EntityType>,
+) -> Result<Json<EntityType>, StatusCode> {
+    let schema = handlers
+        .system
+        .graph_engine
+        .put_schema(schema)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(schema))
+}
+
+pub async fn delete_schema(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = handlers
+        .system
+        .graph_engine
+        .delete_schema(&name)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(if deleted { StatusCode::NO_CONTENT } else { StatusCode::NOT_FOUND })
+}
+
+#[derive(Serialize)]
+pub struct SchemaViolationsResponse {
+    pub violations: Vec<SchemaViolation>,
+}
+
+pub async fn schema_violations(
+    State(handlers): State<Arc<ApiHandlers>>,
+) -> Result<Json<SchemaViolationsResponse>, StatusCode> {
+    let violations = handlers
+        .system
+        .graph_engine
+        .schema_violations()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SchemaViolationsResponse { violations }))
+}
+
+/// Deletes the edges `dangling_edges` reports and returns them.
+pub async fn repair_dangling_edges(
+    State(handlers): State<Arc<ApiHandlers>>,
+) -> Result<Json<EdgeListResponse>, StatusCode> {
+    let edges = handlers
+        .system
+        .graph_engine
+        .repair_dangling_edges()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EdgeListResponse { edges, next_cursor: None }))
+}
+
+/// A bad cursor or a query vector of the wrong size is the client's mistake; any other query
+/// failure is ours.
+fn query_error_status(error: &anyhow::Error) -> StatusCode {
+    match error.downcast_ref::<GraphError>() {
+        Some(GraphError::InvalidCursor(_)) | Some(GraphError::DimensionMismatch { .. }) => {
+            StatusCode::BAD_REQUEST
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Version conflicts are the client's to resolve (re-read and retry), everything else is ours.
+fn write_error_status(error: &anyhow::Error) -> StatusCode {
+    match error.downcast_ref::<GraphError>() {
+        Some(GraphError::VersionConflict { .. }) | Some(GraphError::NodeHasEdges { .. }) => {
+            StatusCode::CONFLICT
+        }
+        Some(GraphError::DanglingEdge { .. })
+        | Some(GraphError::SchemaViolation { .. })
+        | Some(GraphError::InvalidGraphName(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+        Some(GraphError::CheckpointNotFound(_)) | Some(GraphError::SnapshotNotFound(_)) => {
+            StatusCode::NOT_FOUND
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 #[derive(Deserialize)]
 pub struct QueryRequest {
     pub pattern: GraphPattern,
+    /// Query a past state of the graph instead of the current one.
+    #[serde(default)]
+    pub as_of: Option<AsOf>,
 }
 
 pub async fn query_graph(
     State(handlers): State<Arc<ApiHandlers>>,
     Json(request): Json<QueryRequest>,
 ) -> Result<Json<athena_graph::query::QueryResult>, StatusCode> {
+    let engine = &handlers.system.graph_engine;
+    let result = match request.as_of {
+        Some(at) => engine.query_as_of(&request.pattern, at).await,
+        None => engine.query(&request.pattern).await,
+    }
+    .map_err(|e| query_error_status(&e))?;
+
+    Ok(Json(result))
+}
+
+pub async fn expand_node(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(query): Json<ExpandQuery>,
+) -> Result<Json<Neighborhood>, StatusCode> {
     let result = handlers
         .system
         .graph_engine
-        .query(&request.pattern)
+        .expand(&query)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(Json(result))
 }
 
+#[derive(Serialize)]
+pub struct PathListResponse {
+    pub paths: Vec<GraphPath>,
+}
+
+pub async fn find_paths(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(query): Json<PathQuery>,
+) -> Result<Json<PathListResponse>, StatusCode> {
+    let paths = handlers
+        .system
+        .graph_engine
+        .find_paths(&query)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(PathListResponse { paths }))
+}
+
+pub async fn shortest_path(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(query): Json<ShortestPathQuery>,
+) -> Result<Json<GraphPath>, StatusCode> {
+    let path = handlers
+        .system
+        .graph_engine
+        .shortest_path(&query)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(path))
+}
+
+#[derive(Deserialize)]
+pub struct SparqlRequest {
+    pub query: String,
+}
+
+/// Accepts either a raw `application/sparql-query` body or JSON `{"query": "..."}`.
+pub async fn sparql_query(
+    State(handlers): State<Arc<ApiHandlers>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, StatusCode> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/json"))
+        .unwrap_or(false);
+    let query = if is_json {
+        serde_json::from_str::<SparqlRequest>(&body)
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+            .query
+    } else {
+        body
+    };
+
+    let response = handlers
+        .system
+        .graph_engine
+        .sparql(&query)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(([(header::CONTENT_TYPE, response.content_type)], response.body))
+}
+
+#[derive(Deserialize)]
+pub struct TextQueryRequest {
+    pub query: String,
+}
+
+#[derive(Serialize)]
+pub struct QueryErrorResponse {
+    pub error: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+pub async fn text_query(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(request): Json<TextQueryRequest>,
+) -> Result<Json<TextQueryResult>, (StatusCode, Json<QueryErrorResponse>)> {
+    let compiled = TextQuery::compile_str(&request.query).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(QueryErrorResponse {
+                error: e.message,
+                line: Some(e.line),
+                column: Some(e.column),
+            }),
+        )
+    })?;
+
+    let result = compiled
+        .execute(handlers.system.graph_engine.as_ref())
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(QueryErrorResponse {
+                    error: e.to_string(),
+                    line: None,
+                    column: None,
+                }),
+            )
+        })?;
+
+    Ok(Json(result))
+}
+
 #[derive(Serialize)]
 pub struct AgentListResponse {
     pub agents: Vec<Uuid>,
 }
 
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    pub hits: Vec<SearchHit>,
+}
+
+pub async fn search(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchResponse>, StatusCode> {
+    let hits = handlers
+        .system
+        .graph_engine
+        .search(&params.q, params.limit.unwrap_or(20))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SearchResponse { hits }))
+}
+
+/// `days` selects the last N days and overrides `from`/`to`; `fields` is a comma-separated
+/// list of timestamp fields.
+#[derive(Deserialize)]
+pub struct TimelineParams {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub days: Option<u32>,
+    pub fields: Option<String>,
+    pub limit: Option<usize>,
+    /// `asc` (default) or `desc`.
+    pub order: Option<String>,
+    pub bucket: Option<BucketSize>,
+    /// Seconds east of UTC, for histogram day boundaries.
+    pub utc_offset: Option<i64>,
+}
+
+impl TimelineParams {
+    fn time_query(&self) -> Result<TimeQuery, StatusCode> {
+        let mut query = match self.days {
+            Some(days) => TimeQuery::last_days(days),
+            None => TimeQuery {
+                from: self.from,
+                to: self.to,
+                ..Default::default()
+            },
+        };
+        query.fields = self
+            .fields
+            .iter()
+            .flat_map(|fields| fields.split(','))
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect();
+        query.limit = self.limit;
+        query.newest_first = match self.order.as_deref() {
+            None => query.newest_first,
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => return Err(StatusCode::BAD_REQUEST),
+        };
+        Ok(query)
+    }
+}
+
+#[derive(Serialize)]
+pub struct TimelineResponse {
+    pub events: Vec<TimelineEvent>,
+}
+
+pub async fn timeline(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Query(params): Query<TimelineParams>,
+) -> Result<Json<TimelineResponse>, StatusCode> {
+    let events = handlers
+        .system
+        .graph_engine
+        .timeline(&params.time_query()?)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(TimelineResponse { events }))
+}
+
+#[derive(Serialize)]
+pub struct HistogramResponse {
+    pub bucket: BucketSize,
+    pub buckets: Vec<HistogramBucket>,
+}
+
+pub async fn timeline_histogram(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Query(params): Query<TimelineParams>,
+) -> Result<Json<HistogramResponse>, StatusCode> {
+    let bucket = params.bucket.unwrap_or(BucketSize::Day);
+    let buckets = handlers
+        .system
+        .graph_engine
+        .time_histogram(&params.time_query()?, bucket, params.utc_offset.unwrap_or(0))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(HistogramResponse { bucket, buckets }))
+}
+
+#[derive(Serialize)]
+pub struct GraphListResponse {
+    pub graphs: Vec<GraphInfo>,
+}
+
+pub async fn list_graphs(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json<GraphListResponse>, StatusCode> {
+    let graphs = handlers
+        .system
+        .graph_engine
+        .graphs()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(GraphListResponse { graphs }))
+}
+
+/// Moves or copies a subgraph between named graphs.
+pub async fn transfer_graph(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(transfer): Json<GraphTransfer>,
+) -> Result<Json<TransferResult>, StatusCode> {
+    let result = handlers
+        .system
+        .graph_engine
+        .transfer(&transfer)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(result))
+}
+
+#[derive(Serialize)]
+pub struct DeleteGraphResponse {
+    pub deleted_nodes: usize,
+}
+
+pub async fn delete_graph(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(name): Path<String>,
+) -> Result<Json<DeleteGraphResponse>, StatusCode> {
+    let deleted_nodes = handlers
+        .system
+        .graph_engine
+        .delete_graph(&name)
+        .await
+        .map_err(|e| write_error_status(&e))?;
+
+    Ok(Json(DeleteGraphResponse { deleted_nodes }))
+}
+
+/// The common aggregations as query parameters; `POST /api/v1/stats` takes a full
+/// `AggregateQuery` including a filter.
+#[derive(Deserialize)]
+pub struct StatsParams {
+    /// `property` or `property:function`, e.g. `created_at:month`.
+    pub group_by: Option<String>,
+    /// Comma-separated, e.g. `count,avg(size)`.
+    pub metrics: Option<String>,
+    /// Comma-separated, e.g. `type,name:extension`.
+    pub facets: Option<String>,
+    /// Values per facet.
+    pub top: Option<usize>,
+    pub limit: Option<usize>,
+    /// Seconds east of UTC, for keys derived from dates.
+    pub utc_offset: Option<i64>,
+    /// Comma-separated named graphs; all graphs when absent.
+    pub graph: Option<String>,
+}
+
+impl StatsParams {
+    fn aggregate_query(&self) -> anyhow::Result<AggregateQuery> {
+        let top = self.top.unwrap_or(10);
+        // A pattern holding only the graph scope yields the matching node filter
+        let scope = GraphPattern {
+            graphs: graph_scope(self.graph.as_deref()),
+            ..Default::default()
+        };
+        Ok(AggregateQuery {
+            filter: Some(scope.node_expr()),
+            group_by: self.group_by.as_deref().map(GroupBy::parse).transpose()?,
+            metrics: split_list(self.metrics.as_deref().unwrap_or("count"))
+                .map(Metric::parse)
+                .collect::<anyhow::Result<_>>()?,
+            facets: split_list(self.facets.as_deref().unwrap_or_default())
+                .map(|facet| Facet::parse(facet, top))
+                .collect::<anyhow::Result<_>>()?,
+            limit: self.limit,
+            utc_offset: self.utc_offset.unwrap_or(0),
+        })
+    }
+}
+
+pub async fn stats(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<AggregateResult>, StatusCode> {
+    let query = params.aggregate_query().map_err(|_| StatusCode::BAD_REQUEST)?;
+    aggregate(&handlers, &query).await
+}
+
+pub async fn stats_query(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(query): Json<AggregateQuery>,
+) -> Result<Json<AggregateResult>, StatusCode> {
+    query.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+    aggregate(&handlers, &query).await
+}
+
+async fn aggregate(handlers: &ApiHandlers, query: &AggregateQuery) -> Result<Json<AggregateResult>, StatusCode> {
+    let result = handlers
+        .system
+        .graph_engine
+        .aggregate(query)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(result))
+}
+
+/// Either `vector` or `text` (embedded with the server's embedder) is required.
+#[derive(Deserialize)]
+pub struct NearestRequest {
+    #[serde(default)]
+    pub vector: Option<Vec<f32>>,
+    #[serde(default)]
+    pub text: Option<String>,
+    pub k: Option<usize>,
+    #[serde(default)]
+    pub filter: Option<FilterExpr>,
+}
+
+#[derive(Serialize)]
+pub struct VectorHitsResponse {
+    pub hits: Vec<VectorHit>,
+}
+
+pub async fn nearest_nodes(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Json(request): Json<NearestRequest>,
+) -> Result<Json<VectorHitsResponse>, StatusCode> {
+    let engine = &handlers.system.graph_engine;
+    let vector = match (request.vector, request.text) {
+        (Some(vector), _) => vector,
+        (None, Some(text)) => engine
+            .embed(&text)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        (None, None) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let hits = engine
+        .nearest(&vector, request.k.unwrap_or(10), request.filter.as_ref())
+        .await
+        .map_err(|e| query_error_status(&e))?;
+
+    Ok(Json(VectorHitsResponse { hits }))
+}
+
+#[derive(Deserialize)]
+pub struct SimilarParams {
+    pub k: Option<usize>,
+}
+
+pub async fn similar_nodes(
+    State(handlers): State<Arc<ApiHandlers>>,
+    Path(id): Path<String>,
+    Query(params): Query<SimilarParams>,
+) -> Result<Json<VectorHitsResponse>, StatusCode> {
+    let uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let node_id = NodeId::from_uuid(uuid);
+
+    let engine = &handlers.system.graph_engine;
+    if engine
+        .get_node(&node_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_none()
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let hits = engine
+        .similar_to(&node_id, params.k.unwrap_or(10), None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(VectorHitsResponse { hits }))
+}
+
 pub async fn list_agents(State(handlers): State<Arc<ApiHandlers>>) -> Result<Json<AgentListResponse>, StatusCode> {
     let runtime = handlers.system.agent_runtime.read().await;
     let agents = runtime.list_agents().await;
@@ -1,5 +1,5 @@
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use std::sync::Arc;
@@ -10,9 +10,38 @@ pub fn create_router(handlers: Arc<ApiHandlers>) -> Router {
     Router::new()
         .route("/api/v1/health", get(health_handler))
         .route("/api/v1/nodes", get(list_nodes).post(create_node))
-        .route("/api/v1/nodes/:id", get(get_node).delete(delete_node))
+        .route("/api/v1/nodes/:id", get(get_node).put(update_node).delete(delete_node))
+        .route("/api/v1/nodes/:id/history", get(node_history))
+        .route("/api/v1/nodes/:id/restore", post(restore_node))
+        .route("/api/v1/nodes/:id/neighbors", get(node_neighbors))
+        .route("/api/v1/nodes/:id/edges", get(node_edges))
+        .route("/api/v1/nodes/:id/similar", get(similar_nodes))
         .route("/api/v1/edges", get(list_edges).post(create_edge))
+        .route("/api/v1/update", post(apply_update))
         .route("/api/v1/query", post(query_graph))
+        .route("/api/v1/query/text", post(text_query))
+        .route("/api/v1/sparql", post(sparql_query))
+        .route("/api/v1/search", get(search))
+        .route("/api/v1/vector/nearest", post(nearest_nodes))
+        .route("/api/v1/timeline", get(timeline))
+        .route("/api/v1/timeline/histogram", get(timeline_histogram))
+        .route("/api/v1/stats", get(stats).post(stats_query))
+        .route("/api/v1/graphs", get(list_graphs))
+        .route("/api/v1/graphs/transfer", post(transfer_graph))
+        .route("/api/v1/graphs/:name", delete(delete_graph))
+        .route("/api/v1/traverse/expand", post(expand_node))
+        .route("/api/v1/traverse/paths", post(find_paths))
+        .route("/api/v1/traverse/shortest-path", post(shortest_path))
+        .route("/api/v1/checkpoints", get(list_checkpoints).post(create_checkpoint))
+        .route("/api/v1/checkpoints/:id/verify", post(verify_checkpoint))
+        .route("/api/v1/snapshots", get(list_snapshots))
+        .route("/api/v1/snapshots/prune", post(prune_snapshots))
+        .route("/api/v1/snapshots/:id/restore", post(restore_snapshot))
+        .route("/api/v1/integrity/dangling-edges", get(dangling_edges))
+        .route("/api/v1/integrity/repair", post(repair_dangling_edges))
+        .route("/api/v1/schemas", get(list_schemas).post(put_schema))
+        .route("/api/v1/schemas/violations", get(schema_violations))
+        .route("/api/v1/schemas/:name", delete(delete_schema))
         .route("/api/v1/agents", get(list_agents).post(load_agent))
         .route("/api/v1/agents/:id", delete(unload_agent))
         .with_state(handlers)
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use athena_api::server::ApiServer;
 use athena_core::{config::AthenaConfig, system::AthenaSystem};
+use athena_graph::timeline::{BucketSize, TimeQuery};
 use clap::{Parser, Subcommand};
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -16,6 +17,10 @@ struct Cli {
 
     #[arg(long, global = true)]
     config: Option<PathBuf>,
+
+    /// Named graph (workspace) to work in; commands that read see all graphs when absent
+    #[arg(long, global = true)]
+    graph: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -36,12 +41,157 @@ enum Commands {
         label: String,
     },
     /// List all nodes
-    ListNodes,
-    /// Query the graph
+    ListNodes {
+        #[arg(long, default_value = "100")]
+        limit: usize,
+        /// Sort key, e.g. --order-by updated_at:desc --order-by label
+        #[arg(long = "order-by")]
+        order_by: Vec<String>,
+        /// Properties to print next to each node, e.g. --field title --field url
+        #[arg(long = "field")]
+        fields: Vec<String>,
+        /// Continue from the cursor printed with the previous page
+        #[arg(long)]
+        cursor: Option<String>,
+    },
+    /// Query the graph, e.g. `MATCH (n:email)-[:reply]->(m) RETURN n, m LIMIT 10`
     Query {
+        query: String,
+    },
+    /// Full-text search over node labels and string properties
+    Search {
+        query: String,
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Find nodes whose text is most similar to a node's
+    Similar {
+        id: String,
+        #[arg(long, default_value = "10")]
+        k: usize,
+    },
+    /// Show nodes by their timestamps (created, updated and date properties)
+    Timeline {
+        /// Only the last N days, newest first
+        #[arg(long)]
+        days: Option<u32>,
+        /// Unix timestamp to start from (inclusive)
+        #[arg(long)]
+        from: Option<i64>,
+        /// Unix timestamp to stop at (exclusive)
+        #[arg(long)]
+        to: Option<i64>,
+        /// Timestamp fields to include, e.g. --field updated_at --field clipped_at
+        #[arg(long = "field")]
+        fields: Vec<String>,
+        #[arg(long, default_value = "50")]
+        limit: usize,
+        /// Print counts per hour, day or week instead of the nodes
+        #[arg(long)]
+        histogram: Option<String>,
+    },
+    /// Count nodes per group and show the most frequent property values
+    Stats {
+        /// Group by a property, e.g. --group-by type or --group-by created_at:month
+        #[arg(long = "group-by")]
+        group_by: Option<String>,
+        /// Metrics per group, e.g. --metric count --metric "avg(size)"
+        #[arg(long = "metric")]
+        metrics: Vec<String>,
+        /// Top values of a property, e.g. --facet type --facet name:extension
+        #[arg(long = "facet")]
+        facets: Vec<String>,
+        /// Values shown per facet
+        #[arg(long, default_value = "10")]
+        top: usize,
+        /// Groups shown, largest first
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// List named graphs with their node and edge counts
+    Graphs,
+    /// Move (or copy) nodes of one named graph, with the edges between them, into another
+    Transfer {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// Only nodes matching this filter expression (JSON), e.g. '{"filter": {...}}'
+        #[arg(long)]
+        filter: Option<String>,
+        /// Copy under new ids instead of moving
+        #[arg(long)]
+        copy: bool,
+    },
+    /// Delete a named graph with all its nodes and edges
+    DropGraph {
+        name: String,
+    },
+    /// Re-encrypt the graph database under a new data key (stop the server first)
+    RotateStorageKey,
+    /// Write the graph to an archive file
+    Export {
+        /// Archive format; only akf (Athena Knowledge Format) is supported
+        #[arg(long, default_value = "akf")]
+        format: String,
+        #[arg(long, short)]
+        output: PathBuf,
+        /// Include every revision and the revision log
         #[arg(long)]
-        pattern: String,
+        history: bool,
     },
+    /// Read an AKF archive into the graph
+    Import {
+        file: PathBuf,
+    },
+    /// Run a SPARQL query against the RDF view of the graph
+    Sparql {
+        #[arg(long)]
+        query: String,
+    },
+    /// Show the revision history of a node
+    History {
+        id: String,
+    },
+    /// Restore a node to the state it had at a graph version
+    Restore {
+        id: String,
+        #[arg(long)]
+        version: u64,
+    },
+    /// Record a checkpoint with the content hash of the current graph and snapshot the database
+    Checkpoint,
+    /// List recorded checkpoints
+    ListCheckpoints,
+    /// Check that the graph still matches the hash recorded in a checkpoint
+    VerifyCheckpoint {
+        id: String,
+    },
+    /// List checkpoints that still have an on-disk snapshot
+    Snapshots,
+    /// Roll the graph back to the snapshot taken with a checkpoint
+    RestoreSnapshot {
+        id: String,
+    },
+    /// Delete old snapshots, keeping the newest ones
+    PruneSnapshots {
+        #[arg(long, default_value = "5")]
+        keep: usize,
+    },
+    /// Find edges pointing at missing nodes and delete them
+    Repair {
+        /// Only report the dangling edges
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List entity types
+    Schemas,
+    /// Store an entity type from a JSON file
+    PutSchema {
+        file: PathBuf,
+    },
+    /// List nodes and edges that do not conform to their types
+    CheckSchemas,
 }
 
 #[tokio::main]
@@ -102,12 +252,18 @@ async fn main() -> Result<()> {
                 created_at: chrono::Utc::now().timestamp(),
                 updated_at: chrono::Utc::now().timestamp(),
                 version: 1,
+                graph: cli.graph.clone(),
             };
 
             system.graph_engine.put_node(entity.clone()).await?;
             println!("Created node: {}", entity.id.0);
         }
-        Commands::ListNodes => {
+        Commands::ListNodes {
+            limit,
+            order_by,
+            fields,
+            cursor,
+        } => {
             let config = if let Some(config_path) = cli.config {
                 AthenaConfig::load(config_path)?
             } else {
@@ -117,23 +273,604 @@ async fn main() -> Result<()> {
             let system = Arc::new(AthenaSystem::new(config).await?);
             system.initialize().await?;
 
+            let order_by = order_by
+                .iter()
+                .map(|spec| athena_graph::query::OrderBy::parse(spec))
+                .collect::<anyhow::Result<Vec<_>>>()?;
             let pattern = athena_graph::query::GraphPattern {
-                node_filters: vec![],
-                edge_filters: vec![],
-                limit: Some(100),
+                limit: Some(limit),
+                order_by,
+                fields: (!fields.is_empty()).then(|| fields.clone()),
+                cursor,
+                graphs: cli.graph.iter().cloned().collect(),
+                ..Default::default()
             };
 
             let result = system.graph_engine.query(&pattern).await?;
             println!("Found {} nodes:", result.nodes.len());
             for node in result.nodes {
-                println!("  - {}: {}", node.id.0, node.label);
+                let values: String = fields
+                    .iter()
+                    .filter_map(|field| {
+                        let value = athena_graph::query::resolve_property(&node, field)?;
+                        let value = serde_json::to_value(value.as_ref()).ok()?;
+                        Some(format!(" {}={}", field, value["value"]))
+                    })
+                    .collect();
+                println!("  - {}: {}{}", node.id.0, node.label, values);
+            }
+            if let Some(cursor) = result.next_cursor {
+                println!("More nodes: --cursor {}", cursor);
+            }
+        }
+        Commands::Query { query } => {
+            let compiled = athena_graph::query_lang::TextQuery::compile_str(&query)?;
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let result = compiled.execute(system.graph_engine.as_ref()).await?;
+            println!("{}", result.columns.join("\t"));
+            for row in &result.rows {
+                let cells: Vec<String> = row.iter().map(format_binding).collect();
+                println!("{}", cells.join("\t"));
+            }
+            println!("({} rows)", result.rows.len());
+        }
+        Commands::Search { query, limit } => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let hits = system.graph_engine.search(&query, limit).await?;
+            println!("Found {} nodes:", hits.len());
+            for hit in hits {
+                println!("  - {}: {} ({:.3})", hit.node.id.0, hit.node.label, hit.score);
+                if let Some(snippet) = hit.snippet {
+                    println!("      {}: {}", snippet.property, snippet.text);
+                }
+            }
+        }
+        Commands::Similar { id, k } => {
+            let node_id = athena_graph::entity::NodeId::from_uuid(uuid::Uuid::parse_str(&id)?);
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let hits = system.graph_engine.similar_to(&node_id, k, None).await?;
+            println!("Found {} similar nodes:", hits.len());
+            for hit in hits {
+                println!("  - {}: {} ({:.3})", hit.node.id.0, hit.node.label, hit.similarity);
+            }
+        }
+        Commands::Timeline { days, from, to, fields, limit, histogram } => {
+            let mut query = match days {
+                Some(days) => TimeQuery::last_days(days),
+                None => TimeQuery {
+                    from,
+                    to,
+                    ..Default::default()
+                },
+            };
+            query.fields = fields;
+            query.limit = Some(limit);
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            if let Some(bucket) = histogram {
+                let bucket = match bucket.as_str() {
+                    "hour" => BucketSize::Hour,
+                    "day" => BucketSize::Day,
+                    "week" => BucketSize::Week,
+                    other => anyhow::bail!("Unknown bucket size {} (expected hour, day or week)", other),
+                };
+                let buckets = system.graph_engine.time_histogram(&query, bucket, 0).await?;
+                for bucket in buckets {
+                    println!("  {}  {}", format_timestamp(bucket.start), bucket.count);
+                }
+            } else {
+                let events = system.graph_engine.timeline(&query).await?;
+                println!("{} events:", events.len());
+                for event in events {
+                    println!(
+                        "  - {} {}: {} ({})",
+                        format_timestamp(event.timestamp),
+                        event.field,
+                        event.node.label,
+                        event.node.id.0
+                    );
+                }
+            }
+        }
+        Commands::Stats {
+            group_by,
+            metrics,
+            facets,
+            top,
+            limit,
+        } => {
+            use athena_graph::aggregate::{AggregateQuery, Facet, GroupBy, Metric};
+            let metrics = if metrics.is_empty() {
+                vec![Metric::count()]
+            } else {
+                metrics.iter().map(|m| Metric::parse(m)).collect::<Result<Vec<_>>>()?
+            };
+            let scope = athena_graph::query::GraphPattern {
+                graphs: cli.graph.iter().cloned().collect(),
+                ..Default::default()
+            };
+            let query = AggregateQuery {
+                filter: Some(scope.node_expr()),
+                group_by: group_by.as_deref().map(GroupBy::parse).transpose()?,
+                metrics,
+                facets: facets.iter().map(|f| Facet::parse(f, top)).collect::<Result<Vec<_>>>()?,
+                limit,
+                ..Default::default()
+            };
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let result = system.graph_engine.aggregate(&query).await?;
+            let format_metrics = |metrics: &std::collections::BTreeMap<String, Option<f64>>| {
+                metrics
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("{}={}", name, value),
+                        None => format!("{}=-", name),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            };
+            println!("{} nodes  {}", result.count, format_metrics(&result.metrics));
+            for group in &result.groups {
+                println!(
+                    "  {}  {}",
+                    group.key.as_deref().unwrap_or("(none)"),
+                    format_metrics(&group.metrics)
+                );
+            }
+            for facet in &result.facets {
+                println!("{}:", facet.property);
+                for value in &facet.values {
+                    println!("  {}  {}", value.value, value.count);
+                }
+                if facet.other > 0 {
+                    println!("  (other)  {}", facet.other);
+                }
             }
         }
-        Commands::Query { pattern: _ } => {
-            println!("Query functionality coming soon");
+        Commands::Graphs => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            for graph in system.graph_engine.graphs().await? {
+                println!("  {}  {} nodes, {} edges", graph.name, graph.nodes, graph.edges);
+            }
+        }
+        Commands::Transfer { from, to, filter, copy } => {
+            use athena_graph::named_graph::{GraphTransfer, TransferMode};
+            let transfer = GraphTransfer {
+                from,
+                to,
+                filter: filter.as_deref().map(serde_json::from_str).transpose()?,
+                mode: if copy { TransferMode::Copy } else { TransferMode::Move },
+            };
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let result = system.graph_engine.transfer(&transfer).await?;
+            println!(
+                "{} {} nodes and {} edges from {} to {}",
+                if copy { "Copied" } else { "Moved" },
+                result.nodes,
+                result.edges,
+                transfer.from,
+                transfer.to
+            );
+        }
+        Commands::DropGraph { name } => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let deleted = system.graph_engine.delete_graph(&name).await?;
+            println!("Deleted graph {} ({} nodes)", name, deleted);
+        }
+        Commands::RotateStorageKey => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let skipped = AthenaSystem::rotate_storage_key(&config)?;
+            println!("Graph storage re-encrypted under a new data key");
+            for snapshot in skipped {
+                println!("Snapshot {} was not moved to the new key: {}", snapshot.path.display(), snapshot.reason);
+            }
+        }
+        Commands::Export { format, output, history } => {
+            if format != "akf" {
+                anyhow::bail!("Unknown export format {} (expected akf)", format);
+            }
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let file = std::io::BufWriter::new(std::fs::File::create(&output)?);
+            let summary = system.graph_engine.export_akf(Box::new(file), history).await?;
+            println!(
+                "Exported {} nodes, {} edges and {} schemas to {}",
+                summary.nodes,
+                summary.edges,
+                summary.schemas,
+                output.display()
+            );
+            if history {
+                println!(
+                    "History: {} node and {} edge histories, {} versions",
+                    summary.node_histories, summary.edge_histories, summary.log_entries
+                );
+            }
+        }
+        Commands::Import { file } => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let reader = std::io::BufReader::new(std::fs::File::open(&file)?);
+            let summary = system.graph_engine.import_akf(Box::new(reader)).await?;
+            println!(
+                "Imported {} nodes, {} edges and {} schemas from {}",
+                summary.nodes,
+                summary.edges,
+                summary.schemas,
+                file.display()
+            );
+        }
+        Commands::Sparql { query } => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let response = system.graph_engine.sparql(&query).await?;
+            println!("{}", response.body);
+        }
+        Commands::History { id } => {
+            let node_id = athena_graph::entity::NodeId::from_uuid(uuid::Uuid::parse_str(&id)?);
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let revisions = system.graph_engine.node_history(&node_id).await?;
+            println!("{} revisions of {}:", revisions.len(), id);
+            for revision in revisions {
+                match revision.value {
+                    Some(node) => println!(
+                        "  - version {} at {}: {} (node version {})",
+                        revision.version.0, revision.timestamp, node.label, node.version
+                    ),
+                    None => println!("  - version {} at {}: deleted", revision.version.0, revision.timestamp),
+                }
+            }
+        }
+        Commands::Restore { id, version } => {
+            let node_id = athena_graph::entity::NodeId::from_uuid(uuid::Uuid::parse_str(&id)?);
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let entity = system
+                .graph_engine
+                .restore_node(&node_id, athena_graph::version::VersionId(version))
+                .await?;
+            println!("Restored {} to version {}: {}", id, version, entity.label);
+        }
+        Commands::Checkpoint => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let checkpoint = system.graph_engine.checkpoint().await?;
+            println!(
+                "Created checkpoint {} at version {}: {}",
+                checkpoint.id,
+                checkpoint.version.0,
+                format_hash(&checkpoint.hash)
+            );
+            if let Some(path) = &checkpoint.snapshot {
+                println!("Snapshot: {}", path.display());
+            }
+        }
+        Commands::ListCheckpoints => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let checkpoints = system.graph_engine.list_checkpoints().await?;
+            println!("Found {} checkpoints:", checkpoints.len());
+            for checkpoint in checkpoints {
+                println!(
+                    "  - {}: version {} at {} ({})",
+                    checkpoint.id,
+                    checkpoint.version.0,
+                    checkpoint.timestamp,
+                    format_hash(&checkpoint.hash)
+                );
+            }
+        }
+        Commands::VerifyCheckpoint { id } => {
+            let checkpoint_id = uuid::Uuid::parse_str(&id)?;
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let verification = system.graph_engine.verify_checkpoint(&checkpoint_id).await?;
+            if verification.valid {
+                println!("Checkpoint {} is valid", id);
+            } else {
+                println!(
+                    "Checkpoint {} does NOT match: expected {}, found {}",
+                    id,
+                    format_hash(&verification.checkpoint.hash),
+                    format_hash(&verification.actual_hash)
+                );
+                let buckets: Vec<String> = verification
+                    .mismatched_buckets
+                    .iter()
+                    .map(|bucket| format!("{:02x}", bucket))
+                    .collect();
+                println!("Records with ids starting with these bytes differ: {}", buckets.join(", "));
+                std::process::exit(1);
+            }
+        }
+        Commands::Snapshots => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let snapshots = system.graph_engine.list_snapshots().await?;
+            println!("Found {} snapshots:", snapshots.len());
+            for checkpoint in snapshots {
+                if let Some(path) = &checkpoint.snapshot {
+                    println!(
+                        "  - {}: version {} at {} ({})",
+                        checkpoint.id,
+                        checkpoint.version.0,
+                        checkpoint.timestamp,
+                        path.display()
+                    );
+                }
+            }
+        }
+        Commands::RestoreSnapshot { id } => {
+            let checkpoint_id = uuid::Uuid::parse_str(&id)?;
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let version = system.graph_engine.restore_snapshot(&checkpoint_id).await?;
+            println!("Restored snapshot {} as version {}", id, version.0);
+        }
+        Commands::PruneSnapshots { keep } => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let pruned = system.graph_engine.prune_snapshots(keep).await?;
+            println!("Pruned {} snapshots", pruned.len());
+            for checkpoint in pruned {
+                println!("  - {}: version {}", checkpoint.id, checkpoint.version.0);
+            }
+        }
+        Commands::Repair { dry_run } => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let edges = if dry_run {
+                system.graph_engine.dangling_edges().await?
+            } else {
+                system.graph_engine.repair_dangling_edges().await?
+            };
+            let action = if dry_run { "Found" } else { "Deleted" };
+            println!("{} {} dangling edges:", action, edges.len());
+            for edge in edges {
+                println!("  - {}: {} -[{}]-> {}", edge.id, edge.from.0, edge.label, edge.to.0);
+            }
+        }
+        Commands::Schemas => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let schemas = system.graph_engine.schemas().await?;
+            println!("{} entity types:", schemas.len());
+            for schema in schemas {
+                let parent = schema.parent.map(|p| format!(" < {}", p)).unwrap_or_default();
+                let aliases = if schema.aliases.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (type: {})", schema.aliases.join(", "))
+                };
+                println!("  - {}{}{}", schema.name, parent, aliases);
+            }
+        }
+        Commands::PutSchema { file } => {
+            let schema: athena_graph::schema::EntityType = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let schema = system.graph_engine.put_schema(schema).await?;
+            println!("Stored entity type {}", schema.name);
+        }
+        Commands::CheckSchemas => {
+            let config = if let Some(config_path) = cli.config {
+                AthenaConfig::load(config_path)?
+            } else {
+                AthenaConfig::default()
+            };
+
+            let system = Arc::new(AthenaSystem::new(config).await?);
+            system.initialize().await?;
+
+            let violations = system.graph_engine.schema_violations().await?;
+            println!("Found {} schema violations:", violations.len());
+            for violation in violations {
+                match violation.edge {
+                    Some(edge) => println!("  - edge {} of {}: {}", edge, violation.node, violation.message),
+                    None => println!("  - node {}: {}", violation.node, violation.message),
+                }
+            }
         }
     }
 
     Ok(())
 }
 
+fn format_binding(binding: &athena_graph::query_lang::Binding) -> String {
+    use athena_graph::query_lang::Binding;
+    match binding {
+        Binding::Node(node) => format!("{} ({})", node.label, node.id.0),
+        Binding::Edge(edge) => format!("-[{}]-> {}", edge.label, edge.to.0),
+        Binding::Value(Some(value)) => serde_json::to_value(value)
+            .map(|v| v["value"].to_string())
+            .unwrap_or_default(),
+        Binding::Value(None) => "null".to_string(),
+    }
+}
+
+fn format_hash(hash: &[u8]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
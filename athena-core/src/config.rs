@@ -1,3 +1,5 @@
+use athena_graph::integrity::IntegrityPolicy;
+use athena_graph::store::StorageBackend;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -6,9 +8,16 @@ pub struct AthenaConfig {
     pub data_dir: PathBuf,
     pub key_store_path: PathBuf,
     pub graph_db_path: PathBuf,
+    #[serde(default)]
+    pub graph_backend: StorageBackend,
+    /// Encrypt graph values with a key derived from the key manager's master key.
+    #[serde(default)]
+    pub encrypt_graph: bool,
     pub p2p_port: u16,
     pub api_port: u16,
     pub enable_p2p: bool,
+    #[serde(default)]
+    pub integrity: IntegrityPolicy,
 }
 
 impl Default for AthenaConfig {
@@ -21,9 +30,12 @@ impl Default for AthenaConfig {
             data_dir: data_dir.clone(),
             key_store_path: data_dir.join("keys.bin"),
             graph_db_path: data_dir.join("graph"),
+            graph_backend: StorageBackend::default(),
+            encrypt_graph: false,
             p2p_port: 9000,
             api_port: 8080,
             enable_p2p: true,
+            integrity: IntegrityPolicy::default(),
         }
     }
 }
@@ -50,6 +50,7 @@ impl DataImporter {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
@@ -85,6 +86,7 @@ impl DataImporter {
             created_at: chrono::Utc::now().timestamp(),
             updated_at: chrono::Utc::now().timestamp(),
             version: 1,
+            graph: None,
         };
 
         self.graph_engine.put_node(entity).await?;
@@ -1,10 +1,13 @@
 use crate::config::AthenaConfig;
 use anyhow::Result;
 use athena_agents::runtime::AgentRuntime;
+use athena_graph::encryption::{is_encrypted, key_generation, rewrap_key, rotate_key, unlock, StorageKey};
 use athena_graph::engine::{DefaultGraphEngine, GraphEngine};
 use athena_graph::storage::GraphStorage;
+use athena_graph::store::StorageBackend;
 use athena_security::key_manager::KeyManager;
 use athena_sync::p2p::P2PNode;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -16,18 +19,40 @@ pub struct AthenaSystem {
     pub p2p_node: Arc<RwLock<Option<P2PNode>>>,
 }
 
+/// A snapshot `AthenaSystem::rotate_storage_key` could not move to the new storage key.
+#[derive(Debug)]
+pub struct SkippedSnapshot {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
 impl AthenaSystem {
     pub async fn new(config: AthenaConfig) -> Result<Self> {
         std::fs::create_dir_all(&config.data_dir)?;
 
         // Initialize key manager
-        let key_manager = KeyManager::new(&config.key_store_path)?;
-        let key_manager = Arc::new(RwLock::new(key_manager));
+        let mut key_manager = KeyManager::new(&config.key_store_path)?;
 
-        // Initialize graph engine
-        let storage = GraphStorage::open(&config.graph_db_path)?;
-        let graph_engine: Arc<dyn GraphEngine + Send + Sync> =
-            Arc::new(DefaultGraphEngine::new(storage));
+        // Initialize graph engine; the storage key depends on how often it was rotated
+        let store = config.graph_backend.open(&config.graph_db_path)?;
+        let storage_key = if config.encrypt_graph {
+            // A master key is only generated for a database that is not encrypted yet; an
+            // encrypted one opens with nothing but the master key it was encrypted under
+            if !key_manager.has_master_key() && !is_encrypted(store.as_ref())? {
+                key_manager.generate_master_key()?;
+            }
+            Some(StorageKey::derive(&key_manager, key_generation(store.as_ref())?)?)
+        } else {
+            None
+        };
+        let key_manager = Arc::new(RwLock::new(key_manager));
+        let storage = GraphStorage::from_store(unlock(store, storage_key.as_ref())?)?;
+        let mut engine = DefaultGraphEngine::new(storage).with_integrity(config.integrity.clone());
+        // Nothing to snapshot when the graph is not persisted
+        if config.graph_backend != StorageBackend::Memory {
+            engine = engine.with_snapshot_dir(config.data_dir.join("snapshots"));
+        }
+        let graph_engine: Arc<dyn GraphEngine + Send + Sync> = Arc::new(engine);
 
         // Initialize agent runtime
         let agent_runtime = AgentRuntime::new()?;
@@ -50,6 +75,61 @@ impl AthenaSystem {
         })
     }
 
+    /// Re-encrypts the graph database under a new data key, wrapped with the storage key of
+    /// the next generation, and moves its snapshots to that key too. Snapshots that cannot be
+    /// moved are logged and returned. Must run while no system has the database open.
+    pub fn rotate_storage_key(config: &AthenaConfig) -> Result<Vec<SkippedSnapshot>> {
+        if !config.encrypt_graph {
+            anyhow::bail!("Graph encryption is disabled in config");
+        }
+        let key_manager = KeyManager::new(&config.key_store_path)?;
+        let store = config.graph_backend.open(&config.graph_db_path)?;
+        let generation = key_generation(store.as_ref())?;
+        let current = StorageKey::derive(&key_manager, generation)?;
+        let next = StorageKey::derive(&key_manager, generation + 1)?;
+
+        // Snapshots go first and those already moved are left alone, so an interrupted
+        // rotation can simply be run again
+        let mut skipped = Vec::new();
+        let snapshots = config.data_dir.join("snapshots");
+        if config.graph_backend != StorageBackend::Memory && snapshots.is_dir() {
+            for entry in std::fs::read_dir(&snapshots)? {
+                let path = entry?.path();
+                if let Err(error) = Self::rewrap_snapshot(config, &key_manager, &path, generation + 1, &next) {
+                    tracing::warn!("Snapshot {} keeps its old storage key: {}", path.display(), error);
+                    skipped.push(SkippedSnapshot {
+                        path,
+                        reason: error.to_string(),
+                    });
+                }
+            }
+        }
+        rotate_key(store.as_ref(), &current, &next)?;
+        Ok(skipped)
+    }
+
+    /// Moves a snapshot from whichever key generation it was left at to `generation`.
+    fn rewrap_snapshot(
+        config: &AthenaConfig,
+        key_manager: &KeyManager,
+        path: &Path,
+        generation: u32,
+        key: &StorageKey,
+    ) -> Result<()> {
+        let snapshot = config.graph_backend.open(path)?;
+        if !is_encrypted(snapshot.as_ref())? {
+            anyhow::bail!("it was taken before the database was encrypted");
+        }
+        let from = key_generation(snapshot.as_ref())?;
+        if from == generation {
+            return Ok(());
+        }
+        if from > generation {
+            anyhow::bail!("its key generation {} is newer than the database's", from);
+        }
+        rewrap_key(snapshot.as_ref(), &StorageKey::derive(key_manager, from)?, key, generation)
+    }
+
     pub async fn initialize(&self) -> Result<()> {
         // Generate default key if none exists
         {
@@ -0,0 +1,509 @@
+use crate::entity::{Entity, PropertyValue};
+use crate::query::{resolve_property, FilterExpr};
+use crate::timeline::{bucket_start, BucketSize};
+use anyhow::Result;
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Statistics over the nodes matching `filter`: totals, per-group metrics and the most
+/// frequent values of some properties.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AggregateQuery {
+    /// Nodes to aggregate; `None` takes all of them.
+    #[serde(default)]
+    pub filter: Option<FilterExpr>,
+    #[serde(default)]
+    pub group_by: Option<GroupBy>,
+    /// Computed over all matching nodes and, when grouping, for every group.
+    #[serde(default)]
+    pub metrics: Vec<Metric>,
+    #[serde(default)]
+    pub facets: Vec<Facet>,
+    /// Keep at most this many groups, largest first.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Seconds east of UTC, for keys derived from dates.
+    #[serde(default)]
+    pub utc_offset: i64,
+}
+
+impl AggregateQuery {
+    /// Rejects metrics that are missing their property.
+    pub fn validate(&self) -> Result<()> {
+        self.metrics.iter().try_for_each(Metric::validate)
+    }
+}
+
+/// How a property value turns into a group or facet key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyFunction {
+    /// The value itself.
+    #[default]
+    Value,
+    /// Lowercased file extension of a name, path or URL, e.g. `pdf`.
+    Extension,
+    /// `2024-03-06T10:00`
+    Hour,
+    /// `2024-03-06`
+    Day,
+    /// The Monday the week starts on, `2024-03-04`.
+    Week,
+    /// `2024-03`
+    Month,
+    /// `2024`
+    Year,
+}
+
+impl KeyFunction {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "value" => Ok(Self::Value),
+            "extension" => Ok(Self::Extension),
+            "hour" => Ok(Self::Hour),
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "year" => Ok(Self::Year),
+            other => Err(anyhow::anyhow!(
+                "Unknown key function '{}' (expected value, extension, hour, day, week, month or year)",
+                other
+            )),
+        }
+    }
+
+    fn key(&self, value: &PropertyValue, utc_offset: i64) -> Option<String> {
+        match (self, value) {
+            (KeyFunction::Value, value) => value_key(value),
+            (KeyFunction::Extension, PropertyValue::String(name)) => {
+                let name = name.split(['?', '#']).next().unwrap_or_default();
+                let file = name.rsplit(['/', '\\']).next().unwrap_or_default();
+                match file.rsplit_once('.') {
+                    Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => {
+                        Some(extension.to_lowercase())
+                    }
+                    _ => None,
+                }
+            }
+            (KeyFunction::Extension, _) => None,
+            (function, PropertyValue::DateTime(timestamp)) => {
+                let (start, format) = match function {
+                    KeyFunction::Hour => (bucket_start(*timestamp, BucketSize::Hour, utc_offset), "%Y-%m-%dT%H:%M"),
+                    KeyFunction::Day => (bucket_start(*timestamp, BucketSize::Day, utc_offset), "%Y-%m-%d"),
+                    KeyFunction::Week => (bucket_start(*timestamp, BucketSize::Week, utc_offset), "%Y-%m-%d"),
+                    _ => (*timestamp, ""),
+                };
+                let local = chrono::DateTime::from_timestamp(start + utc_offset, 0)?;
+                Some(match function {
+                    KeyFunction::Month => format!("{:04}-{:02}", local.year(), local.month()),
+                    KeyFunction::Year => format!("{:04}", local.year()),
+                    _ => local.format(format).to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Splits `property` or `property:function`, e.g. `created_at:month`.
+fn parse_keyed(spec: &str) -> Result<(String, KeyFunction)> {
+    match spec.rsplit_once(':') {
+        Some((property, function)) => Ok((property.to_string(), KeyFunction::parse(function)?)),
+        None => Ok((spec.to_string(), KeyFunction::Value)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupBy {
+    pub property: String,
+    #[serde(default)]
+    pub key: KeyFunction,
+}
+
+impl GroupBy {
+    /// Parses `property` or `property:function`, e.g. `created_at:month`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (property, key) = parse_keyed(spec)?;
+        Ok(Self { property, key })
+    }
+}
+
+/// The `top` most frequent keys of a property.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Facet {
+    pub property: String,
+    #[serde(default)]
+    pub key: KeyFunction,
+    #[serde(default = "default_top")]
+    pub top: usize,
+}
+
+fn default_top() -> usize {
+    10
+}
+
+impl Facet {
+    /// Parses `property` or `property:function`, e.g. `name:extension`.
+    pub fn parse(spec: &str, top: usize) -> Result<Self> {
+        let (property, key) = parse_keyed(spec)?;
+        Ok(Self { property, key, top })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricOp {
+    /// Nodes, or nodes that have `property` when one is given.
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// A value computed over a set of nodes. Every operation but `count` needs a property
+/// and reads its `Number` and `DateTime` values, including those inside lists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metric {
+    pub op: MetricOp,
+    #[serde(default)]
+    pub property: Option<String>,
+}
+
+impl Metric {
+    pub fn count() -> Self {
+        Self {
+            op: MetricOp::Count,
+            property: None,
+        }
+    }
+
+    pub fn of(op: MetricOp, property: impl Into<String>) -> Self {
+        Self {
+            op,
+            property: Some(property.into()),
+        }
+    }
+
+    /// Parses `count`, `count(property)`, `sum(property)`, `avg(...)`, `min(...)` or `max(...)`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, property) = match spec.split_once('(') {
+            Some((name, rest)) => {
+                let property = rest
+                    .strip_suffix(')')
+                    .ok_or_else(|| anyhow::anyhow!("Unclosed parenthesis in metric '{}'", spec))?;
+                (name.trim(), Some(property.trim().to_string()))
+            }
+            None => (spec.trim(), None),
+        };
+        let op = match name {
+            "count" => MetricOp::Count,
+            "sum" => MetricOp::Sum,
+            "avg" => MetricOp::Avg,
+            "min" => MetricOp::Min,
+            "max" => MetricOp::Max,
+            other => return Err(anyhow::anyhow!("Unknown metric '{}'", other)),
+        };
+        let metric = Self { op, property };
+        metric.validate()?;
+        Ok(metric)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.op != MetricOp::Count && self.property.is_none() {
+            return Err(anyhow::anyhow!("Metric {:?} needs a property", self.op));
+        }
+        Ok(())
+    }
+
+    /// The metric as written in a query, used as its key in results: `avg(size)`.
+    pub fn name(&self) -> String {
+        let op = match self.op {
+            MetricOp::Count => "count",
+            MetricOp::Sum => "sum",
+            MetricOp::Avg => "avg",
+            MetricOp::Min => "min",
+            MetricOp::Max => "max",
+        };
+        match &self.property {
+            Some(property) => format!("{}({})", op, property),
+            None => op.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateResult {
+    /// Number of matching nodes.
+    pub count: u64,
+    /// Metrics over all matching nodes, keyed by `Metric::name`. `None` when there was
+    /// nothing to compute them from.
+    pub metrics: BTreeMap<String, Option<f64>>,
+    /// Largest first. A node with several keys (a list property) counts in each group.
+    pub groups: Vec<GroupResult>,
+    pub facets: Vec<FacetResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupResult {
+    /// `None` collects the nodes without a key.
+    pub key: Option<String>,
+    pub count: u64,
+    pub metrics: BTreeMap<String, Option<f64>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FacetResult {
+    pub property: String,
+    pub key: KeyFunction,
+    pub values: Vec<FacetValue>,
+    /// Occurrences of keys beyond the top ones.
+    pub other: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FacetValue {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Running state of one metric.
+#[derive(Debug, Clone, Default)]
+struct MetricState {
+    nodes: u64,
+    values: u64,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl MetricState {
+    fn add(&mut self, metric: &Metric, entity: &Entity) {
+        let Some(property) = &metric.property else {
+            self.nodes += 1;
+            return;
+        };
+        let Some(value) = resolve_property(entity, property) else {
+            return;
+        };
+        self.nodes += 1;
+        for number in numbers(&value) {
+            self.values += 1;
+            self.sum += number;
+            self.min = Some(self.min.map_or(number, |min| min.min(number)));
+            self.max = Some(self.max.map_or(number, |max| max.max(number)));
+        }
+    }
+
+    fn value(&self, op: MetricOp) -> Option<f64> {
+        match op {
+            MetricOp::Count => Some(self.nodes as f64),
+            MetricOp::Sum => Some(self.sum),
+            MetricOp::Avg => (self.values > 0).then(|| self.sum / self.values as f64),
+            MetricOp::Min => self.min,
+            MetricOp::Max => self.max,
+        }
+    }
+}
+
+fn numbers(value: &PropertyValue) -> Vec<f64> {
+    match value {
+        PropertyValue::Number(n) if !n.is_nan() => vec![*n],
+        PropertyValue::DateTime(t) => vec![*t as f64],
+        PropertyValue::List(items) => items.iter().flat_map(numbers).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn value_key(value: &PropertyValue) -> Option<String> {
+    match value {
+        PropertyValue::String(s) => Some(s.clone()),
+        PropertyValue::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => Some(format!("{}", *n as i64)),
+        PropertyValue::Number(n) => Some(n.to_string()),
+        PropertyValue::Boolean(b) => Some(b.to_string()),
+        PropertyValue::DateTime(t) => Some(t.to_string()),
+        PropertyValue::Reference(id) => Some(id.0.to_string()),
+        PropertyValue::GeoPoint(point) => Some(format!("{},{}", point.lat, point.lon)),
+        PropertyValue::List(_) | PropertyValue::Map(_) => None,
+    }
+}
+
+/// Distinct keys of a property; list items each give their own key.
+fn keys(entity: &Entity, property: &str, function: KeyFunction, utc_offset: i64) -> Vec<String> {
+    let mut keys = match resolve_property(entity, property).as_deref() {
+        Some(PropertyValue::List(items)) => items.iter().filter_map(|item| function.key(item, utc_offset)).collect(),
+        Some(value) => function.key(value, utc_offset).into_iter().collect(),
+        None => Vec::new(),
+    };
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Folds nodes into an `AggregateResult` one at a time, so the matching nodes never
+/// have to be held in memory together.
+pub struct Aggregator<'q> {
+    query: &'q AggregateQuery,
+    count: u64,
+    metrics: Vec<MetricState>,
+    groups: HashMap<Option<String>, (u64, Vec<MetricState>)>,
+    facets: Vec<HashMap<String, u64>>,
+}
+
+impl<'q> Aggregator<'q> {
+    pub fn new(query: &'q AggregateQuery) -> Result<Self> {
+        query.validate()?;
+        Ok(Self {
+            query,
+            count: 0,
+            metrics: vec![MetricState::default(); query.metrics.len()],
+            groups: HashMap::new(),
+            facets: vec![HashMap::new(); query.facets.len()],
+        })
+    }
+
+    /// Adds a node that matched the query's filter.
+    pub fn add(&mut self, entity: &Entity) {
+        let query = self.query;
+        self.count += 1;
+        for (state, metric) in self.metrics.iter_mut().zip(&query.metrics) {
+            state.add(metric, entity);
+        }
+
+        if let Some(group_by) = &query.group_by {
+            let mut group_keys: Vec<Option<String>> = keys(entity, &group_by.property, group_by.key, query.utc_offset)
+                .into_iter()
+                .map(Some)
+                .collect();
+            if group_keys.is_empty() {
+                group_keys.push(None);
+            }
+            for key in group_keys {
+                let (count, states) = self
+                    .groups
+                    .entry(key)
+                    .or_insert_with(|| (0, vec![MetricState::default(); query.metrics.len()]));
+                *count += 1;
+                for (state, metric) in states.iter_mut().zip(&query.metrics) {
+                    state.add(metric, entity);
+                }
+            }
+        }
+
+        for (counts, facet) in self.facets.iter_mut().zip(&query.facets) {
+            for key in keys(entity, &facet.property, facet.key, query.utc_offset) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn finish(self) -> AggregateResult {
+        let metric_values = |states: &[MetricState]| -> BTreeMap<String, Option<f64>> {
+            self.query
+                .metrics
+                .iter()
+                .zip(states)
+                .map(|(metric, state)| (metric.name(), state.value(metric.op)))
+                .collect()
+        };
+
+        let mut groups: Vec<GroupResult> = self
+            .groups
+            .iter()
+            .map(|(key, (count, states))| GroupResult {
+                key: key.clone(),
+                count: *count,
+                metrics: metric_values(states),
+            })
+            .collect();
+        groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        if let Some(limit) = self.query.limit {
+            groups.truncate(limit);
+        }
+
+        let facets = self
+            .query
+            .facets
+            .iter()
+            .zip(&self.facets)
+            .map(|(facet, counts)| {
+                let mut values: Vec<FacetValue> = counts
+                    .iter()
+                    .map(|(value, count)| FacetValue {
+                        value: value.clone(),
+                        count: *count,
+                    })
+                    .collect();
+                values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+                let other = values.iter().skip(facet.top).map(|v| v.count).sum();
+                values.truncate(facet.top);
+                FacetResult {
+                    property: facet.property.clone(),
+                    key: facet.key,
+                    values,
+                    other,
+                }
+            })
+            .collect();
+
+        AggregateResult {
+            count: self.count,
+            metrics: metric_values(&self.metrics),
+            groups,
+            facets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::NodeId;
+
+    fn file(name: &str, size: f64, modified: i64) -> Entity {
+        Entity {
+            id: NodeId::new(),
+            label: name.to_string(),
+            properties: [
+                ("name".to_string(), PropertyValue::String(name.to_string())),
+                ("size".to_string(), PropertyValue::Number(size)),
+                ("modified".to_string(), PropertyValue::DateTime(modified)),
+            ]
+            .into_iter()
+            .collect(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    #[test]
+    fn test_groups_metrics_and_facets() {
+        let query = AggregateQuery {
+            group_by: Some(GroupBy::parse("modified:month").unwrap()),
+            metrics: vec![Metric::count(), Metric::parse("avg(size)").unwrap(), Metric::parse("max(size)").unwrap()],
+            facets: vec![Facet::parse("name:extension", 1).unwrap()],
+            ..Default::default()
+        };
+        assert!(Metric::parse("sum").is_err());
+
+        let mut aggregator = Aggregator::new(&query).unwrap();
+        // 2024-03-06, 2024-03-20 and 2024-04-01
+        aggregator.add(&file("report.PDF", 100.0, 1_709_721_000));
+        aggregator.add(&file("docs/notes.txt", 20.0, 1_710_892_800));
+        aggregator.add(&file("scan.pdf", 300.0, 1_711_929_600));
+        let result = aggregator.finish();
+
+        assert_eq!(result.count, 3);
+        assert_eq!(result.metrics["avg(size)"], Some(140.0));
+        let groups: Vec<(Option<&str>, u64)> = result.groups.iter().map(|g| (g.key.as_deref(), g.count)).collect();
+        assert_eq!(groups, vec![(Some("2024-03"), 2), (Some("2024-04"), 1)]);
+        assert_eq!(result.groups[0].metrics["max(size)"], Some(100.0));
+
+        let facet = &result.facets[0];
+        assert_eq!(facet.values, vec![FacetValue { value: "pdf".to_string(), count: 2 }]);
+        assert_eq!(facet.other, 1);
+    }
+}
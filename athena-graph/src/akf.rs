@@ -0,0 +1,233 @@
+use crate::entity::{Edge, Entity, NodeId};
+use crate::schema::EntityType;
+use crate::storage::GraphMetadata;
+use crate::version::{EdgeRevision, NodeRevision, RevisionLogEntry};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{BufReader, Read, Write};
+
+/// Athena Knowledge Format: `AKF` and a format version byte, followed by a Zstandard stream
+/// of CBOR records. The first record is the `Header` and the last the `End`, which repeats
+/// the record counts so that a truncated archive is detected. Records are written and read
+/// one at a time, so neither side needs the whole graph in memory.
+const MAGIC: &[u8; 3] = b"AKF";
+pub const AKF_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AkfHeader {
+    pub created_at: i64,
+    /// Database the archive was written from, including the graph version it reflects.
+    pub source: GraphMetadata,
+    /// Whether revisions and the revision log follow the current records.
+    pub history: bool,
+}
+
+/// Records in the order they are written: schemas, nodes, edges, then the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AkfRecord {
+    Header(AkfHeader),
+    /// A stored (non built-in) entity type. Schema nodes appear only here, not as `Node`.
+    Schema(EntityType),
+    Node(Entity),
+    Edge(Edge),
+    /// Every revision of one node, oldest first; deleted nodes appear only here.
+    NodeHistory { id: NodeId, revisions: Vec<NodeRevision> },
+    EdgeHistory { id: uuid::Uuid, revisions: Vec<EdgeRevision> },
+    Log(RevisionLogEntry),
+    End(AkfSummary),
+}
+
+/// Number of records of each kind in an archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AkfSummary {
+    pub schemas: u64,
+    pub nodes: u64,
+    pub edges: u64,
+    pub node_histories: u64,
+    pub edge_histories: u64,
+    pub log_entries: u64,
+}
+
+impl AkfSummary {
+    fn count(&mut self, record: &AkfRecord) {
+        match record {
+            AkfRecord::Schema(_) => self.schemas += 1,
+            AkfRecord::Node(_) => self.nodes += 1,
+            AkfRecord::Edge(_) => self.edges += 1,
+            AkfRecord::NodeHistory { .. } => self.node_histories += 1,
+            AkfRecord::EdgeHistory { .. } => self.edge_histories += 1,
+            AkfRecord::Log(_) => self.log_entries += 1,
+            AkfRecord::Header(_) | AkfRecord::End(_) => {}
+        }
+    }
+}
+
+pub struct AkfWriter<W: Write> {
+    encoder: zstd::Encoder<'static, W>,
+    summary: AkfSummary,
+}
+
+impl<W: Write> AkfWriter<W> {
+    pub fn new(mut writer: W, header: AkfHeader) -> Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[AKF_VERSION])?;
+        let mut archive = Self {
+            encoder: zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+            summary: AkfSummary::default(),
+        };
+        archive.write(&AkfRecord::Header(header))?;
+        Ok(archive)
+    }
+
+    pub fn write(&mut self, record: &AkfRecord) -> Result<()> {
+        if matches!(record, AkfRecord::End(_)) {
+            anyhow::bail!("The end of an AKF archive is written by finish");
+        }
+        ciborium::ser::into_writer(record, &mut self.encoder)?;
+        self.summary.count(record);
+        Ok(())
+    }
+
+    /// Writes the `End` record and flushes the compressed stream.
+    pub fn finish(mut self) -> Result<AkfSummary> {
+        ciborium::ser::into_writer(&AkfRecord::End(self.summary.clone()), &mut self.encoder)?;
+        self.encoder.finish()?.flush()?;
+        Ok(self.summary)
+    }
+}
+
+/// Iterates the records after the header. Ends after checking the `End` record, or with an
+/// error when the archive is truncated or its counts do not match.
+pub struct AkfReader<R: Read> {
+    decoder: zstd::Decoder<'static, BufReader<R>>,
+    header: AkfHeader,
+    summary: AkfSummary,
+    done: bool,
+}
+
+impl<R: Read> AkfReader<R> {
+    pub fn open(mut reader: R) -> Result<Self> {
+        let mut prefix = [0u8; 4];
+        reader
+            .read_exact(&mut prefix)
+            .map_err(|_| anyhow::anyhow!("Not an AKF archive"))?;
+        if &prefix[..3] != MAGIC {
+            anyhow::bail!("Not an AKF archive");
+        }
+        if prefix[3] != AKF_VERSION {
+            anyhow::bail!("Unsupported AKF version {} (this build reads version {})", prefix[3], AKF_VERSION);
+        }
+        let mut decoder = zstd::Decoder::new(reader)?;
+        let header = match ciborium::de::from_reader(&mut decoder)? {
+            AkfRecord::Header(header) => header,
+            _ => anyhow::bail!("AKF archive does not start with a header"),
+        };
+        Ok(Self {
+            decoder,
+            header,
+            summary: AkfSummary::default(),
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &AkfHeader {
+        &self.header
+    }
+
+    /// Records read so far.
+    pub fn summary(&self) -> &AkfSummary {
+        &self.summary
+    }
+}
+
+impl<R: Read> Iterator for AkfReader<R> {
+    type Item = Result<AkfRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record: AkfRecord = match ciborium::de::from_reader(&mut self.decoder) {
+            Ok(record) => record,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(anyhow::anyhow!("Truncated or corrupt AKF archive: {}", e)));
+            }
+        };
+        match record {
+            AkfRecord::End(expected) => {
+                self.done = true;
+                if expected != self.summary {
+                    return Some(Err(anyhow::anyhow!(
+                        "AKF archive announces {:?} but holds {:?}",
+                        expected,
+                        self.summary
+                    )));
+                }
+                None
+            }
+            AkfRecord::Header(_) => {
+                self.done = true;
+                Some(Err(anyhow::anyhow!("AKF archive has a second header")))
+            }
+            record => {
+                self.summary.count(&record);
+                Some(Ok(record))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::version::VersionId;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_round_trip_and_truncation() {
+        let header = AkfHeader {
+            created_at: 1_700_000_000,
+            source: GraphMetadata {
+                version: VersionId(7),
+                format: 9,
+                created_at: 1_600_000_000,
+                instance_id: uuid::Uuid::new_v4(),
+            },
+            history: false,
+        };
+        let node = Entity {
+            id: NodeId::new(),
+            label: "Notes".to_string(),
+            properties: HashMap::new(),
+            created_at: 1,
+            updated_at: 2,
+            version: 3,
+            graph: None,
+        };
+        let mut data = Vec::new();
+        let mut writer = AkfWriter::new(&mut data, header).unwrap();
+        for _ in 0..100 {
+            writer.write(&AkfRecord::Node(node.clone())).unwrap();
+        }
+        let summary = writer.finish().unwrap();
+        assert_eq!(summary.nodes, 100);
+
+        let mut reader = AkfReader::open(data.as_slice()).unwrap();
+        assert_eq!(reader.header().source.version, VersionId(7));
+        let records: Vec<AkfRecord> = reader.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(records.len(), 100);
+        match &records[99] {
+            AkfRecord::Node(read) => assert_eq!((read.id.clone(), read.version), (node.id.clone(), 3)),
+            other => panic!("unexpected record {:?}", other),
+        }
+        assert!(reader.next().is_none());
+
+        // A cut-off archive must not pass for a smaller graph
+        let truncated = &data[..data.len() / 2];
+        let result = AkfReader::open(truncated).and_then(|reader| reader.collect::<Result<Vec<AkfRecord>>>());
+        assert!(result.is_err());
+        assert!(AkfReader::open(&b"PK\x03\x04"[..]).is_err());
+    }
+}
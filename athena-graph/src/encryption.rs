@@ -0,0 +1,507 @@
+use crate::error::GraphError;
+use crate::store::{GraphStore, StoreIter, WriteBatch};
+use anyhow::Result;
+use athena_security::{Cipher, KeyManager};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Context the storage key is derived from the key manager's master key under.
+pub const STORAGE_KEY_CONTEXT: &str = "athena-graph/storage";
+
+// Kept in plaintext: they are needed to decrypt everything else
+const HEADER_KEY: &[u8] = b"meta:encryption";
+const GENERATION_KEY: &[u8] = b"meta:key_generation";
+const HEADER_VERSION: u32 = 1;
+/// Records rewritten per write while re-encrypting, which bounds the memory it takes.
+const REENCRYPT_CHUNK: usize = 1000;
+
+/// Unlocks an encrypted graph database. It only wraps the random data key the values are
+/// encrypted with, so the database never depends on the master key directly.
+#[derive(Clone)]
+pub struct StorageKey([u8; 32]);
+
+impl StorageKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// The storage key of `generation`, see `key_generation`. Fails while the key manager
+    /// has no master key.
+    pub fn derive(key_manager: &KeyManager, generation: u32) -> Result<Self> {
+        // Generation 0 is what databases were encrypted under before keys were rotated
+        let context = match generation {
+            0 => STORAGE_KEY_CONTEXT.to_string(),
+            generation => format!("{}/{}", STORAGE_KEY_CONTEXT, generation),
+        };
+        Ok(Self(key_manager.derive_key(&context)?))
+    }
+}
+
+impl fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageKey").finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptionHeader {
+    version: u32,
+    /// The data key, encrypted with the storage key. Empty while a plaintext store is being
+    /// encrypted.
+    data_key: Vec<u8>,
+    created_at: i64,
+    /// Set while the values move to a new data key.
+    rotation: Option<Rotation>,
+}
+
+/// How far `reencrypt` got: values up to and including `done_through` are under the new data
+/// key, the rest are still under the old one.
+#[derive(Serialize, Deserialize)]
+struct Rotation {
+    /// Key generation the old data key is wrapped for.
+    from_generation: u32,
+    /// Key generation the store moves to.
+    to_generation: u32,
+    /// The new data key, encrypted with the storage key of `to_generation`.
+    data_key: Vec<u8>,
+    done_through: Option<Vec<u8>>,
+}
+
+/// Whether the store holds an encrypted graph.
+pub fn is_encrypted(store: &dyn GraphStore) -> Result<bool> {
+    Ok(store.get(HEADER_KEY)?.is_some())
+}
+
+/// Which storage key the store's data key is wrapped with: 0 until the first rotation, then
+/// one more with each `rotate_key`. It is readable without any key, so the storage key can be
+/// derived before the store is unlocked.
+pub fn key_generation(store: &dyn GraphStore) -> Result<u32> {
+    match store.get(GENERATION_KEY)? {
+        Some(value) => Ok(u32::from_be_bytes(value.as_slice().try_into()?)),
+        None => Ok(0),
+    }
+}
+
+/// Makes `store` readable. A plaintext store is returned as it is when no key is given and
+/// encrypted first when one is; an encrypted store needs the key it was encrypted with.
+pub fn unlock(store: Arc<dyn GraphStore>, key: Option<&StorageKey>) -> Result<Arc<dyn GraphStore>> {
+    match (read_header(store.as_ref())?, key) {
+        (None, None) => Ok(store),
+        (Some(_), None) => Err(GraphError::StorageLocked.into()),
+        (Some(header), Some(key)) if header.data_key.is_empty() => {
+            tracing::info!("Resuming the encryption of plaintext graph storage");
+            reencrypt(store.as_ref(), header, None, key)?;
+            unlock(store, Some(key))
+        }
+        (Some(header), Some(_)) if header.rotation.is_some() => Err(GraphError::RotationInterrupted.into()),
+        (Some(header), Some(key)) => Ok(Arc::new(EncryptedStore::new(store, key, &header)?)),
+        (None, Some(key)) => {
+            tracing::info!("Encrypting plaintext graph storage");
+            let generation = key_generation(store.as_ref())?;
+            let header = EncryptionHeader {
+                version: HEADER_VERSION,
+                data_key: Vec::new(),
+                created_at: chrono::Utc::now().timestamp(),
+                rotation: None,
+            };
+            let header = begin_reencrypt(store.as_ref(), header, generation, generation, key)?;
+            reencrypt(store.as_ref(), header, None, key)?;
+            unlock(store, Some(key))
+        }
+    }
+}
+
+/// Re-encrypts every value under a new data key wrapped with `new` and moves the store to the
+/// next key generation. `current` and `new` are the storage keys of the current and the next
+/// generation. A rotation that was interrupted resumes where it stopped. The store must not be
+/// open elsewhere.
+pub fn rotate_key(store: &dyn GraphStore, current: &StorageKey, new: &StorageKey) -> Result<()> {
+    let header = read_header(store)?.ok_or_else(|| anyhow::anyhow!("Graph storage is not encrypted"))?;
+    let old = data_cipher(&header, current)?;
+    let generation = key_generation(store)?;
+    let header = match &header.rotation {
+        None => begin_reencrypt(store, header, generation, generation + 1, new)?,
+        Some(rotation) if rotation.from_generation == generation && rotation.to_generation == generation + 1 => header,
+        Some(rotation) => anyhow::bail!(
+            "Graph storage is being rotated from key generation {} to {}",
+            rotation.from_generation,
+            rotation.to_generation
+        ),
+    };
+    reencrypt(store, header, Some(&old), new)
+}
+
+/// Wraps the store's data key with `new` instead of `current` and moves it to key generation
+/// `generation`, leaving the values as they are. Snapshots keep their own data key, so this is
+/// how they follow the database through a `rotate_key`, even from an older generation.
+pub fn rewrap_key(store: &dyn GraphStore, current: &StorageKey, new: &StorageKey, generation: u32) -> Result<()> {
+    let mut header = read_header(store)?.ok_or_else(|| anyhow::anyhow!("Graph storage is not encrypted"))?;
+    if header.rotation.is_some() {
+        return Err(GraphError::RotationInterrupted.into());
+    }
+    let data_key = unwrap_data_key(&header.data_key, current)?;
+    header.data_key = Cipher::new(&new.0).encrypt(&data_key)?;
+    let mut batch = WriteBatch::default();
+    batch.put(HEADER_KEY, bincode::serialize(&header)?);
+    batch.put(GENERATION_KEY, generation.to_be_bytes());
+    store.write(batch)
+}
+
+fn read_header(store: &dyn GraphStore) -> Result<Option<EncryptionHeader>> {
+    match store.get(HEADER_KEY)? {
+        Some(data) => {
+            let header: EncryptionHeader = bincode::deserialize(&data)?;
+            if header.version != HEADER_VERSION {
+                anyhow::bail!("Unsupported graph encryption version {}", header.version);
+            }
+            Ok(Some(header))
+        }
+        None => Ok(None),
+    }
+}
+
+fn unwrap_data_key(data_key: &[u8], key: &StorageKey) -> Result<[u8; 32]> {
+    let data_key = Cipher::new(&key.0)
+        .decrypt(data_key)
+        .map_err(|_| GraphError::InvalidStorageKey)?;
+    data_key
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Corrupt graph encryption header"))
+}
+
+fn data_cipher(header: &EncryptionHeader, key: &StorageKey) -> Result<Cipher> {
+    Ok(Cipher::new(&unwrap_data_key(&header.data_key, key)?))
+}
+
+fn is_plaintext(key: &[u8]) -> bool {
+    key == HEADER_KEY || key == GENERATION_KEY
+}
+
+/// Records in `header` that the values are moving from `from_generation` to a fresh data key
+/// wrapped with `new` for `to_generation`, and writes it.
+fn begin_reencrypt(
+    store: &dyn GraphStore,
+    mut header: EncryptionHeader,
+    from_generation: u32,
+    to_generation: u32,
+    new: &StorageKey,
+) -> Result<EncryptionHeader> {
+    header.rotation = Some(Rotation {
+        from_generation,
+        to_generation,
+        data_key: Cipher::new(&new.0).encrypt(&Cipher::generate_key())?,
+        done_through: None,
+    });
+    store.put(HEADER_KEY, &bincode::serialize(&header)?)?;
+    Ok(header)
+}
+
+/// Moves every value to the data key of `header.rotation`, decrypting with `old` (values are
+/// taken as they are when the store was in plaintext). Each write covers `REENCRYPT_CHUNK`
+/// records and records its progress in the header, so an interrupted run resumes where it
+/// stopped. The last write installs the new data key and key generation.
+fn reencrypt(store: &dyn GraphStore, mut header: EncryptionHeader, old: Option<&Cipher>, new: &StorageKey) -> Result<()> {
+    let rotation = header
+        .rotation
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Graph storage is not being re-encrypted"))?;
+    let cipher = Cipher::new(&unwrap_data_key(&rotation.data_key, new)?);
+    let (data_key, generation) = (rotation.data_key.clone(), rotation.to_generation);
+    while reencrypt_chunk(store, &mut header, old, &cipher, REENCRYPT_CHUNK)? {}
+
+    let header = EncryptionHeader {
+        version: HEADER_VERSION,
+        data_key,
+        created_at: chrono::Utc::now().timestamp(),
+        rotation: None,
+    };
+    let mut batch = WriteBatch::default();
+    batch.put(HEADER_KEY, bincode::serialize(&header)?);
+    batch.put(GENERATION_KEY, generation.to_be_bytes());
+    store.write(batch)
+}
+
+/// Re-encrypts up to `chunk` records after the last one done, together with the progress in
+/// `header`. Returns whether records may remain.
+fn reencrypt_chunk(
+    store: &dyn GraphStore,
+    header: &mut EncryptionHeader,
+    old: Option<&Cipher>,
+    new: &Cipher,
+    chunk: usize,
+) -> Result<bool> {
+    let Some(rotation) = header.rotation.as_mut() else {
+        return Ok(false);
+    };
+    let start = match &rotation.done_through {
+        Some(last) => [last.as_slice(), &[0]].concat(),
+        None => Vec::new(),
+    };
+    let entries = store.iter_from(&start).take(chunk).collect::<Result<Vec<_>>>()?;
+    let Some((last, _)) = entries.last() else {
+        return Ok(false);
+    };
+    rotation.done_through = Some(last.clone());
+
+    let mut batch = WriteBatch::default();
+    for (entry, value) in &entries {
+        if is_plaintext(entry) || value.is_empty() {
+            continue;
+        }
+        let plaintext = match old {
+            Some(old) => old.decrypt_with_aad(value, entry)?,
+            None => value.clone(),
+        };
+        batch.put(entry, new.encrypt_with_aad(&plaintext, entry)?);
+    }
+    batch.put(HEADER_KEY, bincode::serialize(header)?);
+    store.write(batch)?;
+    Ok(entries.len() == chunk)
+}
+
+/// Encrypts values on their way into another store and decrypts them on the way out. Each
+/// value is bound to its key, so a value copied or moved to another key no longer decrypts.
+///
+/// Keys stay in plaintext, since the indexes depend on their order. Besides record ids, that
+/// leaves readable whatever the index keys are made of: labels (`idx:label:`), indexed
+/// property values (`idx:prop:`), time fields (`idx:time:`), geohashes (`idx:geo:`), graph
+/// names (`idx:graph:`) and full-text terms (`fts:term:`). Empty values (most index entries)
+/// carry nothing and are stored as they are.
+pub struct EncryptedStore {
+    inner: Arc<dyn GraphStore>,
+    key: StorageKey,
+    cipher: Cipher,
+}
+
+impl EncryptedStore {
+    fn new(inner: Arc<dyn GraphStore>, key: &StorageKey, header: &EncryptionHeader) -> Result<Self> {
+        Ok(Self {
+            cipher: data_cipher(header, key)?,
+            key: key.clone(),
+            inner,
+        })
+    }
+
+    fn seal(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        if is_plaintext(key) || value.is_empty() {
+            return Ok(value.to_vec());
+        }
+        self.cipher.encrypt_with_aad(value, key)
+    }
+
+    fn open(&self, key: &[u8], value: Vec<u8>) -> Result<Vec<u8>> {
+        if is_plaintext(key) || value.is_empty() {
+            return Ok(value);
+        }
+        self.cipher.decrypt_with_aad(&value, key)
+    }
+
+    fn decrypting<'a>(&'a self, entries: StoreIter<'a>) -> StoreIter<'a> {
+        Box::new(entries.map(move |item| {
+            let (key, value) = item?;
+            let value = self.open(&key, value)?;
+            Ok((key, value))
+        }))
+    }
+}
+
+impl GraphStore for EncryptedStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner.get(key)?.map(|value| self.open(key, value)).transpose()
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.put(key, &self.seal(key, value)?)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn iter_from(&self, start: &[u8]) -> StoreIter<'_> {
+        self.decrypting(self.inner.iter_from(start))
+    }
+
+    fn iter_back_from(&self, start: &[u8]) -> StoreIter<'_> {
+        self.decrypting(self.inner.iter_back_from(start))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut sealed = WriteBatch::default();
+        for (key, value) in batch {
+            match value {
+                Some(value) => sealed.put(&key, self.seal(&key, &value)?),
+                None => sealed.delete(&key),
+            }
+        }
+        self.inner.write(sealed)
+    }
+
+    fn snapshot(&self, path: &Path) -> Result<()> {
+        self.inner.snapshot(path)
+    }
+
+    /// Snapshots keep the data key they were taken with. They open with this store's storage
+    /// key as long as `rewrap_key` moved them along with each rotation.
+    fn open_snapshot(&self, path: &Path) -> Result<Arc<dyn GraphStore>> {
+        let snapshot = self.inner.open_snapshot(path)?;
+        match read_header(snapshot.as_ref())? {
+            Some(header) => Ok(Arc::new(EncryptedStore::new(snapshot, &self.key, &header)?)),
+            // Taken before the database was encrypted
+            None => Ok(snapshot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{Entity, NodeId};
+    use crate::storage::GraphStorage;
+    use crate::store::MemoryStore;
+    use std::collections::HashMap;
+
+    fn contains(store: &dyn GraphStore, needle: &[u8]) -> bool {
+        store
+            .iter_from(b"")
+            .any(|item| item.unwrap().1.windows(needle.len()).any(|window| window == needle))
+    }
+
+    #[test]
+    fn test_migrate_lock_and_rotate() {
+        let raw = Arc::new(MemoryStore::new());
+        let storage = GraphStorage::from_store(raw.clone()).unwrap();
+        let node = Entity {
+            id: NodeId::new(),
+            label: "Tax return 2025".to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        };
+        storage.put_node(&node).unwrap();
+        drop(storage);
+        assert!(contains(raw.as_ref(), b"Tax return 2025"));
+
+        // Opening a plaintext store with a key migrates it
+        let key = StorageKey::new([7; 32]);
+        let storage = GraphStorage::from_store(unlock(raw.clone(), Some(&key)).unwrap()).unwrap();
+        assert_eq!(storage.get_node(&node.id).unwrap().unwrap().label, node.label);
+        assert!(!contains(raw.as_ref(), b"Tax return 2025"));
+        drop(storage);
+
+        let err = unlock(raw.clone(), None).err().unwrap();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::StorageLocked)));
+        let wrong = StorageKey::new([8; 32]);
+        let err = unlock(raw.clone(), Some(&wrong)).err().unwrap();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::InvalidStorageKey)));
+
+        rotate_key(raw.as_ref(), &key, &wrong).unwrap();
+        assert!(unlock(raw.clone(), Some(&key)).is_err());
+        let storage = GraphStorage::from_store(unlock(raw, Some(&wrong)).unwrap()).unwrap();
+        assert_eq!(storage.get_node(&node.id).unwrap().unwrap().label, node.label);
+    }
+
+    #[test]
+    fn test_interrupted_reencryption_resumes() {
+        let raw = Arc::new(MemoryStore::new());
+        for i in 0..5u8 {
+            raw.put(&[b'n', i], &[i + 1]).unwrap();
+        }
+
+        // Encrypting the plaintext store stops after the first two records
+        let key = StorageKey::new([7; 32]);
+        let plaintext = EncryptionHeader {
+            version: HEADER_VERSION,
+            data_key: Vec::new(),
+            created_at: 0,
+            rotation: None,
+        };
+        let mut header = begin_reencrypt(raw.as_ref(), plaintext, 0, 0, &key).unwrap();
+        let cipher = Cipher::new(&unwrap_data_key(&header.rotation.as_ref().unwrap().data_key, &key).unwrap());
+        assert!(reencrypt_chunk(raw.as_ref(), &mut header, None, &cipher, 2).unwrap());
+        let store = unlock(raw.clone(), Some(&key)).unwrap();
+        for i in 0..5u8 {
+            assert_eq!(store.get(&[b'n', i]).unwrap().unwrap(), [i + 1]);
+        }
+
+        // So does a rotation, which then has to be run again before the store opens
+        let next = StorageKey::new([8; 32]);
+        let old = data_cipher(&read_header(raw.as_ref()).unwrap().unwrap(), &key).unwrap();
+        let header = read_header(raw.as_ref()).unwrap().unwrap();
+        let mut header = begin_reencrypt(raw.as_ref(), header, 0, 1, &next).unwrap();
+        let cipher = Cipher::new(&unwrap_data_key(&header.rotation.as_ref().unwrap().data_key, &next).unwrap());
+        assert!(reencrypt_chunk(raw.as_ref(), &mut header, Some(&old), &cipher, 2).unwrap());
+        let err = unlock(raw.clone(), Some(&key)).err().unwrap();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::RotationInterrupted)));
+
+        rotate_key(raw.as_ref(), &key, &next).unwrap();
+        assert_eq!(key_generation(raw.as_ref()).unwrap(), 1);
+        let store = unlock(raw, Some(&next)).unwrap();
+        for i in 0..5u8 {
+            assert_eq!(store.get(&[b'n', i]).unwrap().unwrap(), [i + 1]);
+        }
+    }
+
+    #[test]
+    fn test_values_are_bound_to_their_keys() {
+        let raw = Arc::new(MemoryStore::new());
+        let store = unlock(raw.clone(), Some(&StorageKey::new([7; 32]))).unwrap();
+        store.put(b"node:a", b"first").unwrap();
+        store.put(b"node:b", b"second").unwrap();
+        assert_eq!(store.get(b"node:a").unwrap().unwrap(), b"first");
+
+        let moved = raw.get(b"node:a").unwrap().unwrap();
+        raw.put(b"node:b", &moved).unwrap();
+        assert!(store.get(b"node:b").is_err());
+    }
+
+    #[test]
+    fn test_rotation_advances_the_key_generation() {
+        // Never saved, so nothing is written to the path
+        let path = std::env::temp_dir().join(format!("athena-keys-{}", uuid::Uuid::new_v4()));
+        let mut key_manager = KeyManager::new(path).unwrap();
+        assert!(StorageKey::derive(&key_manager, 0).is_err());
+        key_manager.set_master_key([9; 32]).unwrap();
+        let (raw, snapshot) = (Arc::new(MemoryStore::new()), Arc::new(MemoryStore::new()));
+        let first = StorageKey::derive(&key_manager, 0).unwrap();
+        let node = Entity {
+            id: NodeId::new(),
+            label: "Passport scan".to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        };
+        for store in [&raw, &snapshot] {
+            let storage = GraphStorage::from_store(unlock(store.clone(), Some(&first)).unwrap()).unwrap();
+            storage.put_node(&node).unwrap();
+        }
+        assert_eq!(key_generation(raw.as_ref()).unwrap(), 0);
+
+        let generation = key_generation(raw.as_ref()).unwrap();
+        let current = StorageKey::derive(&key_manager, generation).unwrap();
+        let next = StorageKey::derive(&key_manager, generation + 1).unwrap();
+        assert_ne!(current.0, next.0);
+        rewrap_key(snapshot.as_ref(), &current, &next, generation + 1).unwrap();
+        assert_eq!(key_generation(snapshot.as_ref()).unwrap(), 1);
+        rotate_key(raw.as_ref(), &current, &next).unwrap();
+        assert_eq!(key_generation(raw.as_ref()).unwrap(), 1);
+
+        // The old key no longer opens the store; the one of its new generation does
+        let err = unlock(raw.clone(), Some(&first)).err().unwrap();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::InvalidStorageKey)));
+        let key = StorageKey::derive(&key_manager, key_generation(raw.as_ref()).unwrap()).unwrap();
+        let storage = GraphStorage::from_store(unlock(raw, Some(&key)).unwrap()).unwrap();
+        assert_eq!(storage.get_node(&node.id).unwrap().unwrap().label, node.label);
+
+        assert!(unlock(snapshot.clone(), Some(&first)).is_err());
+        let storage = GraphStorage::from_store(unlock(snapshot, Some(&key)).unwrap()).unwrap();
+        assert_eq!(storage.get_node(&node.id).unwrap().unwrap().label, node.label);
+    }
+}
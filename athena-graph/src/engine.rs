@@ -1,180 +1,1103 @@
-use crate::entity::{Edge, Entity, GraphUpdate, NodeId};
-use crate::query::{GraphPattern, GraphQuery, QueryResult};
-use crate::storage::GraphStorage;
-use crate::version::{Checkpoint, VersionId};
-use anyhow::Result;
+use crate::aggregate::{AggregateQuery, AggregateResult, Aggregator};
+use crate::akf::{AkfHeader, AkfReader, AkfRecord, AkfSummary, AkfWriter};
+use crate::entity::{Edge, Entity, GraphUpdate, NodeId, PropertyValue};
+use crate::query::{
+    decode_edge_cursor, encode_edge_cursor, is_builtin_property, Direction, EdgeFilter, EdgePage, FilterExpr,
+    FilterOperator, GraphPattern, NodeFilter, QueryResult,
+};
+use crate::rdf::{RdfProjection, SparqlResponse};
+use crate::search::{bm25, query_terms, snippet, SearchHit};
+use crate::timeline::{histogram, BucketSize, HistogramBucket, TimeQuery, TimelineEvent};
+use crate::vector::{
+    embedding_text, hnsw_search, normalize, Embedder, HashingEmbedder, VectorHit, VectorStore, HNSW_EF_SEARCH,
+    MAX_VECTOR_HITS,
+};
+use crate::error::GraphError;
+use crate::integrity::{IntegrityPolicy, NodeDeletePolicy, SchemaValidation};
+use crate::schema::{
+    is_schema_node, parse_schema_node, schema_node, schema_node_id, EntityType, SchemaRegistry, SchemaViolation,
+    SCHEMA_TYPE, TYPE_PROPERTY,
+};
+use crate::storage::{GraphMetadata, GraphStorage, StorageBatch};
+use crate::subscription::{ChangeSet, Subscription, SubscriptionRegistry};
+use crate::traversal::{
+    ExpandQuery, GraphPath, Neighborhood, PathQuery, ShortestPathQuery, Traversal,
+};
+use crate::merkle::{hash_edge, hash_node, MerkleTree};
+use crate::named_graph::{
+    copy_edge, copy_node, graph_field, graph_of, validate_graph, validate_graph_name, GraphInfo, GraphTransfer,
+    TransferMode, TransferResult, GRAPH_FIELD,
+};
+use crate::version::{
+    AsOf, Checkpoint, CheckpointVerification, EdgeRevision, NodeRevision, VersionId,
+};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// Records written per commit when importing an archive.
+const IMPORT_CHUNK: usize = 1000;
+
 #[async_trait]
 pub trait GraphEngine: Send + Sync {
     async fn query(&self, pattern: &GraphPattern) -> Result<QueryResult>;
+    /// Counts, metrics, groups and facets over the nodes matching the query's filter,
+    /// computed in one pass without materializing the nodes.
+    async fn aggregate(&self, query: &AggregateQuery) -> Result<AggregateResult>;
+    /// Applies all changes atomically, with the same version checks as `put_node`/`put_edge`
+    /// and the integrity policy applied to the update as a whole.
     async fn update(&self, update: &GraphUpdate) -> Result<VersionId>;
-    async fn subscribe(&self, pattern: &GraphPattern) -> Result<tokio::sync::mpsc::Receiver<GraphUpdate>>;
+    /// Current version and identity of the underlying database.
+    async fn metadata(&self) -> Result<GraphMetadata>;
+    async fn subscribe(&self, pattern: &GraphPattern) -> Result<Subscription>;
+    async fn unsubscribe(&self, id: &uuid::Uuid) -> Result<bool>;
+    /// Records the Merkle root of the current graph state and persists it, together with
+    /// an on-disk snapshot of the database when a snapshot directory is configured.
     async fn checkpoint(&self) -> Result<Checkpoint>;
+    async fn list_checkpoints(&self) -> Result<Vec<Checkpoint>>;
+    /// Recomputes the graph state a checkpoint was taken at and compares it with the
+    /// recorded hash, detecting records that were altered outside the engine.
+    async fn verify_checkpoint(&self, id: &uuid::Uuid) -> Result<CheckpointVerification>;
+    /// Checkpoints that still have a snapshot, oldest first.
+    async fn list_snapshots(&self) -> Result<Vec<Checkpoint>>;
+    /// Brings every node and edge back to its state in the checkpoint's snapshot. The
+    /// rollback is written as a new version, so history and older checkpoints stay intact.
+    async fn restore_snapshot(&self, id: &uuid::Uuid) -> Result<VersionId>;
+    /// Deletes all but the `keep` newest snapshots and returns the checkpoints they belonged
+    /// to. The checkpoints themselves are kept and can still be verified.
+    async fn prune_snapshots(&self, keep: usize) -> Result<Vec<Checkpoint>>;
     async fn get_node(&self, id: &NodeId) -> Result<Option<Entity>>;
-    async fn put_node(&self, entity: Entity) -> Result<()>;
+    /// Writes a node and returns it as stored. Fails with `GraphError::VersionConflict`
+    /// when `entity.version` does not match the stored version.
+    async fn put_node(&self, entity: Entity) -> Result<Entity>;
     async fn delete_node(&self, id: &NodeId) -> Result<()>;
     async fn get_edge(&self, id: &uuid::Uuid) -> Result<Option<Edge>>;
-    async fn put_edge(&self, edge: Edge) -> Result<()>;
+    async fn put_edge(&self, edge: Edge) -> Result<Edge>;
     async fn delete_edge(&self, id: &uuid::Uuid) -> Result<()>;
+    async fn neighbors(
+        &self,
+        node: &NodeId,
+        direction: Direction,
+        label: Option<&str>,
+    ) -> Result<Vec<Entity>>;
+    async fn edges_of(&self, node: &NodeId) -> Result<Vec<Edge>>;
+    /// Up to `limit` edges in id order, resuming after `cursor` (a previous page's
+    /// `next_cursor`) and restricted to `graphs` unless it is empty.
+    async fn list_edges(&self, graphs: &[String], limit: usize, cursor: Option<&str>) -> Result<EdgePage>;
+    async fn expand(&self, query: &ExpandQuery) -> Result<Neighborhood>;
+    async fn find_paths(&self, query: &PathQuery) -> Result<Vec<GraphPath>>;
+    async fn shortest_path(&self, query: &ShortestPathQuery) -> Result<Option<GraphPath>>;
+    async fn sparql(&self, query: &str) -> Result<SparqlResponse>;
+    /// Full-text search over node labels and string properties, best BM25 score first.
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>>;
+    /// Timestamps of nodes within the query's range, in time order, answered from the time index.
+    async fn timeline(&self, query: &TimeQuery) -> Result<Vec<TimelineEvent>>;
+    /// Number of timestamps within the query's range per bucket, oldest bucket first.
+    /// `utc_offset` (seconds east of UTC) moves day and week boundaries to local midnight.
+    async fn time_histogram(&self, query: &TimeQuery, bucket: BucketSize, utc_offset: i64) -> Result<Vec<HistogramBucket>>;
+    /// Embeds text with the engine's embedder, e.g. to pass the result to `nearest`.
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+    /// Approximate `k` nearest nodes to `vector` by cosine similarity, most similar first,
+    /// restricted to nodes matching `filter`. `k` is capped at `MAX_VECTOR_HITS`.
+    async fn nearest(&self, vector: &[f32], k: usize, filter: Option<&FilterExpr>) -> Result<Vec<VectorHit>>;
+    /// Nodes most similar to `id`, excluding itself. Empty when the node has no text to embed.
+    async fn similar_to(&self, id: &NodeId, k: usize, filter: Option<&FilterExpr>) -> Result<Vec<VectorHit>>;
+    async fn node_history(&self, id: &NodeId) -> Result<Vec<NodeRevision>>;
+    async fn edge_history(&self, id: &uuid::Uuid) -> Result<Vec<EdgeRevision>>;
+    async fn get_node_as_of(&self, id: &NodeId, at: AsOf) -> Result<Option<Entity>>;
+    /// Runs `pattern` against the graph as it was at `at`. Scans the full revision history.
+    async fn query_as_of(&self, pattern: &GraphPattern, at: AsOf) -> Result<QueryResult>;
+    /// Writes the state a node had at `version` as its newest revision.
+    async fn restore_node(&self, id: &NodeId, version: VersionId) -> Result<Entity>;
+    /// Edges whose `from` or `to` node does not exist.
+    async fn dangling_edges(&self) -> Result<Vec<Edge>>;
+    /// Deletes all dangling edges in one update and returns them.
+    async fn repair_dangling_edges(&self) -> Result<Vec<Edge>>;
+    /// Built-in and stored entity types.
+    async fn schemas(&self) -> Result<Vec<EntityType>>;
+    /// Stores a type as a node of the graph, replacing a stored or built-in type of the same name.
+    async fn put_schema(&self, schema: EntityType) -> Result<EntityType>;
+    /// Deletes a stored type; a built-in type of the same name applies again. Returns false
+    /// when no such type is stored.
+    async fn delete_schema(&self, name: &str) -> Result<bool>;
+    /// Stored nodes and edges that do not conform to the current schemas, e.g. after a type changed.
+    async fn schema_violations(&self) -> Result<Vec<SchemaViolation>>;
+    /// Named graphs holding at least one node or edge, by name.
+    async fn graphs(&self) -> Result<Vec<GraphInfo>>;
+    /// Moves or copies the selected nodes of one graph, with the edges between them, into
+    /// another in a single update.
+    async fn transfer(&self, transfer: &GraphTransfer) -> Result<TransferResult>;
+    /// Deletes every node and edge of a graph in a single update. Edges from other graphs
+    /// into it are handled by the node delete policy. Returns the number of nodes deleted.
+    async fn delete_graph(&self, name: &str) -> Result<usize>;
+    /// Writes the graph as an AKF archive: stored schemas, nodes and edges, plus every
+    /// revision and the revision log when `history` is set.
+    async fn export_akf(&self, writer: Box<dyn Write + Send>, history: bool) -> Result<AkfSummary>;
+    /// Reads an AKF archive into the graph. Into a database that has never been written to,
+    /// an archive with history is restored as it was, versions included. Otherwise the
+    /// current records are merged in as new versions, overwriting records with the same id,
+    /// and the archived history is skipped.
+    async fn import_akf(&self, reader: Box<dyn Read + Send>) -> Result<AkfSummary>;
 }
 
 pub struct DefaultGraphEngine {
     storage: Arc<GraphStorage>,
     version: Arc<RwLock<VersionId>>,
+    // Built on the first SPARQL query, then kept in sync by every write
+    rdf: std::sync::RwLock<Option<Arc<RdfProjection>>>,
+    subscriptions: SubscriptionRegistry,
+    snapshot_dir: Option<PathBuf>,
+    integrity: IntegrityPolicy,
+    embedder: Arc<dyn Embedder>,
+    // Set once the vector index is known to be built with `embedder`
+    vectors_ready: AtomicBool,
+    // Loaded on first use, dropped by every write that touches a schema node
+    schemas: std::sync::RwLock<Option<Arc<SchemaRegistry>>>,
 }
 
 impl DefaultGraphEngine {
     pub fn new(storage: GraphStorage) -> Self {
+        // Continue numbering after the last committed version
+        let version = match storage.metadata() {
+            Ok(metadata) => metadata.version,
+            Err(e) => {
+                tracing::warn!("Failed to read the graph metadata: {}", e);
+                VersionId::new()
+            }
+        };
         Self {
             storage: Arc::new(storage),
-            version: Arc::new(RwLock::new(VersionId::new())),
+            version: Arc::new(RwLock::new(version)),
+            rdf: std::sync::RwLock::new(None),
+            subscriptions: SubscriptionRegistry::new(),
+            snapshot_dir: None,
+            integrity: IntegrityPolicy::default(),
+            embedder: Arc::new(HashingEmbedder::default()),
+            vectors_ready: AtomicBool::new(false),
+            schemas: std::sync::RwLock::new(None),
         }
     }
-}
 
-#[async_trait]
-impl GraphEngine for DefaultGraphEngine {
-    async fn query(&self, pattern: &GraphPattern) -> Result<QueryResult> {
-        let mut nodes = Vec::new();
-        let mut edges = Vec::new();
+    pub fn with_integrity(mut self, integrity: IntegrityPolicy) -> Self {
+        self.integrity = integrity;
+        self
+    }
 
-        // Simple implementation - iterate all nodes and filter
-        for node_result in self.storage.iter_nodes() {
-            let node = node_result?;
-            let mut matches = true;
-
-            for filter in &pattern.node_filters {
-                match filter.property.as_str() {
-                    "label" => {
-                        matches = match filter.operator {
-                            crate::query::FilterOperator::Equals => node.label == filter.value,
-                            crate::query::FilterOperator::Contains => node.label.contains(&filter.value),
-                            crate::query::FilterOperator::StartsWith => node.label.starts_with(&filter.value),
-                            _ => false,
-                        };
+    /// Replaces the built-in hashing embedder. Switching embedders re-embeds every node
+    /// on the next write or vector query.
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = embedder;
+        self.vectors_ready = AtomicBool::new(false);
+        self
+    }
+
+    /// (Re)builds the vector index when it was built with another embedder or not at all.
+    /// Callers must hold the version write lock.
+    fn ensure_vector_index(&self) -> Result<()> {
+        if self.vectors_ready.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let model = self.embedder.name();
+        if self.storage.vector_model()?.as_deref() != Some(model.as_str()) {
+            tracing::info!("Embedding all nodes with {}", model);
+            self.storage.clear_vectors()?;
+            let mut batch = self.storage.batch();
+            for (i, node) in self.storage.iter_nodes().enumerate() {
+                self.stage_vector(&mut batch, &node?)?;
+                if i % 1000 == 999 {
+                    batch.commit()?;
+                    batch = self.storage.batch();
+                }
+            }
+            batch.set_vector_model(&model);
+            batch.commit()?;
+        }
+        self.vectors_ready.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn stage_vector(&self, batch: &mut StorageBatch<'_>, node: &Entity) -> Result<()> {
+        let vector = normalize(self.embedder.embed(&embedding_text(node))?);
+        if vector.len() != self.embedder.dimensions() {
+            anyhow::bail!(
+                "Embedder {} returned {} dimensions instead of {}",
+                self.embedder.name(),
+                vector.len(),
+                self.embedder.dimensions()
+            );
+        }
+        if vector.iter().all(|x| *x == 0.0) {
+            batch.delete_vector(&node.id)
+        } else {
+            batch.put_vector(&node.id, vector)
+        }
+    }
+
+    /// Makes every checkpoint also snapshot the database into `<dir>/<checkpoint id>`.
+    pub fn with_snapshot_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.snapshot_dir = Some(dir.into());
+        self
+    }
+
+    fn resolve_as_of(&self, at: AsOf) -> Result<Option<VersionId>> {
+        match at {
+            AsOf::Version(version) => Ok(Some(version)),
+            AsOf::Timestamp(timestamp) => self.storage.version_at(timestamp),
+        }
+    }
+
+    fn projection(&self) -> Option<Arc<RdfProjection>> {
+        self.rdf.read().expect("rdf projection lock poisoned").clone()
+    }
+
+    /// Stages a node write after checking its version against the stored record.
+    /// The record that gets written carries the next version.
+    fn stage_node(
+        &self,
+        batch: &mut StorageBatch<'_>,
+        entity: &Entity,
+        changes: &mut ChangeSet,
+    ) -> Result<()> {
+        validate_graph(entity.graph.as_deref())?;
+        let old = batch.get_node(&entity.id)?;
+        let mut entity = entity.clone();
+        // The default graph is always stored as `None`
+        entity.graph = entity.graph.as_deref().and_then(graph_field);
+        entity.version = next_version(
+            "node",
+            entity.id.0,
+            old.as_ref().map(|old| old.version),
+            entity.version,
+        )?;
+        batch.put_node(&entity)?;
+        changes.nodes.push((old, entity));
+        Ok(())
+    }
+
+    fn stage_node_delete(
+        &self,
+        batch: &mut StorageBatch<'_>,
+        id: &NodeId,
+        changes: &mut ChangeSet,
+    ) -> Result<()> {
+        if let Some(old) = batch.get_node(id)? {
+            batch.delete_node(id)?;
+            changes.deleted_nodes.push(old);
+        }
+        Ok(())
+    }
+
+    fn stage_edge(
+        &self,
+        batch: &mut StorageBatch<'_>,
+        edge: &Edge,
+        changes: &mut ChangeSet,
+    ) -> Result<()> {
+        validate_graph(edge.graph.as_deref())?;
+        let old = batch.get_edge(&edge.id)?;
+        let mut edge = edge.clone();
+        edge.graph = edge.graph.as_deref().and_then(graph_field);
+        edge.version = next_version(
+            "edge",
+            edge.id,
+            old.as_ref().map(|old| old.version),
+            edge.version,
+        )?;
+        batch.put_edge(&edge)?;
+        changes.edges.push((old, edge));
+        Ok(())
+    }
+
+    fn stage_edge_delete(
+        &self,
+        batch: &mut StorageBatch<'_>,
+        id: &uuid::Uuid,
+        changes: &mut ChangeSet,
+    ) -> Result<()> {
+        if let Some(old) = batch.get_edge(id)? {
+            batch.delete_edge(id)?;
+            changes.deleted_edges.push(old);
+        }
+        Ok(())
+    }
+
+    /// Applies the integrity policy once everything in an update is staged, so that an update
+    /// may create a node together with its edges, or delete a node together with its edges.
+    fn enforce_integrity(
+        &self,
+        batch: &mut StorageBatch<'_>,
+        deleted_nodes: &[NodeId],
+        changes: &mut ChangeSet,
+    ) -> Result<()> {
+        for id in deleted_nodes {
+            // Edges stored before this update plus the ones it writes
+            let mut candidates: Vec<uuid::Uuid> = self
+                .storage
+                .adjacent(id, Direction::Both, None)?
+                .into_iter()
+                .map(|adjacency| adjacency.edge_id)
+                .collect();
+            candidates.extend(
+                changes
+                    .edges
+                    .iter()
+                    .filter(|(_, edge)| edge.from == *id || edge.to == *id)
+                    .map(|(_, edge)| edge.id),
+            );
+
+            let mut seen = HashSet::new();
+            let mut remaining = Vec::new();
+            for edge_id in candidates {
+                if !seen.insert(edge_id) {
+                    continue;
+                }
+                if batch
+                    .get_edge(&edge_id)?
+                    .is_some_and(|edge| edge.from == *id || edge.to == *id)
+                {
+                    remaining.push(edge_id);
+                }
+            }
+
+            match self.integrity.on_node_delete {
+                NodeDeletePolicy::Cascade => {
+                    for edge_id in &remaining {
+                        self.stage_edge_delete(batch, edge_id, changes)?;
                     }
-                    _ => {
-                        if let Some(prop_value) = node.properties.get(&filter.property) {
-                            matches = match filter.operator {
-                                crate::query::FilterOperator::Equals => {
-                                    format!("{:?}", prop_value) == filter.value
-                                }
-                                _ => false,
-                            };
-                        } else {
-                            matches = false;
-                        }
+                }
+                NodeDeletePolicy::Restrict if !remaining.is_empty() => {
+                    return Err(GraphError::NodeHasEdges {
+                        id: id.0,
+                        edges: remaining.len(),
                     }
+                    .into());
                 }
-                if !matches {
-                    break;
+                NodeDeletePolicy::Restrict | NodeDeletePolicy::Orphan => {}
+            }
+        }
+
+        if self.integrity.reject_dangling_edges {
+            for (_, edge) in &changes.edges {
+                if batch.get_edge(&edge.id)?.is_none() {
+                    continue;
+                }
+                for endpoint in [&edge.from, &edge.to] {
+                    if batch.get_node(endpoint)?.is_none() {
+                        return Err(GraphError::DanglingEdge {
+                            edge: edge.id,
+                            node: endpoint.0,
+                        }
+                        .into());
+                    }
                 }
             }
+        }
+        Ok(())
+    }
 
-            if matches {
-                nodes.push(node);
+    fn schema_registry(&self) -> Result<Arc<SchemaRegistry>> {
+        if let Some(registry) = self.schemas.read().expect("schema lock poisoned").clone() {
+            return Ok(registry);
+        }
+        let registry = Arc::new(self.load_schemas(Vec::new(), |id| self.storage.get_node(id))?);
+        *self.schemas.write().expect("schema lock poisoned") = Some(registry.clone());
+        Ok(registry)
+    }
+
+    /// Built-in types overlaid with the stored schema nodes, plus the nodes in `extra`,
+    /// all read through `get`.
+    fn load_schemas(
+        &self,
+        extra: Vec<NodeId>,
+        get: impl Fn(&NodeId) -> Result<Option<Entity>>,
+    ) -> Result<SchemaRegistry> {
+        let mut ids = self
+            .storage
+            .find_by_property(
+                TYPE_PROPERTY,
+                FilterOperator::Equals,
+                &PropertyValue::String(SCHEMA_TYPE.to_string()),
+            )?
+            .unwrap_or_default();
+        ids.extend(extra);
+        ids.sort_by_key(|id| id.0);
+        ids.dedup();
+
+        let mut registry = SchemaRegistry::builtin();
+        for id in ids {
+            let Some(node) = get(&id)? else {
+                continue;
+            };
+            if !is_schema_node(&node) {
+                continue;
+            }
+            match parse_schema_node(&node) {
+                Ok(entity_type) => registry.insert(entity_type),
+                Err(e) => tracing::warn!("Skipping schema node {}: {}", id.0, e),
             }
+        }
+        Ok(registry)
+    }
 
-            if let Some(limit) = pattern.limit {
-                if nodes.len() >= limit {
-                    break;
-                }
+    /// Checks the nodes and edges an update writes against their types. Schemas written by
+    /// the same update already apply.
+    fn validate_schema(&self, batch: &StorageBatch<'_>, changes: &ChangeSet) -> Result<()> {
+        let reject_unknown = match self.integrity.schema_validation {
+            SchemaValidation::Off => return Ok(()),
+            SchemaValidation::Known => false,
+            SchemaValidation::Strict => true,
+        };
+        let violation = |kind, id, message| GraphError::SchemaViolation { kind, id, message };
+
+        let touched: Vec<NodeId> = changes
+            .nodes
+            .iter()
+            .flat_map(|(old, new)| old.iter().chain(Some(new)))
+            .chain(&changes.deleted_nodes)
+            .filter(|node| is_schema_node(node))
+            .map(|node| node.id.clone())
+            .collect();
+        let registry = if touched.is_empty() {
+            self.schema_registry()?
+        } else {
+            let registry = self.load_schemas(touched.clone(), |id| batch.get_node(id))?;
+            registry
+                .check()
+                .map_err(|message| violation("node", touched[0].0, message))?;
+            Arc::new(registry)
+        };
+
+        let mut seen = HashSet::new();
+        for (_, node) in &changes.nodes {
+            if !seen.insert(node.id.clone()) {
+                continue;
+            }
+            if let Some(current) = batch.get_node(&node.id)? {
+                registry
+                    .validate_node(&current, reject_unknown)
+                    .map_err(|message| violation("node", node.id.0, message))?;
+            }
+        }
+        for (_, edge) in &changes.edges {
+            let Some(current) = batch.get_edge(&edge.id)? else {
+                continue;
+            };
+            if let (Some(from), Some(to)) = (batch.get_node(&current.from)?, batch.get_node(&current.to)?) {
+                registry
+                    .validate_edge(&current, &from, &to)
+                    .map_err(|message| violation("edge", edge.id, message))?;
             }
         }
+        Ok(())
+    }
 
-        // Find edges
-        for edge_result in self.storage.iter_edges() {
-            let edge = edge_result?;
-            let mut matches = true;
+    fn find_dangling_edges(&self) -> Result<Vec<Edge>> {
+        let mut dangling = Vec::new();
+        for edge in self.storage.iter_edges() {
+            let edge = edge?;
+            if self.storage.get_node(&edge.from)?.is_none() || self.storage.get_node(&edge.to)?.is_none() {
+                dangling.push(edge);
+            }
+        }
+        Ok(dangling)
+    }
 
-            for filter in &pattern.edge_filters {
-                if let Some(ref from) = filter.from {
-                    if &edge.from != from {
-                        matches = false;
-                        break;
+    /// Commits staged writes as `version`, then brings the derived views (RDF projection,
+    /// subscribers) up to date in the order the writes were staged.
+    fn commit(&self, mut batch: StorageBatch<'_>, version: VersionId, changes: &ChangeSet) -> Result<()> {
+        // Deleted nodes already left the vector index with their records
+        let mut embedded = HashSet::new();
+        for (_, node) in &changes.nodes {
+            if !embedded.insert(node.id.clone()) {
+                continue;
+            }
+            if let Some(current) = batch.get_node(&node.id)? {
+                self.stage_vector(&mut batch, &current)?;
+            }
+        }
+        batch.commit_version(version, chrono::Utc::now().timestamp())?;
+
+        let touches_schema = changes
+            .nodes
+            .iter()
+            .any(|(old, new)| is_schema_node(new) || old.as_ref().is_some_and(is_schema_node))
+            || changes.deleted_nodes.iter().any(is_schema_node);
+        if touches_schema {
+            *self.schemas.write().expect("schema lock poisoned") = None;
+        }
+
+        if let Some(rdf) = self.projection() {
+            for (old, new) in &changes.nodes {
+                rdf.put_node(old.as_ref(), new)?;
+            }
+            for (old, new) in &changes.edges {
+                rdf.put_edge(old.as_ref(), new)?;
+            }
+            for old in &changes.deleted_nodes {
+                rdf.delete_node(old)?;
+            }
+            for old in &changes.deleted_edges {
+                rdf.delete_edge(old)?;
+            }
+        }
+        self.subscriptions.publish(changes);
+        Ok(())
+    }
+
+    /// Nodes that may match `expr`, narrowed through the label/property indexes when the
+    /// filter allows it, otherwise a full scan. Callers still have to check `expr.matches`.
+    fn candidates(&self, expr: &FilterExpr) -> Result<Box<dyn Iterator<Item = Result<Entity>> + '_>> {
+        self.candidates_after(expr, None)
+    }
+
+    /// Like `candidates`, in node id order and starting after `after`. Nodes are loaded as
+    /// the iterator advances, so a caller that stops early never reads the rest.
+    fn candidates_after(
+        &self,
+        expr: &FilterExpr,
+        after: Option<&NodeId>,
+    ) -> Result<Box<dyn Iterator<Item = Result<Entity>> + '_>> {
+        Ok(match (self.indexed_candidates(expr)?, after) {
+            (Some(mut ids), _) => {
+                ids.sort_by_key(|id| id.0);
+                ids.dedup();
+                if let Some(after) = after {
+                    ids.retain(|id| id.0 > after.0);
+                }
+                Box::new(
+                    ids.into_iter()
+                        .filter_map(move |id| self.storage.get_node(&id).transpose()),
+                )
+            }
+            (None, Some(after)) => Box::new(self.storage.iter_nodes_after(after)),
+            (None, None) => Box::new(self.storage.iter_nodes()),
+        })
+    }
+
+    fn indexed_candidates(&self, expr: &FilterExpr) -> Result<Option<Vec<NodeId>>> {
+        match expr {
+            FilterExpr::Filter(filter) => self.filter_candidates(filter),
+            FilterExpr::And(children) => {
+                // Any one child bounds the result; prefer the most selective plain filter,
+                // then fall back to nested groups
+                let mut filters: Vec<&NodeFilter> = children
+                    .iter()
+                    .filter_map(|child| match child {
+                        FilterExpr::Filter(filter) => Some(filter),
+                        _ => None,
+                    })
+                    .filter(|filter| index_rank(filter.operator).is_some())
+                    .collect();
+                filters.sort_by_key(|filter| index_rank(filter.operator));
+
+                for filter in filters {
+                    if let Some(ids) = self.filter_candidates(filter)? {
+                        return Ok(Some(ids));
                     }
                 }
-                if let Some(ref to) = filter.to {
-                    if &edge.to != to {
-                        matches = false;
-                        break;
+                for child in children {
+                    if matches!(child, FilterExpr::Filter(_)) {
+                        continue;
+                    }
+                    if let Some(ids) = self.indexed_candidates(child)? {
+                        return Ok(Some(ids));
+                    }
+                }
+                Ok(None)
+            }
+            FilterExpr::Or(children) => {
+                // A union is only usable when every branch is indexed
+                let mut ids = Vec::new();
+                for child in children {
+                    match self.indexed_candidates(child)? {
+                        Some(child_ids) => ids.extend(child_ids),
+                        None => return Ok(None),
                     }
                 }
-                if let Some(ref label) = filter.label {
-                    if &edge.label != label {
-                        matches = false;
+                Ok(Some(ids))
+            }
+            FilterExpr::Not(_) => Ok(None),
+            FilterExpr::Radius(filter) => Ok(Some(
+                self.storage.find_within(&filter.property, &filter.bounding_box())?,
+            )),
+            FilterExpr::BBox(filter) => Ok(Some(self.storage.find_within(&filter.property, &filter.bounds())?)),
+        }
+    }
+
+    fn filter_candidates(&self, filter: &NodeFilter) -> Result<Option<Vec<NodeId>>> {
+        if index_rank(filter.operator).is_none() {
+            return Ok(None);
+        }
+        match (filter.property.as_str(), &filter.value) {
+            ("label", PropertyValue::String(label)) => match filter.operator {
+                FilterOperator::Equals => Ok(Some(self.storage.find_by_label(label)?)),
+                FilterOperator::StartsWith => Ok(Some(self.storage.find_by_label_prefix(label)?)),
+                _ => Ok(None),
+            },
+            ("created_at" | "updated_at", PropertyValue::DateTime(t)) => {
+                let (from, to) = match filter.operator {
+                    FilterOperator::Equals => (Some(*t), Some(t.saturating_add(1))),
+                    FilterOperator::GreaterThan => (Some(t.saturating_add(1)), None),
+                    FilterOperator::GreaterThanOrEqual => (Some(*t), None),
+                    FilterOperator::LessThan => (None, Some(*t)),
+                    FilterOperator::LessThanOrEqual => (None, Some(t.saturating_add(1))),
+                    _ => return Ok(None),
+                };
+                let mut ids = Vec::new();
+                for entry in self.storage.time_entries(from, to, false) {
+                    let (_, field, id) = entry?;
+                    if field == filter.property {
+                        ids.push(id);
+                    }
+                }
+                Ok(Some(ids))
+            }
+            (GRAPH_FIELD, PropertyValue::String(graph)) => match filter.operator {
+                FilterOperator::Equals => Ok(Some(self.storage.graph_nodes(graph)?)),
+                _ => Ok(None),
+            },
+            (property, _) if is_builtin_property(property) => Ok(None),
+            (property, value) => self.storage.find_by_property(property, filter.operator, value),
+        }
+    }
+
+    /// Resolves candidate edge ids through the adjacency index using the first filter
+    /// that names an endpoint. Returns `None` when every filter is unanchored.
+    fn indexed_edge_candidates(&self, filters: &[EdgeFilter]) -> Result<Option<Vec<uuid::Uuid>>> {
+        let adjacent = match filters.iter().find(|f| f.from.is_some() || f.to.is_some()) {
+            Some(EdgeFilter { from: Some(from), label, .. }) => {
+                self.storage.adjacent(from, Direction::Outgoing, label.as_deref())?
+            }
+            Some(EdgeFilter { to: Some(to), label, .. }) => {
+                self.storage.adjacent(to, Direction::Incoming, label.as_deref())?
+            }
+            _ => return Ok(None),
+        };
+
+        let mut seen = HashSet::new();
+        Ok(Some(
+            adjacent
+                .into_iter()
+                .map(|a| a.edge_id)
+                .filter(|id| seen.insert(*id))
+                .collect(),
+        ))
+    }
+
+    /// Writes an archive's records and history as they are, committing every
+    /// `IMPORT_CHUNK` records, and moves the graph to the archived version.
+    fn restore_archive<R: Read>(&self, archive: &mut AkfReader<R>) -> Result<VersionId> {
+        let mut batch = self.storage.batch();
+        let mut staged = 0;
+        for record in archive.by_ref() {
+            match record? {
+                AkfRecord::Schema(schema) => batch.put_node(&schema_node(&schema)?)?,
+                AkfRecord::Node(node) => {
+                    batch.put_node(&node)?;
+                    self.stage_vector(&mut batch, &node)?;
+                }
+                AkfRecord::Edge(edge) => batch.put_edge(&edge)?,
+                AkfRecord::NodeHistory { id, revisions } => batch.put_node_revisions(&id, &revisions)?,
+                AkfRecord::EdgeHistory { id, revisions } => batch.put_edge_revisions(&id, &revisions)?,
+                AkfRecord::Log(entry) => batch.put_revision_log(&entry)?,
+                AkfRecord::Header(_) | AkfRecord::End(_) => {}
+            }
+            staged += 1;
+            if staged == IMPORT_CHUNK {
+                std::mem::replace(&mut batch, self.storage.batch()).commit()?;
+                staged = 0;
+            }
+        }
+        let version = archive.header().source.version;
+        batch.set_version(version);
+        batch.commit()?;
+
+        // Both are rebuilt from storage on next use
+        *self.rdf.write().expect("rdf projection lock poisoned") = None;
+        *self.schemas.write().expect("schema lock poisoned") = None;
+        Ok(version)
+    }
+
+    /// Writes an archive's current records as updates of `IMPORT_CHUNK` records each.
+    fn merge_archive<R: Read>(&self, archive: &mut AkfReader<R>, version: &mut VersionId) -> Result<()> {
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for record in archive.by_ref() {
+            match record? {
+                AkfRecord::Schema(schema) => nodes.push(schema_node(&schema)?),
+                AkfRecord::Node(node) => nodes.push(node),
+                AkfRecord::Edge(edge) => edges.push(edge),
+                // Archived versions would interleave with this database's own
+                _ => {}
+            }
+            if nodes.len() + edges.len() == IMPORT_CHUNK {
+                self.import_chunk(std::mem::take(&mut nodes), std::mem::take(&mut edges), version)?;
+            }
+        }
+        self.import_chunk(nodes, edges, version)
+    }
+
+    fn import_chunk(&self, nodes: Vec<Entity>, edges: Vec<Edge>, version: &mut VersionId) -> Result<()> {
+        if nodes.is_empty() && edges.is_empty() {
+            return Ok(());
+        }
+        let mut batch = self.storage.batch();
+        let mut changes = ChangeSet::default();
+        // Archived records replace stored ones whatever their version
+        for mut node in nodes {
+            if let Some(stored) = batch.get_node(&node.id)? {
+                node.version = stored.version;
+            }
+            self.stage_node(&mut batch, &node, &mut changes)?;
+        }
+        for mut edge in edges {
+            if let Some(stored) = batch.get_edge(&edge.id)? {
+                edge.version = stored.version;
+            }
+            self.stage_edge(&mut batch, &edge, &mut changes)?;
+        }
+        self.enforce_integrity(&mut batch, &[], &mut changes)?;
+        self.validate_schema(&batch, &changes)?;
+
+        let next = version.next();
+        self.commit(batch, next, &changes)?;
+        *version = next;
+        Ok(())
+    }
+}
+
+/// Lower ranks are expected to be more selective; `None` means the operator is never indexed.
+fn index_rank(operator: FilterOperator) -> Option<u8> {
+    match operator {
+        FilterOperator::Equals => Some(0),
+        FilterOperator::StartsWith => Some(1),
+        FilterOperator::GreaterThan
+        | FilterOperator::GreaterThanOrEqual
+        | FilterOperator::LessThan
+        | FilterOperator::LessThanOrEqual => Some(2),
+        FilterOperator::Contains | FilterOperator::EndsWith => None,
+    }
+}
+
+/// Optimistic concurrency: a record that already exists may only be overwritten by a write
+/// carrying its current version, and is stored with the next one. New records keep the
+/// version they were created with.
+fn next_version(kind: &'static str, id: uuid::Uuid, stored: Option<u64>, expected: u64) -> Result<u64> {
+    match stored {
+        Some(actual) if actual != expected => Err(GraphError::VersionConflict {
+            kind,
+            id,
+            expected,
+            actual,
+        }
+        .into()),
+        Some(actual) => Ok(actual + 1),
+        None => Ok(expected.max(1)),
+    }
+}
+
+#[async_trait]
+impl GraphEngine for DefaultGraphEngine {
+    async fn query(&self, pattern: &GraphPattern) -> Result<QueryResult> {
+        let expr = pattern.node_expr();
+        let (nodes, next_cursor) = if pattern.order_by.is_empty() {
+            // Pages follow node id order, so the scan resumes after the cursor and stops once
+            // it has one match more than the page holds
+            let after = pattern.after_id()?;
+            let wanted = pattern.limit.map(|limit| limit.saturating_add(1));
+            let mut nodes = Vec::new();
+            for node_result in self.candidates_after(&expr, after.as_ref())? {
+                let node = node_result?;
+                if expr.matches(&node) {
+                    nodes.push(node);
+                    if Some(nodes.len()) == wanted {
                         break;
                     }
                 }
             }
+            pattern.page(nodes)
+        } else {
+            // Any node may sort first, so all matches are needed
+            let mut nodes = Vec::new();
+            for node_result in self.candidates(&expr)? {
+                let node = node_result?;
+                if expr.matches(&node) {
+                    nodes.push(node);
+                }
+            }
+            pattern.paginate(nodes)?
+        };
 
-            if matches {
-                edges.push(edge);
+        // Only edges touching the page's nodes are returned, so edges page along with them.
+        // Edge filters anchored on an endpoint are answered from the adjacency index,
+        // otherwise the adjacency of the page's nodes is read.
+        let page: HashSet<&NodeId> = nodes.iter().map(|node| &node.id).collect();
+        let edge_ids = match self.indexed_edge_candidates(&pattern.edge_filters)? {
+            Some(ids) => ids,
+            None => {
+                let mut ids = Vec::new();
+                for node in &nodes {
+                    let adjacent = self.storage.adjacent(&node.id, Direction::Both, None)?;
+                    ids.extend(adjacent.into_iter().map(|adjacency| adjacency.edge_id));
+                }
+                ids
+            }
+        };
+        let mut seen = HashSet::new();
+        let mut edges = Vec::new();
+        for id in edge_ids {
+            if !seen.insert(id) {
+                continue;
+            }
+            if let Some(edge) = self.storage.get_edge(&id)? {
+                if (page.contains(&edge.from) || page.contains(&edge.to)) && pattern.edge_matches(&edge) {
+                    edges.push(edge);
+                }
             }
         }
 
-        Ok(QueryResult { nodes, edges })
+        Ok(QueryResult {
+            nodes,
+            edges,
+            next_cursor,
+        })
+    }
+
+    async fn aggregate(&self, query: &AggregateQuery) -> Result<AggregateResult> {
+        let mut aggregator = Aggregator::new(query)?;
+        let expr = query.filter.clone().unwrap_or(FilterExpr::And(vec![]));
+        for node_result in self.candidates(&expr)? {
+            let node = node_result?;
+            if expr.matches(&node) {
+                aggregator.add(&node);
+            }
+        }
+        Ok(aggregator.finish())
     }
 
     async fn update(&self, update: &GraphUpdate) -> Result<VersionId> {
+        // Holding the version lock serializes writers, so version checks and the commit
+        // cannot interleave with another update
         let mut version = self.version.write().await;
+        self.ensure_vector_index()?;
+        let mut batch = self.storage.batch();
+        let mut changes = ChangeSet::default();
 
-        // Apply updates
         for node in &update.nodes {
-            self.storage.put_node(node)?;
+            self.stage_node(&mut batch, node, &mut changes)?;
         }
 
         for edge in &update.edges {
-            self.storage.put_edge(edge)?;
+            self.stage_edge(&mut batch, edge, &mut changes)?;
         }
 
         for node_id in &update.deleted_nodes {
-            self.storage.delete_node(node_id)?;
+            self.stage_node_delete(&mut batch, node_id, &mut changes)?;
         }
 
         for edge_id in &update.deleted_edges {
-            self.storage.delete_edge(edge_id)?;
+            self.stage_edge_delete(&mut batch, edge_id, &mut changes)?;
         }
 
-        *version = version.next();
-        Ok(*version)
+        self.enforce_integrity(&mut batch, &update.deleted_nodes, &mut changes)?;
+        self.validate_schema(&batch, &changes)?;
+
+        let next = version.next();
+        self.commit(batch, next, &changes)?;
+        *version = next;
+        Ok(next)
+    }
+
+    async fn metadata(&self) -> Result<GraphMetadata> {
+        // Read under the version lock so the version is never one an update is still committing
+        let _version = self.version.read().await;
+        self.storage.metadata()
+    }
+
+    async fn subscribe(&self, pattern: &GraphPattern) -> Result<Subscription> {
+        Ok(self.subscriptions.subscribe(pattern))
     }
 
-    async fn subscribe(&self, _pattern: &GraphPattern) -> Result<tokio::sync::mpsc::Receiver<GraphUpdate>> {
-        // Simple implementation - return empty channel
-        let (tx, rx) = tokio::sync::mpsc::channel(100);
-        drop(tx); // Close sender immediately
-        Ok(rx)
+    async fn unsubscribe(&self, id: &uuid::Uuid) -> Result<bool> {
+        Ok(self.subscriptions.unsubscribe(id))
     }
 
     async fn checkpoint(&self) -> Result<Checkpoint> {
-        let version = *self.version.read().await;
-        Ok(Checkpoint {
-            id: uuid::Uuid::new_v4(),
-            version,
+        // Holding the version lock exclusively keeps writers and other checkpoints out while the
+        // tree is brought up to date and the snapshot is written
+        let version = self.version.write().await;
+        let tree = self.storage.merkle_tree()?;
+        let id = uuid::Uuid::new_v4();
+        let snapshot = match &self.snapshot_dir {
+            Some(dir) => {
+                let path = dir.join(id.to_string());
+                self.storage.create_snapshot(&path)?;
+                Some(path)
+            }
+            None => None,
+        };
+        let checkpoint = Checkpoint {
+            id,
+            version: *version,
             timestamp: chrono::Utc::now().timestamp(),
-            hash: vec![], // TODO: compute hash
+            hash: tree.root().to_vec(),
+            snapshot,
+        };
+        self.storage.put_checkpoint(&checkpoint, &tree)?;
+        Ok(checkpoint)
+    }
+
+    async fn list_checkpoints(&self) -> Result<Vec<Checkpoint>> {
+        self.storage.list_checkpoints()
+    }
+
+    async fn verify_checkpoint(&self, id: &uuid::Uuid) -> Result<CheckpointVerification> {
+        let (checkpoint, expected) = self
+            .storage
+            .get_checkpoint(id)?
+            .ok_or(GraphError::CheckpointNotFound(*id))?;
+
+        // Rehash the records themselves, never the cached leaves: the current records when
+        // the checkpoint is at the current version, otherwise the state rebuilt from history
+        let version = self.version.read().await;
+        let actual = if checkpoint.version == *version {
+            MerkleTree::from_records(self.storage.iter_nodes(), self.storage.iter_edges())?
+        } else {
+            MerkleTree::from_records(
+                self.storage.nodes_as_of(checkpoint.version)?.into_iter().map(Ok),
+                self.storage.edges_as_of(checkpoint.version)?.into_iter().map(Ok),
+            )?
+        };
+        drop(version);
+
+        let actual_hash = actual.root().to_vec();
+        Ok(CheckpointVerification {
+            valid: actual_hash == checkpoint.hash,
+            mismatched_buckets: expected.diff(&actual),
+            actual_hash,
+            checkpoint,
         })
     }
 
+    async fn list_snapshots(&self) -> Result<Vec<Checkpoint>> {
+        Ok(self
+            .storage
+            .list_checkpoints()?
+            .into_iter()
+            .filter(|checkpoint| checkpoint.snapshot.is_some())
+            .collect())
+    }
+
+    async fn restore_snapshot(&self, id: &uuid::Uuid) -> Result<VersionId> {
+        let (checkpoint, _) = self
+            .storage
+            .get_checkpoint(id)?
+            .ok_or(GraphError::CheckpointNotFound(*id))?;
+        let path = checkpoint.snapshot.ok_or(GraphError::SnapshotNotFound(*id))?;
+        let snapshot = self.storage.open_snapshot(&path)
+            .with_context(|| format!("Failed to open snapshot at {}", path.display()))?;
+
+        let mut version = self.version.write().await;
+        self.ensure_vector_index()?;
+        let mut batch = self.storage.batch();
+        let mut changes = ChangeSet::default();
+
+        // Records are written back exactly as they were, versions included, so clients that
+        // read them before the snapshot can write again and the graph hashes to the checkpoint
+        for node in snapshot.iter_nodes() {
+            let node = node?;
+            let old = batch.get_node(&node.id)?;
+            if old.as_ref().map(hash_node) != Some(hash_node(&node)) {
+                batch.put_node(&node)?;
+                changes.nodes.push((old, node));
+            }
+        }
+        for edge in snapshot.iter_edges() {
+            let edge = edge?;
+            let old = batch.get_edge(&edge.id)?;
+            if old.as_ref().map(hash_edge) != Some(hash_edge(&edge)) {
+                batch.put_edge(&edge)?;
+                changes.edges.push((old, edge));
+            }
+        }
+        for edge in self.storage.iter_edges() {
+            let edge = edge?;
+            if snapshot.get_edge(&edge.id)?.is_none() {
+                self.stage_edge_delete(&mut batch, &edge.id, &mut changes)?;
+            }
+        }
+        for node in self.storage.iter_nodes() {
+            let node = node?;
+            if snapshot.get_node(&node.id)?.is_none() {
+                self.stage_node_delete(&mut batch, &node.id, &mut changes)?;
+            }
+        }
+
+        let next = version.next();
+        self.commit(batch, next, &changes)?;
+        *version = next;
+
+        if self.storage.merkle_tree()?.root().as_slice() != checkpoint.hash.as_slice() {
+            tracing::warn!("Graph restored from snapshot {} does not match the checkpoint hash", id);
+        }
+        Ok(next)
+    }
+
+    async fn prune_snapshots(&self, keep: usize) -> Result<Vec<Checkpoint>> {
+        let snapshots = self.list_snapshots().await?;
+        let excess = snapshots.len().saturating_sub(keep);
+        let mut pruned = Vec::new();
+        for mut checkpoint in snapshots.into_iter().take(excess) {
+            if let Some(path) = checkpoint.snapshot.take() {
+                match std::fs::remove_dir_all(&path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if let Some((_, tree)) = self.storage.get_checkpoint(&checkpoint.id)? {
+                self.storage.put_checkpoint(&checkpoint, &tree)?;
+            }
+            pruned.push(checkpoint);
+        }
+        Ok(pruned)
+    }
+
     async fn get_node(&self, id: &NodeId) -> Result<Option<Entity>> {
         Ok(self.storage.get_node(id)?)
     }
 
-    async fn put_node(&self, mut entity: Entity) -> Result<()> {
+    async fn put_node(&self, mut entity: Entity) -> Result<Entity> {
         entity.updated_at = chrono::Utc::now().timestamp();
         if entity.created_at == 0 {
             entity.created_at = entity.updated_at;
         }
-        self.storage.put_node(&entity)?;
-        Ok(())
+        let id = entity.id.clone();
+        self.update(&GraphUpdate {
+            nodes: vec![entity],
+            edges: vec![],
+            deleted_nodes: vec![],
+            deleted_edges: vec![],
+        })
+        .await?;
+        self.storage
+            .get_node(&id)?
+            .ok_or_else(|| anyhow::anyhow!("Node {} vanished after write", id.0))
     }
 
     async fn delete_node(&self, id: &NodeId) -> Result<()> {
-        self.storage.delete_node(id)?;
+        self.update(&GraphUpdate {
+            nodes: vec![],
+            edges: vec![],
+            deleted_nodes: vec![id.clone()],
+            deleted_edges: vec![],
+        })
+        .await?;
         Ok(())
     }
 
@@ -182,14 +1105,1494 @@ impl GraphEngine for DefaultGraphEngine {
         Ok(self.storage.get_edge(id)?)
     }
 
-    async fn put_edge(&self, edge: Edge) -> Result<()> {
-        self.storage.put_edge(&edge)?;
-        Ok(())
+    async fn put_edge(&self, edge: Edge) -> Result<Edge> {
+        let id = edge.id;
+        self.update(&GraphUpdate {
+            nodes: vec![],
+            edges: vec![edge],
+            deleted_nodes: vec![],
+            deleted_edges: vec![],
+        })
+        .await?;
+        self.storage
+            .get_edge(&id)?
+            .ok_or_else(|| anyhow::anyhow!("Edge {} vanished after write", id))
     }
 
     async fn delete_edge(&self, id: &uuid::Uuid) -> Result<()> {
-        self.storage.delete_edge(id)?;
+        self.update(&GraphUpdate {
+            nodes: vec![],
+            edges: vec![],
+            deleted_nodes: vec![],
+            deleted_edges: vec![*id],
+        })
+        .await?;
         Ok(())
     }
+
+    async fn neighbors(
+        &self,
+        node: &NodeId,
+        direction: Direction,
+        label: Option<&str>,
+    ) -> Result<Vec<Entity>> {
+        let mut seen = HashSet::new();
+        let mut nodes = Vec::new();
+        for adjacency in self.storage.adjacent(node, direction, label)? {
+            if !seen.insert(adjacency.neighbor.clone()) {
+                continue;
+            }
+            if let Some(entity) = self.storage.get_node(&adjacency.neighbor)? {
+                nodes.push(entity);
+            }
+        }
+        Ok(nodes)
+    }
+
+    async fn edges_of(&self, node: &NodeId) -> Result<Vec<Edge>> {
+        let mut seen = HashSet::new();
+        let mut edges = Vec::new();
+        for adjacency in self.storage.adjacent(node, Direction::Both, None)? {
+            // Self-loops show up in both directions
+            if !seen.insert(adjacency.edge_id) {
+                continue;
+            }
+            if let Some(edge) = self.storage.get_edge(&adjacency.edge_id)? {
+                edges.push(edge);
+            }
+        }
+        Ok(edges)
+    }
+
+    async fn list_edges(&self, graphs: &[String], limit: usize, cursor: Option<&str>) -> Result<EdgePage> {
+        let edges: Box<dyn Iterator<Item = Result<Edge>> + '_> = match cursor {
+            Some(cursor) => Box::new(self.storage.iter_edges_after(&decode_edge_cursor(cursor)?)),
+            None => Box::new(self.storage.iter_edges()),
+        };
+
+        // Read one edge past the page to tell whether another page follows
+        let mut page = Vec::new();
+        for edge in edges {
+            let edge = edge?;
+            if graphs.is_empty() || graphs.iter().any(|graph| graph == graph_of(edge.graph.as_deref())) {
+                page.push(edge);
+                if page.len() > limit {
+                    break;
+                }
+            }
+        }
+
+        let next_cursor = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|edge| encode_edge_cursor(&edge.id))
+        } else {
+            None
+        };
+        Ok(EdgePage { edges: page, next_cursor })
+    }
+
+    async fn expand(&self, query: &ExpandQuery) -> Result<Neighborhood> {
+        Traversal::new(&self.storage).expand(query)
+    }
+
+    async fn find_paths(&self, query: &PathQuery) -> Result<Vec<GraphPath>> {
+        let starts = match (&query.start, &query.start_filter) {
+            (Some(start), _) => vec![start.clone()],
+            (None, Some(filter)) => {
+                let pattern = GraphPattern {
+                    filter: Some(filter.clone()),
+                    ..Default::default()
+                };
+                self.query(&pattern).await?.nodes.into_iter().map(|n| n.id).collect()
+            }
+            (None, None) => {
+                return Err(anyhow::anyhow!("Path query needs a start node or a start filter"))
+            }
+        };
+        Traversal::new(&self.storage).find_paths(&starts, query)
+    }
+
+    async fn shortest_path(&self, query: &ShortestPathQuery) -> Result<Option<GraphPath>> {
+        Traversal::new(&self.storage).shortest_path(query)
+    }
+
+    async fn sparql(&self, query: &str) -> Result<SparqlResponse> {
+        let projection = match self.projection() {
+            Some(projection) => projection,
+            None => {
+                let mut guard = self.rdf.write().expect("rdf projection lock poisoned");
+                if guard.is_none() {
+                    *guard = Some(Arc::new(RdfProjection::load(&self.storage)?));
+                }
+                guard.clone().expect("projection was just loaded")
+            }
+        };
+        projection.query(query)
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let terms = query_terms(query);
+        let (documents, length) = self.storage.text_stats()?;
+        if terms.is_empty() || documents == 0 {
+            return Ok(Vec::new());
+        }
+        let average_length = length as f64 / documents as f64;
+
+        let mut scores: HashMap<NodeId, f64> = HashMap::new();
+        for term in &terms {
+            let postings = self.storage.text_postings(term)?;
+            let frequency = postings.len();
+            for (id, term_frequency, node_length) in postings {
+                *scores.entry(id).or_insert(0.0) +=
+                    bm25(term_frequency, frequency, documents, node_length, average_length);
+            }
+        }
+
+        let mut ranked: Vec<(NodeId, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0 .0.cmp(&b.0 .0)));
+
+        let terms: HashSet<String> = terms.into_iter().collect();
+        let mut hits = Vec::new();
+        for (id, score) in ranked.into_iter().take(limit) {
+            if let Some(node) = self.storage.get_node(&id)? {
+                let snippet = snippet(&node, &terms);
+                hits.push(SearchHit { node, score, snippet });
+            }
+        }
+        Ok(hits)
+    }
+
+    async fn timeline(&self, query: &TimeQuery) -> Result<Vec<TimelineEvent>> {
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut events = Vec::new();
+        for entry in self.storage.time_entries(query.from, query.to, query.newest_first) {
+            if events.len() >= limit {
+                break;
+            }
+            let (timestamp, field, id) = entry?;
+            if !query.includes_field(&field) {
+                continue;
+            }
+            if let Some(node) = self.storage.get_node(&id)? {
+                events.push(TimelineEvent { timestamp, field, node });
+            }
+        }
+        Ok(events)
+    }
+
+    async fn time_histogram(&self, query: &TimeQuery, bucket: BucketSize, utc_offset: i64) -> Result<Vec<HistogramBucket>> {
+        let mut timestamps = Vec::new();
+        for entry in self.storage.time_entries(query.from, query.to, false) {
+            let (timestamp, field, _) = entry?;
+            if query.includes_field(&field) {
+                timestamps.push(timestamp);
+            }
+        }
+        Ok(histogram(timestamps, bucket, utc_offset))
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(normalize(self.embedder.embed(text)?))
+    }
+
+    async fn nearest(&self, vector: &[f32], k: usize, filter: Option<&FilterExpr>) -> Result<Vec<VectorHit>> {
+        if vector.len() != self.embedder.dimensions() {
+            return Err(GraphError::DimensionMismatch {
+                expected: self.embedder.dimensions(),
+                actual: vector.len(),
+            }
+            .into());
+        }
+        let k = k.min(MAX_VECTOR_HITS);
+        if !self.vectors_ready.load(Ordering::Acquire) {
+            let _version = self.version.write().await;
+            self.ensure_vector_index()?;
+        }
+        let query = normalize(vector.to_vec());
+        let _version = self.version.read().await;
+
+        // A filter can reject most candidates; widen the search until enough pass
+        let mut ef = HNSW_EF_SEARCH.max(k);
+        loop {
+            let found = hnsw_search(self.storage.as_ref(), &query, ef)?;
+            let exhausted = found.len() < ef;
+            let mut hits = Vec::new();
+            for (id, distance) in found {
+                let Some(node) = self.storage.get_node(&NodeId::from_uuid(id))? else {
+                    continue;
+                };
+                if filter.is_none_or(|filter| filter.matches(&node)) {
+                    hits.push(VectorHit { node, similarity: 1.0 - distance });
+                    if hits.len() == k {
+                        break;
+                    }
+                }
+            }
+            if hits.len() >= k || exhausted {
+                return Ok(hits);
+            }
+            ef *= 4;
+        }
+    }
+
+    async fn similar_to(&self, id: &NodeId, k: usize, filter: Option<&FilterExpr>) -> Result<Vec<VectorHit>> {
+        if !self.vectors_ready.load(Ordering::Acquire) {
+            let _version = self.version.write().await;
+            self.ensure_vector_index()?;
+        }
+        let Some(node) = self.storage.vector_node(&id.0)? else {
+            return Ok(Vec::new());
+        };
+        let k = k.min(MAX_VECTOR_HITS);
+        let mut hits = self.nearest(&node.vector, k.saturating_add(1), filter).await?;
+        hits.retain(|hit| hit.node.id != *id);
+        hits.truncate(k);
+        Ok(hits)
+    }
+
+    async fn node_history(&self, id: &NodeId) -> Result<Vec<NodeRevision>> {
+        self.storage.node_history(id)
+    }
+
+    async fn edge_history(&self, id: &uuid::Uuid) -> Result<Vec<EdgeRevision>> {
+        self.storage.edge_history(id)
+    }
+
+    async fn get_node_as_of(&self, id: &NodeId, at: AsOf) -> Result<Option<Entity>> {
+        match self.resolve_as_of(at)? {
+            Some(version) => self.storage.node_as_of(id, version),
+            None => Ok(None),
+        }
+    }
+
+    async fn query_as_of(&self, pattern: &GraphPattern, at: AsOf) -> Result<QueryResult> {
+        let version = match self.resolve_as_of(at)? {
+            Some(version) => version,
+            None => {
+                return Ok(QueryResult {
+                    nodes: vec![],
+                    edges: vec![],
+                    next_cursor: None,
+                })
+            }
+        };
+
+        let expr = pattern.node_expr();
+        let nodes: Vec<Entity> = self
+            .storage
+            .nodes_as_of(version)?
+            .into_iter()
+            .filter(|node| expr.matches(node))
+            .collect();
+        let (nodes, next_cursor) = pattern.paginate(nodes)?;
+        let edges = self
+            .storage
+            .edges_as_of(version)?
+            .into_iter()
+            .filter(|edge| pattern.edge_matches(edge))
+            .collect();
+
+        Ok(QueryResult {
+            nodes,
+            edges,
+            next_cursor,
+        })
+    }
+
+    async fn restore_node(&self, id: &NodeId, version: VersionId) -> Result<Entity> {
+        let mut entity = self.storage.node_as_of(id, version)?.ok_or_else(|| {
+            anyhow::anyhow!("Node {} did not exist at version {}", id.0, version.0)
+        })?;
+        // Written on top of whatever is stored now; a concurrent write still conflicts
+        if let Some(current) = self.storage.get_node(id)? {
+            entity.version = current.version;
+        }
+        self.put_node(entity).await
+    }
+
+    async fn schemas(&self) -> Result<Vec<EntityType>> {
+        Ok(self.schema_registry()?.types().cloned().collect())
+    }
+
+    async fn put_schema(&self, schema: EntityType) -> Result<EntityType> {
+        let mut node = schema_node(&schema)?;
+        if let Some(current) = self.storage.get_node(&node.id)? {
+            node.version = current.version;
+            node.created_at = current.created_at;
+        }
+        self.put_node(node).await?;
+        Ok(schema)
+    }
+
+    async fn delete_schema(&self, name: &str) -> Result<bool> {
+        let id = schema_node_id(name);
+        if self.storage.get_node(&id)?.is_none() {
+            return Ok(false);
+        }
+        self.delete_node(&id).await?;
+        Ok(true)
+    }
+
+    async fn schema_violations(&self) -> Result<Vec<SchemaViolation>> {
+        let registry = self.schema_registry()?;
+        let reject_unknown = self.integrity.schema_validation == SchemaValidation::Strict;
+        let mut violations = Vec::new();
+        for node in self.storage.iter_nodes() {
+            let node = node?;
+            if let Err(message) = registry.validate_node(&node, reject_unknown) {
+                violations.push(SchemaViolation {
+                    node: node.id.0,
+                    edge: None,
+                    message,
+                });
+            }
+        }
+        for edge in self.storage.iter_edges() {
+            let edge = edge?;
+            if let (Some(from), Some(to)) = (self.storage.get_node(&edge.from)?, self.storage.get_node(&edge.to)?) {
+                if let Err(message) = registry.validate_edge(&edge, &from, &to) {
+                    violations.push(SchemaViolation {
+                        node: edge.from.0,
+                        edge: Some(edge.id),
+                        message,
+                    });
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    async fn dangling_edges(&self) -> Result<Vec<Edge>> {
+        self.find_dangling_edges()
+    }
+
+    async fn repair_dangling_edges(&self) -> Result<Vec<Edge>> {
+        let mut version = self.version.write().await;
+        self.ensure_vector_index()?;
+        let dangling = self.find_dangling_edges()?;
+        if dangling.is_empty() {
+            return Ok(dangling);
+        }
+
+        let mut batch = self.storage.batch();
+        let mut changes = ChangeSet::default();
+        for edge in &dangling {
+            self.stage_edge_delete(&mut batch, &edge.id, &mut changes)?;
+        }
+
+        let next = version.next();
+        self.commit(batch, next, &changes)?;
+        *version = next;
+        tracing::info!("Deleted {} dangling edges", dangling.len());
+        Ok(dangling)
+    }
+
+    async fn graphs(&self) -> Result<Vec<GraphInfo>> {
+        Ok(self
+            .storage
+            .graph_counts()?
+            .into_iter()
+            .map(|(name, nodes, edges)| GraphInfo { name, nodes, edges })
+            .collect())
+    }
+
+    async fn transfer(&self, transfer: &GraphTransfer) -> Result<TransferResult> {
+        validate_graph_name(&transfer.from)?;
+        validate_graph_name(&transfer.to)?;
+        let pattern = GraphPattern {
+            filter: transfer.filter.clone(),
+            graphs: vec![transfer.from.clone()],
+            ..Default::default()
+        };
+        // Read outside the write lock; the versions carried over make the update fail
+        // instead of overwriting anything that changed in between
+        let selection = self.query(&pattern).await?;
+        let selected: HashSet<NodeId> = selection.nodes.iter().map(|node| node.id.clone()).collect();
+        let edges: Vec<Edge> = selection
+            .edges
+            .into_iter()
+            .filter(|edge| selected.contains(&edge.from) && selected.contains(&edge.to))
+            .collect();
+
+        let mut copies = HashMap::new();
+        let update = match transfer.mode {
+            TransferMode::Move => GraphUpdate {
+                nodes: selection
+                    .nodes
+                    .into_iter()
+                    .map(|mut node| {
+                        node.graph = graph_field(&transfer.to);
+                        node
+                    })
+                    .collect(),
+                edges: edges
+                    .iter()
+                    .cloned()
+                    .map(|mut edge| {
+                        edge.graph = graph_field(&transfer.to);
+                        edge
+                    })
+                    .collect(),
+                deleted_nodes: vec![],
+                deleted_edges: vec![],
+            },
+            TransferMode::Copy => {
+                let now = chrono::Utc::now().timestamp();
+                copies = selected.iter().map(|id| (id.clone(), NodeId::new())).collect();
+                GraphUpdate {
+                    nodes: selection
+                        .nodes
+                        .iter()
+                        .map(|node| copy_node(node, copies[&node.id].clone(), &transfer.to, &copies, now))
+                        .collect(),
+                    edges: edges.iter().map(|edge| copy_edge(edge, &transfer.to, &copies, now)).collect(),
+                    deleted_nodes: vec![],
+                    deleted_edges: vec![],
+                }
+            }
+        };
+
+        if !update.nodes.is_empty() {
+            self.update(&update).await?;
+        }
+        Ok(TransferResult {
+            nodes: update.nodes.len(),
+            edges: update.edges.len(),
+            copies,
+        })
+    }
+
+    async fn delete_graph(&self, name: &str) -> Result<usize> {
+        validate_graph_name(name)?;
+        let update = GraphUpdate {
+            nodes: vec![],
+            edges: vec![],
+            deleted_nodes: self.storage.graph_nodes(name)?,
+            deleted_edges: self.storage.graph_edges(name)?,
+        };
+        if !update.deleted_nodes.is_empty() || !update.deleted_edges.is_empty() {
+            self.update(&update).await?;
+        }
+        Ok(update.deleted_nodes.len())
+    }
+
+    async fn export_akf(&self, writer: Box<dyn Write + Send>, history: bool) -> Result<AkfSummary> {
+        // Holding off writers keeps the archive at a single version
+        let _version = self.version.read().await;
+        let header = AkfHeader {
+            created_at: chrono::Utc::now().timestamp(),
+            source: self.storage.metadata()?,
+            history,
+        };
+        let mut archive = AkfWriter::new(writer, header)?;
+
+        // Schemas first, so that an import can validate the nodes after them
+        let schema_ids = self
+            .storage
+            .find_by_property(TYPE_PROPERTY, FilterOperator::Equals, &PropertyValue::String(SCHEMA_TYPE.to_string()))?
+            .unwrap_or_default();
+        for id in schema_ids {
+            if let Some(node) = self.storage.get_node(&id)?.filter(is_schema_node) {
+                archive.write(&AkfRecord::Schema(parse_schema_node(&node)?))?;
+            }
+        }
+        for node in self.storage.iter_nodes() {
+            let node = node?;
+            if !is_schema_node(&node) {
+                archive.write(&AkfRecord::Node(node))?;
+            }
+        }
+        for edge in self.storage.iter_edges() {
+            archive.write(&AkfRecord::Edge(edge?))?;
+        }
+
+        if history {
+            self.storage
+                .for_each_node_history(|id, revisions| archive.write(&AkfRecord::NodeHistory { id, revisions }))?;
+            self.storage
+                .for_each_edge_history(|id, revisions| archive.write(&AkfRecord::EdgeHistory { id, revisions }))?;
+            for entry in self.storage.revision_log() {
+                archive.write(&AkfRecord::Log(entry?))?;
+            }
+        }
+        archive.finish()
+    }
+
+    async fn import_akf(&self, reader: Box<dyn Read + Send>) -> Result<AkfSummary> {
+        let mut archive = AkfReader::open(reader)?;
+        let mut version = self.version.write().await;
+        self.ensure_vector_index()?;
+        if archive.header().history && self.storage.latest_version()?.is_none() {
+            *version = self.restore_archive(&mut archive)?;
+        } else {
+            self.merge_archive(&mut archive, &mut version)?;
+        }
+        Ok(archive.summary().clone())
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::StorageBackend;
+    use std::collections::HashMap;
+
+    fn open_engine() -> DefaultGraphEngine {
+        DefaultGraphEngine::new(GraphStorage::in_memory().unwrap())
+    }
+
+    /// A fresh path under the temp dir, removed with everything below it when dropped.
+    /// Declare it before the engine using it, so the engine is closed first.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(prefix: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn note(label: &str) -> Entity {
+        Entity {
+            id: NodeId::new(),
+            label: label.to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_version_conflict_rejects_whole_update() {
+        let engine = open_engine();
+        let stored = engine.put_node(note("draft")).await.unwrap();
+        assert_eq!(stored.version, 1);
+
+        let mut edited = stored.clone();
+        edited.label = "first edit".to_string();
+        assert_eq!(engine.put_node(edited).await.unwrap().version, 2);
+
+        // A second writer still holding version 1 must not overwrite the first edit,
+        // and nothing else in its update may be applied
+        let mut stale = stored.clone();
+        stale.label = "second edit".to_string();
+        let other = note("other");
+        let err = engine
+            .update(&GraphUpdate {
+                nodes: vec![other.clone(), stale],
+                edges: vec![],
+                deleted_nodes: vec![],
+                deleted_edges: vec![],
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GraphError>(),
+            Some(GraphError::VersionConflict { expected: 1, actual: 2, .. })
+        ));
+        assert!(engine.get_node(&other.id).await.unwrap().is_none());
+        assert_eq!(engine.get_node(&stored.id).await.unwrap().unwrap().label, "first edit");
+    }
+
+    #[tokio::test]
+    async fn test_history_as_of_and_restore() {
+        let engine = open_engine();
+        let created = engine.put_node(note("v1")).await.unwrap();
+        let first = *engine.version.read().await;
+
+        let mut edited = created.clone();
+        edited.label = "v2".to_string();
+        engine.put_node(edited).await.unwrap();
+        engine.delete_node(&created.id).await.unwrap();
+
+        let history = engine.node_history(&created.id).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history[2].value.is_none());
+        assert!(engine.get_node(&created.id).await.unwrap().is_none());
+
+        let old = engine
+            .get_node_as_of(&created.id, AsOf::Version(first))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(old.label, "v1");
+        let past = engine
+            .query_as_of(&GraphPattern::default(), AsOf::Version(first))
+            .await
+            .unwrap();
+        assert_eq!(past.nodes.len(), 1);
+
+        let restored = engine.restore_node(&created.id, first).await.unwrap();
+        assert_eq!(restored.label, "v1");
+        assert_eq!(engine.node_history(&created.id).await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_detects_tampering() {
+        let engine = open_engine();
+        let mut nodes = Vec::new();
+        for i in 0..20 {
+            nodes.push(engine.put_node(note(&format!("note {}", i))).await.unwrap());
+        }
+        engine.delete_node(&nodes[0].id).await.unwrap();
+
+        let checkpoint = engine.checkpoint().await.unwrap();
+        let verification = engine.verify_checkpoint(&checkpoint.id).await.unwrap();
+        assert!(verification.valid);
+        assert!(verification.mismatched_buckets.is_empty());
+
+        // Later writes don't invalidate an older checkpoint: it is checked against history
+        let mut edited = nodes[1].clone();
+        edited.label = "edited".to_string();
+        engine.put_node(edited).await.unwrap();
+        assert!(engine.verify_checkpoint(&checkpoint.id).await.unwrap().valid);
+
+        // A record rewritten behind the engine's back does
+        let latest = engine.checkpoint().await.unwrap();
+        let mut tampered = engine.get_node(&nodes[2].id).await.unwrap().unwrap();
+        tampered.label = "tampered".to_string();
+        engine.storage.put_node(&tampered).unwrap();
+        let verification = engine.verify_checkpoint(&latest.id).await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.mismatched_buckets, vec![nodes[2].id.0.as_bytes()[0]]);
+    }
+
+    #[tokio::test]
+    async fn test_restore_and_prune_snapshots() {
+        let (path, snapshots) = (TempPath::new("athena-engine"), TempPath::new("athena-snapshots"));
+        let dir = &snapshots.0;
+        let engine = DefaultGraphEngine::new(GraphStorage::open(&path.0).unwrap()).with_snapshot_dir(dir);
+        let kept = engine.put_node(note("kept")).await.unwrap();
+        let edited = engine.put_node(note("original")).await.unwrap();
+        let checkpoint = engine.checkpoint().await.unwrap();
+        assert!(checkpoint.snapshot.as_ref().unwrap().starts_with(dir));
+
+        // A "bulk import" to roll back: an edit, a delete and a new node
+        let mut edit = edited.clone();
+        edit.label = "imported".to_string();
+        engine.put_node(edit).await.unwrap();
+        engine.delete_node(&kept.id).await.unwrap();
+        let added = engine.put_node(note("added")).await.unwrap();
+
+        let version = engine.restore_snapshot(&checkpoint.id).await.unwrap();
+        assert_eq!(engine.get_node(&kept.id).await.unwrap().unwrap().label, "kept");
+        let restored = engine.get_node(&edited.id).await.unwrap().unwrap();
+        assert_eq!((restored.label.as_str(), restored.version), ("original", edited.version));
+        assert!(engine.get_node(&added.id).await.unwrap().is_none());
+        assert_eq!(engine.storage.merkle_tree().unwrap().root().to_vec(), checkpoint.hash);
+        // The rollback is just another version; the import stays in history
+        assert_eq!(engine.node_history(&edited.id).await.unwrap().last().unwrap().version, version);
+
+        engine.checkpoint().await.unwrap();
+        let pruned = engine.prune_snapshots(1).await.unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].id, checkpoint.id);
+        assert!(!checkpoint.snapshot.unwrap().exists());
+        assert_eq!(engine.list_snapshots().await.unwrap().len(), 1);
+        assert_eq!(engine.list_checkpoints().await.unwrap().len(), 2);
+        let err = engine.restore_snapshot(&checkpoint.id).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::SnapshotNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_version_survives_reopen() {
+        let path = TempPath::new("athena-engine");
+        let engine = DefaultGraphEngine::new(GraphStorage::open(&path.0).unwrap());
+        let created = engine.metadata().await.unwrap();
+        engine.put_node(note("first")).await.unwrap();
+        let version = engine
+            .update(&GraphUpdate {
+                nodes: vec![note("second")],
+                edges: vec![],
+                deleted_nodes: vec![],
+                deleted_edges: vec![],
+            })
+            .await
+            .unwrap();
+        drop(engine);
+
+        let engine = DefaultGraphEngine::new(GraphStorage::open(&path.0).unwrap());
+        let metadata = engine.metadata().await.unwrap();
+        assert_eq!(metadata.version, version);
+        assert_eq!(metadata.instance_id, created.instance_id);
+        assert_eq!(metadata.created_at, created.created_at);
+        engine.put_node(note("third")).await.unwrap();
+        assert_eq!(engine.metadata().await.unwrap().version, version.next());
+    }
+
+    fn link(from: &Entity, to: &Entity) -> Edge {
+        Edge {
+            id: uuid::Uuid::new_v4(),
+            from: from.id.clone(),
+            to: to.id.clone(),
+            label: "link".to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_integrity_policies() {
+        let engine = open_engine();
+        let a = engine.put_node(note("a")).await.unwrap();
+        let b = engine.put_node(note("b")).await.unwrap();
+        let ghost = note("never stored");
+
+        let err = engine.put_edge(link(&a, &ghost)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::DanglingEdge { .. })));
+
+        // Cascade (the default) takes the edges along with the node
+        let edge = engine.put_edge(link(&a, &b)).await.unwrap();
+        engine.delete_node(&b.id).await.unwrap();
+        assert!(engine.get_edge(&edge.id).await.unwrap().is_none());
+
+        // Restrict refuses, unless the edges go in the same update
+        let engine = engine.with_integrity(IntegrityPolicy {
+            on_node_delete: NodeDeletePolicy::Restrict,
+            ..Default::default()
+        });
+        let c = engine.put_node(note("c")).await.unwrap();
+        let edge = engine.put_edge(link(&a, &c)).await.unwrap();
+        let err = engine.delete_node(&c.id).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::NodeHasEdges { edges: 1, .. })));
+        engine
+            .update(&GraphUpdate {
+                nodes: vec![],
+                edges: vec![],
+                deleted_nodes: vec![c.id.clone()],
+                deleted_edges: vec![edge.id],
+            })
+            .await
+            .unwrap();
+
+        // Orphan leaves them for the repair routine
+        let engine = engine.with_integrity(IntegrityPolicy {
+            on_node_delete: NodeDeletePolicy::Orphan,
+            ..Default::default()
+        });
+        let d = engine.put_node(note("d")).await.unwrap();
+        let edge = engine.put_edge(link(&a, &d)).await.unwrap();
+        engine.delete_node(&d.id).await.unwrap();
+        assert_eq!(engine.dangling_edges().await.unwrap().len(), 1);
+        let repaired = engine.repair_dangling_edges().await.unwrap();
+        assert_eq!(repaired[0].id, edge.id);
+        assert!(engine.dangling_edges().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_traversal() {
+        use crate::traversal::PathStep;
+
+        // a -> b -> c -> a is a cycle; b -cites-> d hangs off it
+        let engine = open_engine();
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(note);
+        for node in [&a, &b, &c, &d] {
+            engine.put_node(node.clone()).await.unwrap();
+        }
+        let mut cites = link(&b, &d);
+        cites.label = "cites".to_string();
+        for edge in [link(&a, &b), link(&b, &c), link(&c, &a), cites] {
+            engine.put_edge(edge).await.unwrap();
+        }
+
+        let expand = |max_depth, direction, edge_labels: &[&str]| ExpandQuery {
+            start: a.id.clone(),
+            max_depth,
+            direction,
+            edge_labels: edge_labels.iter().map(|label| label.to_string()).collect(),
+            limit: 1000,
+        };
+        let labels = |hood: &Neighborhood| -> Vec<(String, usize)> {
+            let mut labels: Vec<_> = hood.nodes.iter().map(|n| (n.entity.label.clone(), n.depth)).collect();
+            labels.sort();
+            labels
+        };
+        let hood = engine.expand(&expand(1, Direction::Outgoing, &[])).await.unwrap();
+        assert_eq!(labels(&hood), vec![("a".to_string(), 0), ("b".to_string(), 1)]);
+        // The cycle back to a adds its edge but never revisits the node
+        let hood = engine.expand(&expand(5, Direction::Outgoing, &[])).await.unwrap();
+        assert_eq!(hood.nodes.len(), 4);
+        assert_eq!(hood.edges.len(), 4);
+        let hood = engine.expand(&expand(5, Direction::Outgoing, &["link"])).await.unwrap();
+        assert_eq!(
+            labels(&hood),
+            vec![("a".to_string(), 0), ("b".to_string(), 1), ("c".to_string(), 2)]
+        );
+        let hood = engine.expand(&expand(1, Direction::Incoming, &[])).await.unwrap();
+        assert_eq!(labels(&hood), vec![("a".to_string(), 0), ("c".to_string(), 1)]);
+
+        let step = |label: &str| PathStep {
+            label: Some(label.to_string()),
+            direction: Direction::Outgoing,
+            filter: None,
+        };
+        let paths = |steps: Vec<PathStep>| PathQuery {
+            start: None,
+            start_filter: Some(FilterExpr::Filter(NodeFilter::new(
+                "label",
+                FilterOperator::Equals,
+                PropertyValue::String("a".to_string()),
+            ))),
+            steps,
+            limit: 10,
+        };
+        let found = engine.find_paths(&paths(vec![step("link"), step("cites")])).await.unwrap();
+        assert_eq!(found.len(), 1);
+        let hops: Vec<&str> = found[0].nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(hops, vec!["a", "b", "d"]);
+        assert_eq!(found[0].edges.len(), 2);
+        // Going round the cycle would revisit a, so there is no simple path
+        let found = engine
+            .find_paths(&paths(vec![step("link"), step("link"), step("link")]))
+            .await
+            .unwrap();
+        assert!(found.is_empty());
+        let mut filtered = step("link");
+        filtered.filter = Some(FilterExpr::Filter(NodeFilter::new(
+            "label",
+            FilterOperator::Equals,
+            PropertyValue::String("c".to_string()),
+        )));
+        assert!(engine.find_paths(&paths(vec![filtered])).await.unwrap().is_empty());
+
+        let shortest = |from: &Entity, to: &Entity, max_depth, direction| ShortestPathQuery {
+            from: from.id.clone(),
+            to: to.id.clone(),
+            max_depth,
+            direction,
+            edge_labels: vec![],
+            max_visited: 1000,
+        };
+        let path = engine
+            .shortest_path(&shortest(&a, &d, 3, Direction::Outgoing))
+            .await
+            .unwrap()
+            .unwrap();
+        let hops: Vec<&str> = path.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(hops, vec!["a", "b", "d"]);
+        assert!(engine
+            .shortest_path(&shortest(&a, &d, 1, Direction::Outgoing))
+            .await
+            .unwrap()
+            .is_none());
+        // d has no outgoing edges, so only an undirected search reaches a
+        assert!(engine
+            .shortest_path(&shortest(&d, &a, 3, Direction::Outgoing))
+            .await
+            .unwrap()
+            .is_none());
+        let path = engine
+            .shortest_path(&shortest(&d, &a, 3, Direction::Both))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.edges.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_and_follows_updates() {
+        let engine = open_engine();
+        let mut email = note("Quarterly report");
+        email.properties.insert(
+            "html_content".to_string(),
+            PropertyValue::String("<p>The <b>report</b> is attached, see the report</p>".to_string()),
+        );
+        let email = engine.put_node(email).await.unwrap();
+        let mut memo = note("Отчёт за квартал");
+        memo.properties.insert(
+            "content".to_string(),
+            PropertyValue::String("Квартальные отчёты и один report".to_string()),
+        );
+        let memo = engine.put_node(memo).await.unwrap();
+        engine.put_node(note("Groceries")).await.unwrap();
+
+        let hits = engine.search("reports", 10).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].node.id, email.id);
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(engine.search("отчетами", 10).await.unwrap()[0].node.id, memo.id);
+
+        // Rewrites and deletes are reflected in the index
+        let mut renamed = engine.get_node(&email.id).await.unwrap().unwrap();
+        renamed.properties.clear();
+        renamed.label = "Invoice".to_string();
+        engine.put_node(renamed).await.unwrap();
+        engine.delete_node(&memo.id).await.unwrap();
+        assert!(engine.search("report", 10).await.unwrap().is_empty());
+        assert_eq!(engine.search("invoice", 10).await.unwrap().len(), 1);
+        assert_eq!(engine.storage.text_stats().unwrap(), (2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_similar_nodes() {
+        let engine = open_engine();
+        let text = |label: &str, content: &str| {
+            let mut node = note(label);
+            node.properties.insert("content".to_string(), PropertyValue::String(content.to_string()));
+            node
+        };
+        let hiking = engine
+            .put_node(text("Trip", "hiking in the mountains, trail maps and tents"))
+            .await
+            .unwrap();
+        let camping = engine
+            .put_node(text("Weekend", "camping trip to the mountains with tents and maps"))
+            .await
+            .unwrap();
+        let taxes = engine
+            .put_node(text("Taxes", "quarterly tax return, invoices and receipts"))
+            .await
+            .unwrap();
+
+        let hits = engine.similar_to(&hiking.id, 2, None).await.unwrap();
+        assert_eq!(hits[0].node.id, camping.id);
+        assert!(hits[0].similarity > hits[1].similarity);
+
+        let query = engine.embed("invoices for the tax return").await.unwrap();
+        assert_eq!(engine.nearest(&query, 1, None).await.unwrap()[0].node.id, taxes.id);
+        let filter = FilterExpr::Filter(NodeFilter::new(
+            "label",
+            FilterOperator::Equals,
+            PropertyValue::String("Weekend".to_string()),
+        ));
+        assert_eq!(engine.nearest(&query, 1, Some(&filter)).await.unwrap()[0].node.id, camping.id);
+
+        engine.delete_node(&camping.id).await.unwrap();
+        let hits = engine.similar_to(&hiking.id, 5, None).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].node.id, taxes.id);
+
+        // `k` comes straight from requests
+        assert_eq!(engine.similar_to(&hiking.id, usize::MAX, None).await.unwrap().len(), 1);
+        let err = engine.nearest(&[1.0, 0.0], 1, None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<GraphError>(), Some(GraphError::DimensionMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_timeline_and_histogram() {
+        let engine = open_engine();
+        let day = 86_400;
+        let mut clipped = Vec::new();
+        for (label, clipped_at) in [("a", 10 * day), ("b", 10 * day + 60), ("c", 12 * day), ("d", 20 * day)] {
+            let mut node = note(label);
+            node.properties.insert("clipped_at".to_string(), PropertyValue::DateTime(clipped_at));
+            clipped.push(engine.put_node(node).await.unwrap());
+        }
+
+        let mut query = TimeQuery::between(10 * day, 13 * day);
+        query.fields = vec!["clipped_at".to_string()];
+        let events = engine.timeline(&query).await.unwrap();
+        let labels: Vec<&str> = events.iter().map(|e| e.node.label.as_str()).collect();
+        assert_eq!(labels, vec!["a", "b", "c"]);
+
+        query.newest_first = true;
+        query.limit = Some(1);
+        assert_eq!(engine.timeline(&query).await.unwrap()[0].node.label, "c");
+
+        let counts: Vec<u64> = engine
+            .time_histogram(&query, BucketSize::Day, 0)
+            .await
+            .unwrap()
+            .iter()
+            .map(|b| b.count)
+            .collect();
+        assert_eq!(counts, vec![2, 0, 1]);
+
+        // Updates move a node in the index rather than adding to it
+        let mut d = clipped[3].clone();
+        d.properties.insert("clipped_at".to_string(), PropertyValue::DateTime(11 * day));
+        engine.put_node(d).await.unwrap();
+        query.newest_first = false;
+        query.limit = None;
+        assert_eq!(engine.timeline(&query).await.unwrap().len(), 4);
+
+        // created_at filters are answered from the time index
+        let after = FilterExpr::Filter(NodeFilter::new(
+            "created_at",
+            FilterOperator::GreaterThanOrEqual,
+            PropertyValue::DateTime(0),
+        ));
+        assert_eq!(engine.indexed_candidates(&after).unwrap().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_geo_filters() {
+        use crate::geo::{BBoxFilter, GeoPoint, RadiusFilter};
+        let engine = open_engine();
+        for (label, lat, lon) in [
+            ("Red Square", 55.7539, 37.6208),
+            ("Gorky Park", 55.7298, 37.6011),
+            ("Hermitage", 59.9398, 30.3146),
+        ] {
+            let mut node = note(label);
+            node.properties.insert("location".to_string(), PropertyValue::GeoPoint(GeoPoint::new(lat, lon)));
+            engine.put_node(node).await.unwrap();
+        }
+        let labels = |result: QueryResult| {
+            let mut labels: Vec<String> = result.nodes.into_iter().map(|n| n.label).collect();
+            labels.sort();
+            labels
+        };
+
+        let near = FilterExpr::Radius(RadiusFilter {
+            property: "location".to_string(),
+            center: GeoPoint::new(55.7520, 37.6175),
+            radius: 5_000.0,
+        });
+        assert!(engine.indexed_candidates(&near).unwrap().unwrap().len() < 3);
+        let pattern = GraphPattern {
+            filter: Some(near),
+            ..Default::default()
+        };
+        assert_eq!(labels(engine.query(&pattern).await.unwrap()), vec!["Gorky Park", "Red Square"]);
+
+        let pattern = GraphPattern {
+            filter: Some(FilterExpr::BBox(BBoxFilter {
+                property: "location".to_string(),
+                min: GeoPoint::new(55.74, 30.0),
+                max: GeoPoint::new(61.0, 38.0),
+            })),
+            ..Default::default()
+        };
+        assert_eq!(labels(engine.query(&pattern).await.unwrap()), vec!["Hermitage", "Red Square"]);
+    }
+
+    #[tokio::test]
+    async fn test_schema_validation() {
+        use crate::schema::{EdgeRule, PropertySchema, PropertyType};
+
+        let engine = open_engine();
+        let typed = |type_name: &str, properties: Vec<(&str, PropertyValue)>| {
+            let mut node = note(type_name);
+            node.properties
+                .insert(TYPE_PROPERTY.to_string(), PropertyValue::String(type_name.to_string()));
+            for (name, value) in properties {
+                node.properties.insert(name.to_string(), value);
+            }
+            node
+        };
+
+        // Built-in types cover the importers' conventions
+        let page = typed("web_page", vec![("url", PropertyValue::String("https://example.org".into()))]);
+        engine.put_node(page).await.unwrap();
+        let error = engine.put_node(typed("web_page", vec![])).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<GraphError>(), Some(GraphError::SchemaViolation { .. })));
+
+        // A stored type inherits from a built-in one and restricts its edges
+        let mut trip = EntityType {
+            name: "Trip".to_string(),
+            parent: Some("Event".to_string()),
+            aliases: vec![],
+            same_as: vec![],
+            description: None,
+            properties: [(
+                "destination".to_string(),
+                PropertySchema {
+                    value_type: PropertyType::String,
+                    required: true,
+                    list: false,
+                },
+            )]
+            .into(),
+            edges: vec![EdgeRule {
+                label: "photo".to_string(),
+                target: "ImageObject".to_string(),
+            }],
+            strict: false,
+        };
+        engine.put_schema(trip.clone()).await.unwrap();
+        assert!(engine.schemas().await.unwrap().iter().any(|t| t.name == "Trip"));
+
+        let rome = engine
+            .put_node(typed(
+                "Trip",
+                vec![
+                    ("destination", PropertyValue::String("Rome".into())),
+                    ("startDate", PropertyValue::DateTime(1_700_000_000)),
+                ],
+            ))
+            .await
+            .unwrap();
+        assert!(engine
+            .put_node(typed("Trip", vec![("startDate", PropertyValue::String("soon".into()))]))
+            .await
+            .is_err());
+        let photo = engine.put_node(typed("photo", vec![])).await.unwrap();
+        let email = engine.put_node(typed("email", vec![])).await.unwrap();
+        let labelled = |to: &Entity| Edge {
+            label: "photo".to_string(),
+            ..link(&rome, to)
+        };
+        engine.put_edge(labelled(&photo)).await.unwrap();
+        assert!(engine.put_edge(labelled(&email)).await.is_err());
+
+        // Schemas must form a valid hierarchy
+        trip.parent = Some("Journey".to_string());
+        assert!(engine.put_schema(trip).await.is_err());
+
+        // Existing data is reported once its type changes
+        let strict = EntityType {
+            name: "Trip".to_string(),
+            parent: None,
+            aliases: vec![],
+            same_as: vec![],
+            description: None,
+            properties: Default::default(),
+            edges: vec![],
+            strict: true,
+        };
+        engine.put_schema(strict).await.unwrap();
+        assert_eq!(engine.schema_violations().await.unwrap().len(), 2);
+        assert!(engine.delete_schema("Trip").await.unwrap());
+        assert!(!engine.delete_schema("Trip").await.unwrap());
+        assert!(engine.schema_violations().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sorted_pages_and_projection() {
+        use crate::query::OrderBy;
+        let engine = open_engine();
+        for (label, rank) in [("a", 3.0), ("b", 1.0), ("c", 2.0), ("d", 2.0), ("e", 5.0)] {
+            let mut node = note(label);
+            node.properties.insert("rank".to_string(), PropertyValue::Number(rank));
+            node.properties.insert("body".to_string(), PropertyValue::String(label.repeat(3)));
+            engine.put_node(node).await.unwrap();
+        }
+        engine.put_node(note("unranked")).await.unwrap();
+
+        let mut pattern = GraphPattern {
+            limit: Some(4),
+            order_by: vec![OrderBy::parse("rank:desc").unwrap()],
+            fields: Some(vec!["rank".to_string()]),
+            ..Default::default()
+        };
+        let mut labels = Vec::new();
+        loop {
+            let page = engine.query(&pattern).await.unwrap();
+            assert!(page.nodes.iter().all(|n| !n.properties.contains_key("body")));
+            labels.extend(page.nodes.into_iter().map(|n| n.label));
+            match page.next_cursor {
+                Some(cursor) => pattern.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        // Ties keep a stable order and nodes without the property come last
+        assert_eq!(labels.len(), 6);
+        assert_eq!(labels[0], "e");
+        assert_eq!(labels[1], "a");
+        assert_eq!(labels[4], "b");
+        assert_eq!(labels[5], "unranked");
+
+        pattern.order_by.clear();
+        assert!(engine.query(&pattern).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unsorted_pages_stream_in_id_order() {
+        let engine = open_engine();
+        let mut nodes = Vec::new();
+        for label in ["a", "b", "c", "d", "e"] {
+            nodes.push(engine.put_node(note(label)).await.unwrap());
+        }
+        let other = engine.put_node(note("other")).await.unwrap();
+        for pair in nodes.windows(2) {
+            engine.put_edge(link(&pair[0], &pair[1])).await.unwrap();
+        }
+        let mut expected: Vec<NodeId> = nodes.iter().map(|node| node.id.clone()).collect();
+        expected.push(other.id.clone());
+        expected.sort_by_key(|id| id.0);
+
+        // Both the full scan and the index-backed path resume after the cursor
+        let filters = [
+            None,
+            Some(FilterExpr::Filter(NodeFilter::new(
+                "label",
+                FilterOperator::StartsWith,
+                PropertyValue::String(String::new()),
+            ))),
+        ];
+        for filter in filters {
+            let mut pattern = GraphPattern {
+                filter,
+                limit: Some(2),
+                ..Default::default()
+            };
+            let mut ids = Vec::new();
+            let mut pages = 0;
+            loop {
+                let page = engine.query(&pattern).await.unwrap();
+                pages += 1;
+                let on_page: HashSet<&NodeId> = page.nodes.iter().map(|node| &node.id).collect();
+                assert!(page
+                    .edges
+                    .iter()
+                    .all(|edge| on_page.contains(&edge.from) || on_page.contains(&edge.to)));
+                ids.extend(page.nodes.iter().map(|node| node.id.clone()));
+                match page.next_cursor {
+                    Some(cursor) => pattern.cursor = Some(cursor),
+                    None => break,
+                }
+            }
+            assert_eq!(ids, expected);
+            assert_eq!(pages, 3);
+        }
+
+        let alone = engine
+            .query(&GraphPattern {
+                node_filters: vec![NodeFilter::new(
+                    "label",
+                    FilterOperator::Equals,
+                    PropertyValue::String("other".to_string()),
+                )],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(alone.nodes.len(), 1);
+        assert!(alone.edges.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_edge_pages() {
+        let engine = open_engine();
+        let a = engine.put_node(note("a")).await.unwrap();
+        let b = engine.put_node(note("b")).await.unwrap();
+        let mut expected = Vec::new();
+        for i in 0..5 {
+            let mut edge = link(&a, &b);
+            if i % 2 == 0 {
+                edge.graph = Some("work".to_string());
+            }
+            expected.push(engine.put_edge(edge).await.unwrap().id);
+        }
+        expected.sort();
+
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = engine.list_edges(&[], 2, cursor.as_deref()).await.unwrap();
+            assert!(page.edges.len() <= 2);
+            ids.extend(page.edges.iter().map(|edge| edge.id));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(ids, expected);
+
+        let work = engine.list_edges(&["work".to_string()], 10, None).await.unwrap();
+        assert_eq!(work.edges.len(), 3);
+        assert!(work.next_cursor.is_none());
+        assert!(engine.list_edges(&[], 10, Some("nonsense")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_by_type() {
+        use crate::aggregate::{AggregateQuery, Facet, GroupBy, Metric, MetricOp};
+        let engine = open_engine();
+        for (label, kind, size) in [("a.pdf", "file", 10.0), ("b.pdf", "file", 30.0), ("inbox", "email", 5.0)] {
+            let mut node = note(label);
+            node.properties.insert("type".to_string(), PropertyValue::String(kind.to_string()));
+            node.properties.insert("size".to_string(), PropertyValue::Number(size));
+            engine.put_node(node).await.unwrap();
+        }
+
+        let query = AggregateQuery {
+            filter: Some(FilterExpr::Filter(NodeFilter::new(
+                "size",
+                FilterOperator::GreaterThan,
+                PropertyValue::Number(1.0),
+            ))),
+            group_by: Some(GroupBy::parse("type").unwrap()),
+            metrics: vec![Metric::count(), Metric::of(MetricOp::Sum, "size")],
+            facets: vec![Facet::parse("label:extension", 5).unwrap()],
+            ..Default::default()
+        };
+        let result = engine.aggregate(&query).await.unwrap();
+        assert_eq!(result.count, 3);
+        assert_eq!(result.metrics["sum(size)"], Some(45.0));
+        assert_eq!(result.groups[0].key.as_deref(), Some("file"));
+        assert_eq!(result.groups[0].metrics["sum(size)"], Some(40.0));
+        assert_eq!(result.groups[1].count, 1);
+        assert_eq!(result.facets[0].values.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_named_graphs() {
+        use crate::named_graph::{GraphTransfer, TransferMode, DEFAULT_GRAPH};
+        let engine = open_engine();
+        let mut nodes = Vec::new();
+        for label in ["plan", "budget", "diary"] {
+            let mut node = note(label);
+            if label == "diary" {
+                // A user property named like the field leaves the node in the default graph
+                node.properties
+                    .insert("graph".to_string(), PropertyValue::String("work".to_string()));
+                node.graph = Some(DEFAULT_GRAPH.to_string());
+            } else {
+                node.graph = Some("work".to_string());
+            }
+            nodes.push(engine.put_node(node).await.unwrap());
+        }
+        assert_eq!(nodes[2].graph, None);
+        assert_eq!(nodes[2].properties["graph"], PropertyValue::String("work".to_string()));
+        let mut edge = link(&nodes[0], &nodes[1]);
+        edge.graph = Some("work".to_string());
+        engine.put_edge(edge).await.unwrap();
+        let mut bad = note("bad");
+        bad.graph = Some("no spaces".to_string());
+        assert!(engine.put_node(bad).await.is_err());
+
+        let scoped = |graph: &str| GraphPattern {
+            graphs: vec![graph.to_string()],
+            ..Default::default()
+        };
+        let work = engine.query(&scoped("work")).await.unwrap();
+        assert_eq!((work.nodes.len(), work.edges.len()), (2, 1));
+        assert_eq!(engine.query(&scoped(DEFAULT_GRAPH)).await.unwrap().nodes[0].label, "diary");
+
+        let copy = GraphTransfer {
+            from: "work".to_string(),
+            to: "alpha".to_string(),
+            filter: None,
+            mode: TransferMode::Copy,
+        };
+        let copied = engine.transfer(&copy).await.unwrap();
+        assert_eq!((copied.nodes, copied.edges), (2, 1));
+        let alpha = engine.query(&scoped("alpha")).await.unwrap();
+        assert_eq!(alpha.edges[0].from, copied.copies[&nodes[0].id]);
+
+        let moved = GraphTransfer {
+            from: "work".to_string(),
+            to: "personal".to_string(),
+            filter: Some(FilterExpr::Filter(NodeFilter::new(
+                "label",
+                FilterOperator::Equals,
+                PropertyValue::String("budget".to_string()),
+            ))),
+            mode: TransferMode::Move,
+        };
+        assert_eq!(engine.transfer(&moved).await.unwrap().nodes, 1);
+        // The edge now crosses graphs and stays where it was
+        let work = engine.query(&scoped("work")).await.unwrap();
+        assert_eq!((work.nodes.len(), work.edges.len()), (1, 1));
+
+        let counts: Vec<(String, u64, u64)> = engine
+            .graphs()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| (g.name, g.nodes, g.edges))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("alpha".to_string(), 2, 1),
+                ("default".to_string(), 1, 0),
+                ("personal".to_string(), 1, 0),
+                ("work".to_string(), 1, 1),
+            ]
+        );
+
+        assert_eq!(engine.delete_graph("alpha").await.unwrap(), 2);
+        assert!(engine.query(&scoped("alpha")).await.unwrap().nodes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_storage_backends() {
+        for backend in [StorageBackend::Memory, StorageBackend::Sled] {
+            let (path, snapshots) = (TempPath::new("athena-engine"), TempPath::new("athena-snapshots"));
+            let mut engine = DefaultGraphEngine::new(GraphStorage::open_with(backend, &path.0).unwrap());
+            if backend != StorageBackend::Memory {
+                engine = engine.with_snapshot_dir(&snapshots.0);
+            }
+            let a = engine.put_node(note("alpha")).await.unwrap();
+            let b = engine.put_node(note("beta")).await.unwrap();
+            engine.put_edge(link(&a, &b)).await.unwrap();
+            let checkpoint = engine.checkpoint().await.unwrap();
+
+            let mut renamed = a.clone();
+            renamed.label = "gamma".to_string();
+            engine.put_node(renamed).await.unwrap();
+            let neighbors = engine.neighbors(&a.id, Direction::Outgoing, None).await.unwrap();
+            assert_eq!(neighbors.len(), 1, "{:?}", backend);
+            assert_eq!(engine.node_history(&a.id).await.unwrap().len(), 2);
+            let pattern = GraphPattern {
+                node_filters: vec![NodeFilter::new(
+                    "label",
+                    FilterOperator::Equals,
+                    PropertyValue::String("gamma".to_string()),
+                )],
+                ..Default::default()
+            };
+            assert_eq!(engine.query(&pattern).await.unwrap().nodes.len(), 1);
+
+            if backend == StorageBackend::Memory {
+                assert!(checkpoint.snapshot.is_none());
+            } else {
+                engine.restore_snapshot(&checkpoint.id).await.unwrap();
+                assert_eq!(engine.get_node(&a.id).await.unwrap().unwrap().label, "alpha");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_akf_export_and_import() {
+        let source = DefaultGraphEngine::new(GraphStorage::in_memory().unwrap());
+        let trip = EntityType {
+            name: "Trip".to_string(),
+            parent: Some("Event".to_string()),
+            aliases: vec![],
+            same_as: vec![],
+            description: None,
+            properties: Default::default(),
+            edges: vec![],
+            strict: false,
+        };
+        source.put_schema(trip.clone()).await.unwrap();
+        let a = source.put_node(note("a")).await.unwrap();
+        let b = source.put_node(note("b")).await.unwrap();
+        let gone = source.put_node(note("gone")).await.unwrap();
+        source.put_edge(link(&a, &b)).await.unwrap();
+        let mut edited = a.clone();
+        edited.label = "a2".to_string();
+        let edited = source.put_node(edited).await.unwrap();
+        source.delete_node(&gone.id).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("athena-{}.akf", uuid::Uuid::new_v4()));
+        let file = std::fs::File::create(&path).unwrap();
+        let exported = source.export_akf(Box::new(file), true).await.unwrap();
+        assert_eq!((exported.schemas, exported.nodes, exported.edges), (1, 2, 1));
+        assert_eq!(exported.node_histories, 4);
+
+        // A fresh database gets the archive as it was, history and versions included
+        let restored = DefaultGraphEngine::new(GraphStorage::in_memory().unwrap());
+        let open = || Box::new(std::fs::File::open(&path).unwrap());
+        assert_eq!(restored.import_akf(open()).await.unwrap(), exported);
+        assert_eq!(restored.metadata().await.unwrap().version, source.metadata().await.unwrap().version);
+        assert_eq!(restored.get_node(&a.id).await.unwrap().unwrap().version, edited.version);
+        assert_eq!(restored.node_history(&a.id).await.unwrap().len(), 2);
+        assert!(restored.get_node(&gone.id).await.unwrap().is_none());
+        assert_eq!(restored.node_history(&gone.id).await.unwrap().len(), 2);
+        assert_eq!(restored.edges_of(&a.id).await.unwrap().len(), 1);
+        assert!(restored.schemas().await.unwrap().contains(&trip));
+
+        // Into a database with history of its own, only the current records are merged in
+        let merged = open_engine();
+        merged.put_node(note("local")).await.unwrap();
+        merged.import_akf(open()).await.unwrap();
+        assert_eq!(merged.get_node(&a.id).await.unwrap().unwrap().label, "a2");
+        assert_eq!(merged.node_history(&a.id).await.unwrap().len(), 1);
+        assert_eq!(merged.query(&GraphPattern::default()).await.unwrap().nodes.len(), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
-use serde::{Deserialize, Serialize};
+use crate::geo::GeoPoint;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -23,10 +24,12 @@ pub struct Entity {
     pub created_at: i64,
     pub updated_at: i64,
     pub version: u64,
+    /// Named graph the node belongs to; `None` is the default graph.
+    #[serde(default)]
+    pub graph: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PropertyValue {
     String(String),
     Number(f64),
@@ -35,6 +38,56 @@ pub enum PropertyValue {
     Reference(NodeId),
     List(Vec<PropertyValue>),
     Map(HashMap<String, PropertyValue>),
+    GeoPoint(GeoPoint),
+}
+
+// Human-readable formats get adjacently tagged values, e.g. `{"type": "String", "value": "email"}`.
+// Binary formats such as bincode cannot decode tagged enums and get the plain variant index.
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PropertyValue", tag = "type", content = "value")]
+enum TaggedValue {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    DateTime(i64),
+    Reference(NodeId),
+    List(Vec<PropertyValue>),
+    Map(HashMap<String, PropertyValue>),
+    GeoPoint(GeoPoint),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PropertyValue")]
+enum CompactValue {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    DateTime(i64),
+    Reference(NodeId),
+    List(Vec<PropertyValue>),
+    Map(HashMap<String, PropertyValue>),
+    GeoPoint(GeoPoint),
+}
+
+impl Serialize for PropertyValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            TaggedValue::serialize(self, serializer)
+        } else {
+            CompactValue::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PropertyValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            TaggedValue::deserialize(deserializer)
+        } else {
+            CompactValue::deserialize(deserializer)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +99,9 @@ pub struct Edge {
     pub properties: HashMap<String, PropertyValue>,
     pub created_at: i64,
     pub version: u64,
+    /// Named graph the edge belongs to; `None` is the default graph.
+    #[serde(default)]
+    pub graph: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,3 +112,27 @@ pub struct GraphUpdate {
     pub deleted_edges: Vec<Uuid>,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_property_value_round_trips() {
+        let value = PropertyValue::List(vec![
+            PropertyValue::String("email".to_string()),
+            PropertyValue::Number(1.5),
+            PropertyValue::Map(HashMap::from([(
+                "ref".to_string(),
+                PropertyValue::Reference(NodeId::new()),
+            )])),
+        ]);
+
+        let json = serde_json::to_value(&value).unwrap();
+        assert_eq!(json["type"], "List");
+        assert_eq!(json["value"][0], serde_json::json!({"type": "String", "value": "email"}));
+        assert_eq!(serde_json::from_value::<PropertyValue>(json).unwrap(), value);
+
+        let bytes = bincode::serialize(&value).unwrap();
+        assert_eq!(bincode::deserialize::<PropertyValue>(&bytes).unwrap(), value);
+    }
+}
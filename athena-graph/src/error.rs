@@ -0,0 +1,51 @@
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum GraphError {
+    /// A write carried a version that no longer matches the stored record, i.e. someone
+    /// else updated it since it was read.
+    #[error("Version conflict on {kind} {id}: expected version {expected}, stored version is {actual}")]
+    VersionConflict {
+        kind: &'static str,
+        id: Uuid,
+        expected: u64,
+        actual: u64,
+    },
+    /// An edge would point at a node that does not exist.
+    #[error("Edge {edge} references missing node {node}")]
+    DanglingEdge { edge: Uuid, node: Uuid },
+    /// A node was deleted while it still had edges and the policy is `Restrict`.
+    #[error("Node {id} still has {edges} edges")]
+    NodeHasEdges { id: Uuid, edges: usize },
+    /// A node or edge does not conform to the schema of its type.
+    #[error("{kind} {id} violates the schema: {message}")]
+    SchemaViolation {
+        kind: &'static str,
+        id: Uuid,
+        message: String,
+    },
+    /// A pagination cursor is malformed or was issued for a different sort order.
+    #[error("Invalid cursor: {0}")]
+    InvalidCursor(String),
+    /// A graph name is empty, too long or contains characters other than ASCII letters,
+    /// digits, `-`, `_` and `.`.
+    #[error("Invalid graph name {0}")]
+    InvalidGraphName(String),
+    /// The database is encrypted and was opened without a storage key.
+    #[error("Graph storage is encrypted and locked; open it with the storage key derived from the key manager")]
+    StorageLocked,
+    /// The storage key does not unlock the database's data key.
+    #[error("The storage key does not match the one the graph storage was encrypted with")]
+    InvalidStorageKey,
+    /// A key rotation stopped part way; the database opens again once it is run to the end.
+    #[error("A graph storage key rotation was interrupted; run it again to finish it")]
+    RotationInterrupted,
+    /// A query vector does not have as many dimensions as the embedder produces.
+    #[error("Expected a vector with {expected} dimensions, got {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[error("Checkpoint {0} not found")]
+    CheckpointNotFound(Uuid),
+    #[error("Checkpoint {0} has no snapshot to restore")]
+    SnapshotNotFound(Uuid),
+}
@@ -0,0 +1,228 @@
+use crate::entity::{Entity, PropertyValue};
+use crate::query::resolve_property;
+use serde::{Deserialize, Serialize};
+
+/// Mean Earth radius in meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// A lookup covers a region with at most this many geohash cells.
+const MAX_COVER_CELLS: u64 = 16;
+
+/// A WGS84 position in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// Great-circle distance in meters (haversine formula).
+    pub fn distance_to(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+}
+
+/// A latitude/longitude rectangle. A box with `min.lon > max.lon` crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoBox {
+    pub min: GeoPoint,
+    pub max: GeoPoint,
+}
+
+impl GeoBox {
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        let lon = if self.min.lon <= self.max.lon {
+            self.min.lon <= point.lon && point.lon <= self.max.lon
+        } else {
+            point.lon >= self.min.lon || point.lon <= self.max.lon
+        };
+        lon && self.min.lat <= point.lat && point.lat <= self.max.lat
+    }
+
+    /// Inclusive ranges of geohashes covering the box; every point inside hashes into one
+    /// of them, but they also cover some points outside.
+    pub fn geohash_ranges(&self) -> Vec<(u64, u64)> {
+        if self.min.lon > self.max.lon {
+            let mut ranges = GeoBox {
+                min: self.min,
+                max: GeoPoint::new(self.max.lat, 180.0),
+            }
+            .geohash_ranges();
+            ranges.extend(
+                GeoBox {
+                    min: GeoPoint::new(self.min.lat, -180.0),
+                    max: self.max,
+                }
+                .geohash_ranges(),
+            );
+            return ranges;
+        }
+        if self.min.lat > self.max.lat {
+            return Vec::new();
+        }
+
+        let (x_min, y_min) = quantize(&self.min);
+        let (x_max, y_max) = quantize(&self.max);
+        // The finest level at which the box spans few enough cells
+        let level = (0..=32u32)
+            .rev()
+            .find(|&level| {
+                let cells = |min: u32, max: u32| ((max as u64) >> (32 - level)) - ((min as u64) >> (32 - level)) + 1;
+                cells(x_min, x_max) * cells(y_min, y_max) <= MAX_COVER_CELLS
+            })
+            .unwrap_or(0);
+
+        let shift = 32 - level;
+        let span = u64::MAX.checked_shr(2 * level).unwrap_or(0);
+        let mut ranges = Vec::new();
+        for x in (x_min as u64 >> shift)..=(x_max as u64 >> shift) {
+            for y in (y_min as u64 >> shift)..=(y_max as u64 >> shift) {
+                let start = interleave((x << shift) as u32, (y << shift) as u32);
+                ranges.push((start, start | span));
+            }
+        }
+        ranges.sort_unstable();
+        ranges
+    }
+}
+
+/// Nodes whose `property` holds a point within `radius` meters of `center`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RadiusFilter {
+    pub property: String,
+    pub center: GeoPoint,
+    pub radius: f64,
+}
+
+impl RadiusFilter {
+    pub fn matches(&self, entity: &Entity) -> bool {
+        any_point(entity, &self.property, |point| self.center.distance_to(point) <= self.radius)
+    }
+
+    /// Smallest latitude/longitude box around the circle.
+    pub fn bounding_box(&self) -> GeoBox {
+        let angle = self.radius.max(0.0) / EARTH_RADIUS;
+        let dlat = angle.to_degrees();
+        let (lat_min, lat_max) = (self.center.lat - dlat, self.center.lat + dlat);
+        // A circle over a pole covers every longitude
+        let sin_dlon = angle.sin() / self.center.lat.to_radians().cos();
+        if lat_min <= -90.0 || lat_max >= 90.0 || angle >= std::f64::consts::FRAC_PI_2 || sin_dlon >= 1.0 {
+            return GeoBox {
+                min: GeoPoint::new(lat_min.max(-90.0), -180.0),
+                max: GeoPoint::new(lat_max.min(90.0), 180.0),
+            };
+        }
+
+        let dlon = sin_dlon.asin().to_degrees();
+        let wrap = |lon: f64| if lon < -180.0 { lon + 360.0 } else if lon > 180.0 { lon - 360.0 } else { lon };
+        GeoBox {
+            min: GeoPoint::new(lat_min, wrap(self.center.lon - dlon)),
+            max: GeoPoint::new(lat_max, wrap(self.center.lon + dlon)),
+        }
+    }
+}
+
+/// Nodes whose `property` holds a point inside the box from `min` to `max`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BBoxFilter {
+    pub property: String,
+    pub min: GeoPoint,
+    pub max: GeoPoint,
+}
+
+impl BBoxFilter {
+    pub fn bounds(&self) -> GeoBox {
+        GeoBox {
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    pub fn matches(&self, entity: &Entity) -> bool {
+        let bounds = self.bounds();
+        any_point(entity, &self.property, |point| bounds.contains(point))
+    }
+}
+
+/// Whether any point of a property (a single point or a list of them) passes `test`.
+fn any_point(entity: &Entity, property: &str, test: impl Fn(&GeoPoint) -> bool) -> bool {
+    match resolve_property(entity, property).as_deref() {
+        Some(PropertyValue::GeoPoint(point)) => test(point),
+        Some(PropertyValue::List(items)) => items.iter().any(|item| match item {
+            PropertyValue::GeoPoint(point) => test(point),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// 64-bit geohash: longitude and latitude bits interleaved, longitude first, so that
+/// points sharing a hash prefix share a cell and nearby cells sort close together.
+pub fn geohash(point: &GeoPoint) -> u64 {
+    let (x, y) = quantize(point);
+    interleave(x, y)
+}
+
+fn quantize(point: &GeoPoint) -> (u32, u32) {
+    let scale = |value: f64, min: f64, range: f64| {
+        (((value - min) / range) * 4_294_967_296.0).clamp(0.0, u32::MAX as f64) as u32
+    };
+    (scale(point.lon, -180.0, 360.0), scale(point.lat, -90.0, 180.0))
+}
+
+fn interleave(x: u32, y: u32) -> u64 {
+    (spread(x) << 1) | spread(y)
+}
+
+/// Moves bit `i` of `value` to bit `2i`.
+fn spread(value: u32) -> u64 {
+    let mut v = value as u64;
+    v = (v | (v << 16)) & 0x0000_FFFF_0000_FFFF;
+    v = (v | (v << 8)) & 0x00FF_00FF_00FF_00FF;
+    v = (v | (v << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+    (v | (v << 1)) & 0x5555_5555_5555_5555
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covering_ranges_contain_points() {
+        let moscow = GeoPoint::new(55.7558, 37.6173);
+        let spb = GeoPoint::new(59.9343, 30.3351);
+        assert!((moscow.distance_to(&spb) - 634_000.0).abs() < 5_000.0);
+
+        let filter = RadiusFilter {
+            property: "location".to_string(),
+            center: moscow,
+            radius: 700_000.0,
+        };
+        let bounds = filter.bounding_box();
+        assert!(bounds.contains(&spb));
+        let hash = geohash(&spb);
+        let ranges = bounds.geohash_ranges();
+        assert!(ranges.len() as u64 <= MAX_COVER_CELLS);
+        assert!(ranges.iter().any(|&(start, end)| start <= hash && hash <= end));
+
+        // Fiji straddles the antimeridian
+        let fiji = GeoBox {
+            min: GeoPoint::new(-21.0, 177.0),
+            max: GeoPoint::new(-12.0, -178.0),
+        };
+        let suva = GeoPoint::new(-18.14, 178.44);
+        assert!(fiji.contains(&suva));
+        assert!(!fiji.contains(&GeoPoint::new(-18.0, 0.0)));
+        let hash = geohash(&suva);
+        assert!(fiji.geohash_ranges().iter().any(|&(start, end)| start <= hash && hash <= end));
+    }
+}
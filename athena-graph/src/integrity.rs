@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens to the edges of a node that is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeDeletePolicy {
+    /// Delete the edges in the same update.
+    #[default]
+    Cascade,
+    /// Refuse to delete a node that still has edges, unless the same update deletes them.
+    Restrict,
+    /// Leave the edges in place, dangling.
+    Orphan,
+}
+
+/// Which nodes are checked against the schema registry on write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaValidation {
+    Off,
+    /// Nodes whose `type` is registered; other types and untyped nodes pass.
+    #[default]
+    Known,
+    /// Like `Known`, but a `type` that is not registered is rejected.
+    Strict,
+}
+
+/// Referential integrity rules enforced by the engine on every update.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityPolicy {
+    /// Reject edges whose `from` or `to` node does not exist once the update is applied.
+    #[serde(default = "default_reject_dangling_edges")]
+    pub reject_dangling_edges: bool,
+    #[serde(default)]
+    pub on_node_delete: NodeDeletePolicy,
+    #[serde(default)]
+    pub schema_validation: SchemaValidation,
+}
+
+impl Default for IntegrityPolicy {
+    fn default() -> Self {
+        Self {
+            reject_dangling_edges: default_reject_dangling_edges(),
+            on_node_delete: NodeDeletePolicy::default(),
+            schema_validation: SchemaValidation::default(),
+        }
+    }
+}
+
+fn default_reject_dangling_edges() -> bool {
+    true
+}
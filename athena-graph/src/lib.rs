@@ -1,12 +1,46 @@
 pub mod engine;
+pub mod error;
 pub mod storage;
 pub mod query;
 pub mod entity;
 pub mod version;
+pub mod traversal;
+pub mod rdf;
+pub mod merkle;
+pub mod query_lang;
+pub mod subscription;
+pub mod integrity;
+pub mod search;
+pub mod vector;
+pub mod timeline;
+pub mod geo;
+pub mod schema;
+pub mod aggregate;
+pub mod named_graph;
+pub mod store;
+pub mod encryption;
+pub mod akf;
 
 pub use engine::*;
+pub use error::*;
 pub use entity::*;
 pub use query::*;
 pub use storage::*;
 pub use version::*;
+pub use traversal::*;
+pub use rdf::*;
+pub use merkle::*;
+pub use query_lang::*;
+pub use subscription::*;
+pub use integrity::*;
+pub use search::*;
+pub use vector::*;
+pub use timeline::*;
+pub use geo::*;
+pub use schema::*;
+pub use aggregate::*;
+pub use named_graph::*;
+pub use store::*;
+pub use encryption::*;
+pub use akf::*;
 
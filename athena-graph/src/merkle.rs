@@ -0,0 +1,228 @@
+use crate::entity::{Edge, Entity, PropertyValue};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Leaves are spread over this many buckets by the first byte of the record id.
+pub const BUCKET_COUNT: usize = 256;
+
+pub type Hash = [u8; 32];
+
+/// Distinguishes node and edge leaves that could otherwise share an id.
+pub const NODE_LEAF: u8 = b'n';
+pub const EDGE_LEAF: u8 = b'e';
+
+/// Two-level Merkle tree over all records: leaf hashes are combined per bucket, bucket
+/// hashes into the root. Comparing bucket hashes narrows a mismatch down to 1/256th of the ids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleTree {
+    pub buckets: Vec<Hash>,
+}
+
+impl MerkleTree {
+    /// Builds the tree from scratch, independently of any stored leaf hashes.
+    pub fn from_records<N, E>(nodes: N, edges: E) -> anyhow::Result<Self>
+    where
+        N: IntoIterator<Item = anyhow::Result<Entity>>,
+        E: IntoIterator<Item = anyhow::Result<Edge>>,
+    {
+        let mut buckets: Vec<BTreeMap<(u8, Uuid), Hash>> = vec![BTreeMap::new(); BUCKET_COUNT];
+        for node in nodes {
+            let node = node?;
+            buckets[bucket_of(&node.id.0) as usize].insert((NODE_LEAF, node.id.0), hash_node(&node));
+        }
+        for edge in edges {
+            let edge = edge?;
+            buckets[bucket_of(&edge.id) as usize].insert((EDGE_LEAF, edge.id), hash_edge(&edge));
+        }
+
+        Ok(Self {
+            buckets: buckets
+                .iter()
+                .map(|leaves| {
+                    let mut hasher = BucketHasher::new();
+                    for ((kind, id), leaf) in leaves {
+                        hasher.add(*kind, id, leaf);
+                    }
+                    hasher.finish()
+                })
+                .collect(),
+        })
+    }
+
+    pub fn root(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        for bucket in &self.buckets {
+            hasher.update(bucket);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Buckets whose hashes differ between two trees.
+    pub fn diff(&self, other: &MerkleTree) -> Vec<u8> {
+        (0..BUCKET_COUNT)
+            .filter(|&i| self.buckets.get(i) != other.buckets.get(i))
+            .map(|i| i as u8)
+            .collect()
+    }
+}
+
+/// Combines the leaves of one bucket; they must be added in `(kind, id)` order.
+pub struct BucketHasher(Sha256);
+
+impl BucketHasher {
+    pub fn new() -> Self {
+        Self(Sha256::new())
+    }
+
+    pub fn add(&mut self, kind: u8, id: &Uuid, leaf: &[u8]) {
+        self.0.update([kind]);
+        self.0.update(id.as_bytes());
+        self.0.update(leaf);
+    }
+
+    pub fn finish(self) -> Hash {
+        self.0.finalize().into()
+    }
+}
+
+impl Default for BucketHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn bucket_of(id: &Uuid) -> u8 {
+    id.as_bytes()[0]
+}
+
+// Records are hashed from a canonical encoding rather than their bincode form, because
+// property maps are `HashMap`s and serialize in arbitrary order.
+
+pub fn hash_node(entity: &Entity) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(b"node");
+    hasher.update(entity.id.0.as_bytes());
+    write_str(&mut hasher, &entity.label);
+    hasher.update(entity.created_at.to_be_bytes());
+    hasher.update(entity.updated_at.to_be_bytes());
+    hasher.update(entity.version.to_be_bytes());
+    write_properties(&mut hasher, &entity.properties);
+    write_graph(&mut hasher, entity.graph.as_deref());
+    hasher.finalize().into()
+}
+
+pub fn hash_edge(edge: &Edge) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(b"edge");
+    hasher.update(edge.id.as_bytes());
+    hasher.update(edge.from.0.as_bytes());
+    hasher.update(edge.to.0.as_bytes());
+    write_str(&mut hasher, &edge.label);
+    hasher.update(edge.created_at.to_be_bytes());
+    hasher.update(edge.version.to_be_bytes());
+    write_properties(&mut hasher, &edge.properties);
+    write_graph(&mut hasher, edge.graph.as_deref());
+    hasher.finalize().into()
+}
+
+fn write_str(hasher: &mut Sha256, value: &str) {
+    hasher.update((value.len() as u64).to_be_bytes());
+    hasher.update(value.as_bytes());
+}
+
+// Only named graphs are hashed, so records in the default graph keep the hashes they had
+// before the field existed
+fn write_graph(hasher: &mut Sha256, graph: Option<&str>) {
+    if let Some(graph) = graph {
+        hasher.update(b"graph");
+        write_str(hasher, graph);
+    }
+}
+
+fn write_properties(hasher: &mut Sha256, properties: &HashMap<String, PropertyValue>) {
+    let mut entries: Vec<_> = properties.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    hasher.update((entries.len() as u64).to_be_bytes());
+    for (name, value) in entries {
+        write_str(hasher, name);
+        write_value(hasher, value);
+    }
+}
+
+fn write_value(hasher: &mut Sha256, value: &PropertyValue) {
+    match value {
+        PropertyValue::String(s) => {
+            hasher.update(b"s");
+            write_str(hasher, s);
+        }
+        PropertyValue::Number(n) => {
+            hasher.update(b"n");
+            hasher.update(n.to_bits().to_be_bytes());
+        }
+        PropertyValue::Boolean(b) => {
+            hasher.update(b"b");
+            hasher.update([*b as u8]);
+        }
+        PropertyValue::DateTime(t) => {
+            hasher.update(b"t");
+            hasher.update(t.to_be_bytes());
+        }
+        PropertyValue::Reference(id) => {
+            hasher.update(b"r");
+            hasher.update(id.0.as_bytes());
+        }
+        PropertyValue::List(items) => {
+            hasher.update(b"l");
+            hasher.update((items.len() as u64).to_be_bytes());
+            for item in items {
+                write_value(hasher, item);
+            }
+        }
+        PropertyValue::Map(map) => {
+            hasher.update(b"m");
+            write_properties(hasher, map);
+        }
+        PropertyValue::GeoPoint(point) => {
+            hasher.update(b"g");
+            hasher.update(point.lat.to_bits().to_be_bytes());
+            hasher.update(point.lon.to_bits().to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::NodeId;
+
+    #[test]
+    fn test_hash_ignores_map_order() {
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for i in 0..32 {
+            a.insert(format!("k{}", i), PropertyValue::Number(i as f64));
+        }
+        for i in (0..32).rev() {
+            b.insert(format!("k{}", i), PropertyValue::Number(i as f64));
+        }
+        let node = |properties| Entity {
+            id: NodeId::from_uuid(Uuid::nil()),
+            label: "note".to_string(),
+            properties,
+            created_at: 1,
+            updated_at: 2,
+            version: 3,
+            graph: None,
+        };
+        let (a, mut b) = (node(a), node(b));
+        assert_eq!(hash_node(&a), hash_node(&b));
+
+        let tree = MerkleTree::from_records(vec![Ok(a.clone())], vec![]).unwrap();
+        b.label = "changed".to_string();
+        let changed = MerkleTree::from_records(vec![Ok(b)], vec![]).unwrap();
+        assert_ne!(tree.root(), changed.root());
+        assert_eq!(tree.diff(&changed), vec![bucket_of(&a.id.0)]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::entity::{Edge, Entity, NodeId, PropertyValue};
+use crate::error::GraphError;
+use crate::query::FilterExpr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Name `Entity::graph` and `Edge::graph` go by in filters, sorting and group-by, like the
+/// entity's other own fields.
+pub const GRAPH_FIELD: &str = "graph";
+pub const DEFAULT_GRAPH: &str = "default";
+
+const MAX_GRAPH_NAME: usize = 64;
+
+/// Name of the graph a record's `graph` field puts it in.
+pub fn graph_of(graph: Option<&str>) -> &str {
+    graph.unwrap_or(DEFAULT_GRAPH)
+}
+
+/// Value of the `graph` field for a record in `graph`. The default graph is stored as `None`,
+/// like on records written before named graphs existed.
+pub fn graph_field(graph: &str) -> Option<String> {
+    (graph != DEFAULT_GRAPH).then(|| graph.to_string())
+}
+
+/// Graph names are 1-64 ASCII letters, digits, `-`, `_` or `.`, so they can be used in
+/// URLs and storage keys as they are.
+pub fn validate_graph_name(name: &str) -> Result<(), GraphError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_GRAPH_NAME
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(GraphError::InvalidGraphName(name.to_string()))
+    }
+}
+
+/// Checks the `graph` field of a record about to be written.
+pub fn validate_graph(graph: Option<&str>) -> Result<(), GraphError> {
+    graph.map_or(Ok(()), validate_graph_name)
+}
+
+/// A graph and how many records it holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphInfo {
+    pub name: String,
+    pub nodes: u64,
+    pub edges: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferMode {
+    /// Reassigns the nodes and their edges; ids, versions and history are kept.
+    #[default]
+    Move,
+    /// Writes copies under new ids and leaves the source untouched.
+    Copy,
+}
+
+/// Moves or copies a subgraph: the nodes of `from` matching `filter` and the edges of
+/// `from` running between them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphTransfer {
+    pub from: String,
+    pub to: String,
+    /// Nodes to transfer; `None` takes the whole graph.
+    #[serde(default)]
+    pub filter: Option<FilterExpr>,
+    #[serde(default)]
+    pub mode: TransferMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferResult {
+    pub nodes: usize,
+    pub edges: usize,
+    /// For copies, the id of each copied node keyed by the id of its original.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub copies: HashMap<NodeId, NodeId>,
+}
+
+/// A copy of `node` in `graph` under `id`. References to other copied nodes are rewritten
+/// to point at their copies.
+pub fn copy_node(node: &Entity, id: NodeId, graph: &str, copies: &HashMap<NodeId, NodeId>, now: i64) -> Entity {
+    Entity {
+        id,
+        label: node.label.clone(),
+        properties: node
+            .properties
+            .iter()
+            .map(|(name, value)| (name.clone(), remap_references(value, copies)))
+            .collect(),
+        created_at: now,
+        updated_at: now,
+        version: 1,
+        graph: graph_field(graph),
+    }
+}
+
+/// A copy of `edge` in `graph` between the copies of its endpoints.
+pub fn copy_edge(edge: &Edge, graph: &str, copies: &HashMap<NodeId, NodeId>, now: i64) -> Edge {
+    let endpoint = |id: &NodeId| copies.get(id).cloned().unwrap_or_else(|| id.clone());
+    Edge {
+        id: uuid::Uuid::new_v4(),
+        from: endpoint(&edge.from),
+        to: endpoint(&edge.to),
+        label: edge.label.clone(),
+        properties: edge
+            .properties
+            .iter()
+            .map(|(name, value)| (name.clone(), remap_references(value, copies)))
+            .collect(),
+        created_at: now,
+        version: 1,
+        graph: graph_field(graph),
+    }
+}
+
+fn remap_references(value: &PropertyValue, copies: &HashMap<NodeId, NodeId>) -> PropertyValue {
+    match value {
+        PropertyValue::Reference(id) => PropertyValue::Reference(copies.get(id).cloned().unwrap_or_else(|| id.clone())),
+        PropertyValue::List(items) => PropertyValue::List(items.iter().map(|item| remap_references(item, copies)).collect()),
+        PropertyValue::Map(map) => PropertyValue::Map(
+            map.iter()
+                .map(|(key, item)| (key.clone(), remap_references(item, copies)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_names_and_copies() {
+        assert!(validate_graph_name("project-alpha").is_ok());
+        assert!(validate_graph_name("").is_err());
+        assert!(validate_graph_name("work/secret").is_err());
+
+        assert_eq!(graph_field("work").as_deref(), Some("work"));
+        assert_eq!(graph_field(DEFAULT_GRAPH), None);
+        assert_eq!(graph_of(None), DEFAULT_GRAPH);
+        assert!(validate_graph(Some("no spaces")).is_err());
+
+        let (a, b) = (NodeId::new(), NodeId::new());
+        let original = Entity {
+            id: a.clone(),
+            label: "a".to_string(),
+            properties: [("next".to_string(), PropertyValue::Reference(b.clone()))]
+                .into_iter()
+                .collect(),
+            created_at: 0,
+            updated_at: 0,
+            version: 3,
+            graph: None,
+        };
+        let copies: HashMap<NodeId, NodeId> = [(a, NodeId::new()), (b.clone(), NodeId::new())].into_iter().collect();
+        let copy = copy_node(&original, NodeId::new(), "personal", &copies, 10);
+        assert_eq!(copy.properties["next"], PropertyValue::Reference(copies[&b].clone()));
+        assert_eq!(copy.graph.as_deref(), Some("personal"));
+        assert_eq!(copy.version, 1);
+    }
+}
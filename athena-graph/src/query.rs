@@ -1,25 +1,342 @@
-use crate::entity::{Edge, Entity, NodeId};
+use crate::entity::{Edge, Entity, NodeId, PropertyValue};
+use crate::error::GraphError;
+use crate::geo::{BBoxFilter, RadiusFilter};
+use crate::named_graph::{graph_of, GRAPH_FIELD};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResult {
     pub nodes: Vec<Entity>,
     pub edges: Vec<Edge>,
+    /// Pass back as `GraphPattern::cursor` to fetch the next page; absent on the last one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
+/// One page of `GraphEngine::list_edges`, in edge id order.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgePage {
+    pub edges: Vec<Edge>,
+    /// Pass back as the cursor to fetch the next page; absent on the last one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GraphPattern {
+    /// Flat filters that must all match; combined with `filter` by AND.
+    #[serde(default)]
     pub node_filters: Vec<NodeFilter>,
+    #[serde(default)]
     pub edge_filters: Vec<EdgeFilter>,
+    /// Boolean filter expression over nodes.
+    #[serde(default)]
+    pub filter: Option<FilterExpr>,
     pub limit: Option<usize>,
+    /// Sort keys, most significant first. Ties (and an empty list) are broken by node id,
+    /// so the order is total and pages never overlap.
+    #[serde(default)]
+    pub order_by: Vec<OrderBy>,
+    /// Properties to return; `None` returns all of them. The entity's own fields are
+    /// always present.
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+    /// Continuation from a previous `QueryResult::next_cursor`.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Named graphs to search; empty searches all of them.
+    #[serde(default)]
+    pub graphs: Vec<String>,
+}
+
+impl GraphPattern {
+    /// The complete node predicate: `node_filters`, `filter` and the graph scope joined by AND.
+    pub fn node_expr(&self) -> FilterExpr {
+        let mut terms: Vec<FilterExpr> = self
+            .node_filters
+            .iter()
+            .cloned()
+            .map(FilterExpr::Filter)
+            .collect();
+        if let Some(filter) = &self.filter {
+            terms.push(filter.clone());
+        }
+        if !self.graphs.is_empty() {
+            terms.push(FilterExpr::Or(
+                self.graphs
+                    .iter()
+                    .map(|graph| {
+                        FilterExpr::Filter(NodeFilter::new(
+                            GRAPH_FIELD,
+                            FilterOperator::Equals,
+                            PropertyValue::String(graph.clone()),
+                        ))
+                    })
+                    .collect(),
+            ));
+        }
+        match terms.len() {
+            1 => terms.remove(0),
+            _ => FilterExpr::And(terms),
+        }
+    }
+
+    /// Whether an edge passes `edge_filters` and lies in one of the pattern's graphs.
+    pub fn edge_matches(&self, edge: &Edge) -> bool {
+        (self.graphs.is_empty() || self.graphs.iter().any(|graph| graph == graph_of(edge.graph.as_deref())))
+            && self.edge_filters.iter().all(|filter| filter.matches(edge))
+    }
+
+    /// Sorts matching nodes, skips past the cursor, cuts the page at `limit` and applies
+    /// the projection. Returns the page and the cursor for the next one.
+    pub fn paginate(&self, mut nodes: Vec<Entity>) -> Result<(Vec<Entity>, Option<String>)> {
+        let after = self.after()?;
+        let mut keyed: Vec<(Vec<Option<PropertyValue>>, Entity)> = nodes
+            .drain(..)
+            .map(|node| (sort_key(&node, &self.order_by), node))
+            .collect();
+        keyed.sort_by(|(a, x), (b, y)| compare_sort_keys(&self.order_by, (a, &x.id), (b, &y.id)));
+        if let Some(after) = &after {
+            keyed.retain(|(key, node)| {
+                compare_sort_keys(&self.order_by, (key, &node.id), (&after.key, &after.id)) == Ordering::Greater
+            });
+        }
+        Ok(self.cut(keyed))
+    }
+
+    /// Node id the page starts after. Without `order_by` pages follow node id order, so
+    /// this is all a scan needs to resume.
+    pub fn after_id(&self) -> Result<Option<NodeId>> {
+        Ok(self.after()?.map(|cursor| cursor.id))
+    }
+
+    /// Like `paginate`, for nodes that are already in order and past the cursor. Only the
+    /// first `limit + 1` of them are needed to tell whether another page follows.
+    pub fn page(&self, nodes: Vec<Entity>) -> (Vec<Entity>, Option<String>) {
+        self.cut(
+            nodes
+                .into_iter()
+                .map(|node| (sort_key(&node, &self.order_by), node))
+                .collect(),
+        )
+    }
+
+    fn after(&self) -> Result<Option<Cursor>> {
+        let after = self.cursor.as_deref().map(Cursor::decode).transpose()?;
+        if let Some(after) = &after {
+            if after.key.len() != self.order_by.len() {
+                return Err(GraphError::InvalidCursor("issued for a different sort order".to_string()).into());
+            }
+        }
+        Ok(after)
+    }
+
+    fn cut(&self, mut keyed: Vec<(Vec<Option<PropertyValue>>, Entity)>) -> (Vec<Entity>, Option<String>) {
+        let mut next_cursor = None;
+        if let Some(limit) = self.limit {
+            if keyed.len() > limit {
+                keyed.truncate(limit);
+                next_cursor = keyed.last().map(|(key, node)| {
+                    Cursor {
+                        key: key.clone(),
+                        id: node.id.clone(),
+                    }
+                    .encode()
+                });
+            }
+        }
+
+        let page = keyed
+            .into_iter()
+            .map(|(_, mut node)| {
+                if let Some(fields) = &self.fields {
+                    node.properties.retain(|name, _| fields.contains(name));
+                }
+                node
+            })
+            .collect();
+        (page, next_cursor)
+    }
+}
+
+/// One sort key of a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderBy {
+    /// A property name, or `id`, `label`, `created_at`, `updated_at`.
+    pub property: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl OrderBy {
+    pub fn asc(property: impl Into<String>) -> Self {
+        Self {
+            property: property.into(),
+            descending: false,
+        }
+    }
+
+    pub fn desc(property: impl Into<String>) -> Self {
+        Self {
+            property: property.into(),
+            descending: true,
+        }
+    }
+
+    /// Parses `name`, `name:asc` or `name:desc`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.rsplit_once(':') {
+            Some((property, "asc")) => Ok(Self::asc(property)),
+            Some((property, "desc")) => Ok(Self::desc(property)),
+            Some((_, direction)) => Err(anyhow::anyhow!("Unknown sort direction '{}'", direction)),
+            None => Ok(Self::asc(spec)),
+        }
+    }
+}
+
+/// Position of the last node of a page. Encoded as hex so clients treat it as opaque.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    key: Vec<Option<PropertyValue>>,
+    id: NodeId,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        json.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn decode(cursor: &str) -> Result<Self> {
+        let invalid = || GraphError::InvalidCursor("not a cursor from a previous page".to_string());
+        if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+            return Err(invalid().into());
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        Ok(serde_json::from_slice(&bytes).map_err(|_| invalid())?)
+    }
+}
+
+/// Edge pages resume after the id of the previous page's last edge, written as plain hex.
+pub(crate) fn encode_edge_cursor(id: &uuid::Uuid) -> String {
+    id.simple().to_string()
+}
+
+pub(crate) fn decode_edge_cursor(cursor: &str) -> Result<uuid::Uuid> {
+    match uuid::Uuid::try_parse(cursor) {
+        Ok(id) if cursor.len() == 32 => Ok(id),
+        _ => Err(GraphError::InvalidCursor("not a cursor from a previous page".to_string()).into()),
+    }
+}
+
+fn sort_key(entity: &Entity, order_by: &[OrderBy]) -> Vec<Option<PropertyValue>> {
+    order_by
+        .iter()
+        .map(|order| resolve_property(entity, &order.property).map(Cow::into_owned))
+        .collect()
+}
+
+/// Compares `(sort key, id)` pairs. Nodes missing a sort property come last in either
+/// direction; values of different types are grouped by type.
+fn compare_sort_keys(
+    order_by: &[OrderBy],
+    (a, a_id): (&[Option<PropertyValue>], &NodeId),
+    (b, b_id): (&[Option<PropertyValue>], &NodeId),
+) -> Ordering {
+    order_by
+        .iter()
+        .zip(a.iter().zip(b))
+        .map(|(order, pair)| match pair {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(x), Some(y)) => {
+                let ordering = compare_values(x, y)
+                    .unwrap_or_else(|| type_rank(x).cmp(&type_rank(y)));
+                if order.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a_id.0.cmp(&b_id.0))
+}
+
+fn type_rank(value: &PropertyValue) -> u8 {
+    match value {
+        PropertyValue::String(_) => 0,
+        PropertyValue::Number(_) => 1,
+        PropertyValue::Boolean(_) => 2,
+        PropertyValue::DateTime(_) => 3,
+        PropertyValue::Reference(_) => 4,
+        PropertyValue::List(_) => 5,
+        PropertyValue::Map(_) => 6,
+        PropertyValue::GeoPoint(_) => 7,
+    }
+}
+
+/// Filter expression tree. In JSON each node is an object with a single key, e.g.
+/// `{"and": [{"filter": {...}}, {"not": {"filter": {...}}}]}` or
+/// `{"radius": {"property": "location", "center": {"lat": 55.75, "lon": 37.62}, "radius": 1000}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterExpr {
+    Filter(NodeFilter),
+    /// Matches when every child matches; an empty group matches everything.
+    And(Vec<FilterExpr>),
+    /// Matches when any child matches; an empty group matches nothing.
+    Or(Vec<FilterExpr>),
+    Not(Box<FilterExpr>),
+    /// A point property within a distance in meters.
+    Radius(RadiusFilter),
+    /// A point property inside a latitude/longitude box.
+    BBox(BBoxFilter),
+}
+
+impl FilterExpr {
+    pub fn matches(&self, entity: &Entity) -> bool {
+        match self {
+            FilterExpr::Filter(filter) => filter.matches(entity),
+            FilterExpr::And(children) => children.iter().all(|c| c.matches(entity)),
+            FilterExpr::Or(children) => children.iter().any(|c| c.matches(entity)),
+            FilterExpr::Not(child) => !child.matches(entity),
+            FilterExpr::Radius(filter) => filter.matches(entity),
+            FilterExpr::BBox(filter) => filter.matches(entity),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeFilter {
     pub property: String,
     pub operator: FilterOperator,
-    pub value: String,
+    pub value: PropertyValue,
+}
+
+impl NodeFilter {
+    pub fn new(property: impl Into<String>, operator: FilterOperator, value: PropertyValue) -> Self {
+        Self {
+            property: property.into(),
+            operator,
+            value,
+        }
+    }
+
+    pub fn matches(&self, entity: &Entity) -> bool {
+        match resolve_property(entity, &self.property) {
+            Some(actual) => self.operator.evaluate(&actual, &self.value),
+            None => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,7 +346,23 @@ pub struct EdgeFilter {
     pub label: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl EdgeFilter {
+    pub fn matches(&self, edge: &Edge) -> bool {
+        self.from.as_ref().is_none_or(|from| &edge.from == from)
+            && self.to.as_ref().is_none_or(|to| &edge.to == to)
+            && self.label.as_ref().is_none_or(|label| &edge.label == label)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FilterOperator {
     Equals,
     Contains,
@@ -37,6 +370,92 @@ pub enum FilterOperator {
     EndsWith,
     GreaterThan,
     LessThan,
+    GreaterThanOrEqual,
+    LessThanOrEqual,
+}
+
+impl FilterOperator {
+    /// Applies the operator to a stored value and the filter's expected value.
+    ///
+    /// Values are compared within their own type only: numbers and date-times are
+    /// ordered numerically, strings lexicographically, references by id. A list
+    /// matches when any of its elements does; `Equals` and `Contains` on a list are
+    /// membership tests.
+    pub fn evaluate(&self, actual: &PropertyValue, expected: &PropertyValue) -> bool {
+        match (actual, expected) {
+            (PropertyValue::List(a), PropertyValue::List(b)) if *self == FilterOperator::Equals => {
+                a == b
+            }
+            (PropertyValue::List(items), _) if *self == FilterOperator::Contains => items
+                .iter()
+                .any(|item| FilterOperator::Equals.evaluate(item, expected)),
+            (PropertyValue::List(items), _) => {
+                items.iter().any(|item| self.evaluate(item, expected))
+            }
+            (PropertyValue::GeoPoint(a), PropertyValue::GeoPoint(b)) => {
+                *self == FilterOperator::Equals && a == b
+            }
+            (PropertyValue::Map(map), PropertyValue::String(key))
+                if *self == FilterOperator::Contains =>
+            {
+                map.contains_key(key)
+            }
+            (PropertyValue::String(a), PropertyValue::String(b)) => match self {
+                FilterOperator::Contains => a.contains(b.as_str()),
+                FilterOperator::StartsWith => a.starts_with(b.as_str()),
+                FilterOperator::EndsWith => a.ends_with(b.as_str()),
+                _ => self.accepts(a.as_str().cmp(b.as_str())),
+            },
+            _ => match compare_values(actual, expected) {
+                Some(ordering) => self.accepts(ordering),
+                None => false,
+            },
+        }
+    }
+
+    fn accepts(&self, ordering: Ordering) -> bool {
+        match self {
+            FilterOperator::Equals => ordering == Ordering::Equal,
+            FilterOperator::GreaterThan => ordering == Ordering::Greater,
+            FilterOperator::LessThan => ordering == Ordering::Less,
+            FilterOperator::GreaterThanOrEqual => ordering != Ordering::Less,
+            FilterOperator::LessThanOrEqual => ordering != Ordering::Greater,
+            FilterOperator::Contains | FilterOperator::StartsWith | FilterOperator::EndsWith => false,
+        }
+    }
+}
+
+/// Orders two values of the same scalar type. Returns `None` for mismatched or
+/// unordered types (and for NaN).
+pub fn compare_values(a: &PropertyValue, b: &PropertyValue) -> Option<Ordering> {
+    match (a, b) {
+        (PropertyValue::String(a), PropertyValue::String(b)) => Some(a.cmp(b)),
+        (PropertyValue::Number(a), PropertyValue::Number(b)) => a.partial_cmp(b),
+        (PropertyValue::DateTime(a), PropertyValue::DateTime(b)) => Some(a.cmp(b)),
+        (PropertyValue::Boolean(a), PropertyValue::Boolean(b)) => Some(a.cmp(b)),
+        (PropertyValue::Reference(a), PropertyValue::Reference(b)) => Some(a.0.cmp(&b.0)),
+        _ => None,
+    }
+}
+
+/// Looks up a property for filtering. `id`, `label`, `created_at`, `updated_at` and `graph`
+/// address the entity's own fields; any other name is read from `properties`.
+pub fn resolve_property<'a>(entity: &'a Entity, property: &str) -> Option<Cow<'a, PropertyValue>> {
+    match property {
+        GRAPH_FIELD => Some(Cow::Owned(PropertyValue::String(
+            graph_of(entity.graph.as_deref()).to_string(),
+        ))),
+        "id" => Some(Cow::Owned(PropertyValue::Reference(entity.id.clone()))),
+        "label" => Some(Cow::Owned(PropertyValue::String(entity.label.clone()))),
+        "created_at" => Some(Cow::Owned(PropertyValue::DateTime(entity.created_at))),
+        "updated_at" => Some(Cow::Owned(PropertyValue::DateTime(entity.updated_at))),
+        _ => entity.properties.get(property).map(Cow::Borrowed),
+    }
+}
+
+/// Whether `property` names one of the entity's own fields rather than a stored property.
+pub fn is_builtin_property(property: &str) -> bool {
+    matches!(property, "id" | "label" | "created_at" | "updated_at" | GRAPH_FIELD)
 }
 
 pub trait GraphQuery {
@@ -45,3 +464,66 @@ pub trait GraphQuery {
     fn find_edges(&self, from: Option<&NodeId>, to: Option<&NodeId>) -> Result<Vec<Edge>>;
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entity(properties: Vec<(&str, PropertyValue)>) -> Entity {
+        Entity {
+            id: NodeId::new(),
+            label: "Note: test".to_string(),
+            properties: properties
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<HashMap<_, _>>(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    #[test]
+    fn test_typed_comparisons() {
+        let op = FilterOperator::GreaterThan;
+        assert!(op.evaluate(&PropertyValue::Number(20.0), &PropertyValue::Number(10.0)));
+        assert!(!op.evaluate(&PropertyValue::Number(20.0), &PropertyValue::DateTime(10)));
+        assert!(FilterOperator::Equals.evaluate(
+            &PropertyValue::String("email".to_string()),
+            &PropertyValue::String("email".to_string())
+        ));
+        assert!(FilterOperator::EndsWith.evaluate(
+            &PropertyValue::String("notes.md".to_string()),
+            &PropertyValue::String(".md".to_string())
+        ));
+        let tags = PropertyValue::List(vec![PropertyValue::String("rust".to_string())]);
+        assert!(FilterOperator::Contains.evaluate(&tags, &PropertyValue::String("rust".to_string())));
+        assert!(!FilterOperator::Contains.evaluate(&tags, &PropertyValue::String("ru".to_string())));
+    }
+
+    #[test]
+    fn test_boolean_composition() {
+        let expr: FilterExpr = serde_json::from_value(serde_json::json!({
+            "and": [
+                {"or": [
+                    {"filter": {"property": "tags", "operator": "Contains", "value": {"type": "String", "value": "rust"}}},
+                    {"filter": {"property": "tags", "operator": "Contains", "value": {"type": "String", "value": "wasm"}}}
+                ]},
+                {"not": {"filter": {"property": "archived", "operator": "Equals", "value": {"type": "Boolean", "value": true}}}}
+            ]
+        }))
+        .unwrap();
+
+        let tags = |t: &[&str]| {
+            PropertyValue::List(t.iter().map(|s| PropertyValue::String(s.to_string())).collect())
+        };
+        assert!(expr.matches(&entity(vec![("tags", tags(&["wasm"]))])));
+        assert!(!expr.matches(&entity(vec![("tags", tags(&["go"]))])));
+        assert!(!expr.matches(&entity(vec![
+            ("tags", tags(&["rust"])),
+            ("archived", PropertyValue::Boolean(true)),
+        ])));
+    }
+}
@@ -0,0 +1,1130 @@
+//! Compact Cypher-like text query language.
+//!
+//! ```text
+//! MATCH (n:email {from: "alice@example.com"})-[r:reply]->(m)
+//! WHERE n.size > 1000 AND (m.subject CONTAINS "rust" OR NOT m.archived = true)
+//! RETURN n, r, m.subject
+//! LIMIT 20
+//! ```
+//!
+//! `(n:email)` matches nodes whose `type` property is `"email"`. Conditions in `WHERE` are
+//! split on top-level `AND`; every resulting term must refer to a single node variable.
+//! Besides strings, numbers and booleans, values can be `datetime("2024-05-01")`,
+//! `ref("<uuid>")` and lists `["a", "b"]`.
+//! Each relationship is a single step; variable-length ones like `-[*1..3]->` are rejected.
+
+use crate::engine::GraphEngine;
+use crate::entity::{Edge, Entity, NodeId, PropertyValue};
+use crate::query::{resolve_property, Direction, FilterExpr, FilterOperator, GraphPattern, NodeFilter};
+use crate::traversal::{PathQuery, PathStep};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+const DEFAULT_PATH_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ParseError {
+    fn new(source: &str, offset: usize, message: impl Into<String>) -> Self {
+        let offset = offset.min(source.len());
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map(|l| l.chars().count()).unwrap_or(0) + 1;
+        Self {
+            message: message.into(),
+            offset,
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(f64),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Colon,
+    Comma,
+    Dot,
+    Minus,
+    Star,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "'{}'", s),
+            Token::Str(s) => write!(f, "string \"{}\"", s),
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Eof => write!(f, "end of query"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBracket => write!(f, "'['"),
+            Token::RBracket => write!(f, "']'"),
+            Token::LBrace => write!(f, "'{{'"),
+            Token::RBrace => write!(f, "'}}'"),
+            Token::Colon => write!(f, "':'"),
+            Token::Comma => write!(f, "','"),
+            Token::Dot => write!(f, "'.'"),
+            Token::Minus => write!(f, "'-'"),
+            Token::Star => write!(f, "'*'"),
+            Token::Eq => write!(f, "'='"),
+            Token::Ne => write!(f, "'<>'"),
+            Token::Lt => write!(f, "'<'"),
+            Token::Gt => write!(f, "'>'"),
+            Token::Le => write!(f, "'<='"),
+            Token::Ge => write!(f, "'>='"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let single = match c {
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            '[' => Some(Token::LBracket),
+            ']' => Some(Token::RBracket),
+            '{' => Some(Token::LBrace),
+            '}' => Some(Token::RBrace),
+            ':' => Some(Token::Colon),
+            ',' => Some(Token::Comma),
+            '.' => Some(Token::Dot),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '=' => Some(Token::Eq),
+            _ => None,
+        };
+        if let Some(token) = single {
+            tokens.push((token, offset));
+            i += 1;
+            continue;
+        }
+
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '<' | '>' | '!' => {
+                let (token, len) = match (c, next) {
+                    ('<', Some('=')) => (Token::Le, 2),
+                    ('<', Some('>')) => (Token::Ne, 2),
+                    ('>', Some('=')) => (Token::Ge, 2),
+                    ('!', Some('=')) => (Token::Ne, 2),
+                    ('<', _) => (Token::Lt, 1),
+                    ('>', _) => (Token::Gt, 1),
+                    _ => return Err(ParseError::new(source, offset, "Unexpected character '!'")),
+                };
+                tokens.push((token, offset));
+                i += len;
+            }
+            '"' | '\'' => {
+                let quote = c;
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(ParseError::new(source, offset, "Unterminated string literal"))
+                        }
+                        Some(&(_, ch)) if ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&(escape_offset, '\\')) => {
+                            let escaped = match chars.get(i + 1).map(|&(_, c)| c) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(c @ ('\\' | '"' | '\'')) => c,
+                                _ => {
+                                    return Err(ParseError::new(
+                                        source,
+                                        escape_offset,
+                                        "Invalid escape sequence",
+                                    ))
+                                }
+                            };
+                            value.push(escaped);
+                            i += 2;
+                        }
+                        Some(&(_, ch)) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push((Token::Str(value), offset));
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                    i += 1;
+                }
+                let end = chars.get(i).map(|&(o, _)| o).unwrap_or(source.len());
+                let text = &source[offset..end];
+                let number = text.parse::<f64>().map_err(|_| {
+                    ParseError::new(source, chars[start].0, format!("Invalid number '{}'", text))
+                })?;
+                tokens.push((Token::Number(number), offset));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                    i += 1;
+                }
+                let end = chars.get(i).map(|&(o, _)| o).unwrap_or(source.len());
+                tokens.push((Token::Ident(source[offset..end].to_string()), offset));
+            }
+            '`' => {
+                // Backquoted identifiers allow property names with spaces or dashes
+                let start = offset + 1;
+                i += 1;
+                while i < chars.len() && chars[i].1 != '`' {
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(ParseError::new(source, offset, "Unterminated quoted identifier"));
+                }
+                tokens.push((Token::Ident(source[start..chars[i].0].to_string()), offset));
+                i += 1;
+            }
+            other => {
+                return Err(ParseError::new(
+                    source,
+                    offset,
+                    format!("Unexpected character '{}'", other),
+                ))
+            }
+        }
+    }
+
+    tokens.push((Token::Eof, source.len()));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodePattern {
+    pub variable: Option<String>,
+    pub type_name: Option<String>,
+    pub properties: Vec<(String, PropertyValue)>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelPattern {
+    pub variable: Option<String>,
+    pub label: Option<String>,
+    pub direction: Direction,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare {
+        variable: String,
+        property: String,
+        operator: FilterOperator,
+        negated: bool,
+        value: PropertyValue,
+        offset: usize,
+    },
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnItem {
+    pub variable: String,
+    pub property: Option<String>,
+    pub offset: usize,
+}
+
+/// Parsed text query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextQuery {
+    pub nodes: Vec<NodePattern>,
+    /// `relationships[i]` connects `nodes[i]` and `nodes[i + 1]`.
+    pub relationships: Vec<RelPattern>,
+    pub condition: Option<Condition>,
+    /// Empty means `RETURN *`.
+    pub returns: Vec<ReturnItem>,
+    pub limit: Option<usize>,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens[self.position].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if self.position + 1 < self.tokens.len() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.source, self.offset(), message)
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        self.error(format!("Expected {}, found {}", expected, self.peek()))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if *self.peek() == token {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.is_keyword(keyword) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn identifier(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Token::Ident(name) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<TextQuery, ParseError> {
+        self.expect_keyword("MATCH")?;
+
+        let mut nodes = vec![self.parse_node()?];
+        let mut relationships = Vec::new();
+        while matches!(self.peek(), Token::Minus | Token::Lt) {
+            relationships.push(self.parse_relationship()?);
+            nodes.push(self.parse_node()?);
+        }
+
+        let condition = if self.is_keyword("WHERE") {
+            self.advance();
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+
+        let mut returns = Vec::new();
+        if self.is_keyword("RETURN") {
+            self.advance();
+            if *self.peek() == Token::Star {
+                self.advance();
+            } else {
+                loop {
+                    let offset = self.offset();
+                    let variable = self.identifier("a variable")?;
+                    let property = if *self.peek() == Token::Dot {
+                        self.advance();
+                        Some(self.identifier("a property name")?)
+                    } else {
+                        None
+                    };
+                    returns.push(ReturnItem {
+                        variable,
+                        property,
+                        offset,
+                    });
+                    if *self.peek() != Token::Comma {
+                        break;
+                    }
+                    self.advance();
+                }
+            }
+        }
+
+        let limit = if self.is_keyword("LIMIT") {
+            self.advance();
+            match self.peek() {
+                Token::Number(n) if *n >= 0.0 && n.fract() == 0.0 => {
+                    let n = *n as usize;
+                    self.advance();
+                    Some(n)
+                }
+                _ => return Err(self.unexpected("a non-negative integer")),
+            }
+        } else {
+            None
+        };
+
+        if *self.peek() != Token::Eof {
+            return Err(self.unexpected("WHERE, RETURN, LIMIT or end of query"));
+        }
+
+        Ok(TextQuery {
+            nodes,
+            relationships,
+            condition,
+            returns,
+            limit,
+        })
+    }
+
+    fn parse_node(&mut self) -> Result<NodePattern, ParseError> {
+        let offset = self.offset();
+        self.expect(Token::LParen)?;
+
+        let variable = match self.peek() {
+            Token::Ident(_) => Some(self.identifier("a variable")?),
+            _ => None,
+        };
+        let type_name = if *self.peek() == Token::Colon {
+            self.advance();
+            Some(self.identifier("a node type")?)
+        } else {
+            None
+        };
+
+        let mut properties = Vec::new();
+        if *self.peek() == Token::LBrace {
+            self.advance();
+            if *self.peek() != Token::RBrace {
+                loop {
+                    let name = self.identifier("a property name")?;
+                    self.expect(Token::Colon)?;
+                    properties.push((name, self.parse_value()?));
+                    if *self.peek() != Token::Comma {
+                        break;
+                    }
+                    self.advance();
+                }
+            }
+            self.expect(Token::RBrace)?;
+        }
+
+        self.expect(Token::RParen)?;
+        Ok(NodePattern {
+            variable,
+            type_name,
+            properties,
+            offset,
+        })
+    }
+
+    fn parse_relationship(&mut self) -> Result<RelPattern, ParseError> {
+        let offset = self.offset();
+        let incoming = if *self.peek() == Token::Lt {
+            self.advance();
+            true
+        } else {
+            false
+        };
+        self.expect(Token::Minus)?;
+
+        let mut variable = None;
+        let mut label = None;
+        if *self.peek() == Token::LBracket {
+            self.advance();
+            if let Token::Ident(_) = self.peek() {
+                variable = Some(self.identifier("a variable")?);
+            }
+            if *self.peek() == Token::Colon {
+                self.advance();
+                label = Some(self.identifier("an edge label")?);
+            }
+            if *self.peek() == Token::Star {
+                return Err(self.error("Variable-length relationships are not supported; spell out each step"));
+            }
+            self.expect(Token::RBracket)?;
+        }
+
+        self.expect(Token::Minus)?;
+        let outgoing = if *self.peek() == Token::Gt {
+            self.advance();
+            true
+        } else {
+            false
+        };
+
+        let direction = match (incoming, outgoing) {
+            (true, true) => {
+                return Err(ParseError::new(
+                    self.source,
+                    offset,
+                    "A relationship cannot point both ways",
+                ))
+            }
+            (true, false) => Direction::Incoming,
+            (false, true) => Direction::Outgoing,
+            (false, false) => Direction::Both,
+        };
+
+        Ok(RelPattern {
+            variable,
+            label,
+            direction,
+            offset,
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Condition, ParseError> {
+        let mut terms = vec![self.parse_and()?];
+        while self.is_keyword("OR") {
+            self.advance();
+            terms.push(self.parse_and()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Condition::Or(terms)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Condition, ParseError> {
+        let mut terms = vec![self.parse_not()?];
+        while self.is_keyword("AND") {
+            self.advance();
+            terms.push(self.parse_not()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Condition::And(terms)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Condition, ParseError> {
+        if self.is_keyword("NOT") {
+            self.advance();
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+        if *self.peek() == Token::LParen {
+            self.advance();
+            let condition = self.parse_or()?;
+            self.expect(Token::RParen)?;
+            return Ok(condition);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Condition, ParseError> {
+        let offset = self.offset();
+        let variable = self.identifier("a condition like n.property = value")?;
+        self.expect(Token::Dot)?;
+        let property = self.identifier("a property name")?;
+
+        let (operator, negated) = match self.peek().clone() {
+            Token::Eq => (FilterOperator::Equals, false),
+            Token::Ne => (FilterOperator::Equals, true),
+            Token::Lt => (FilterOperator::LessThan, false),
+            Token::Gt => (FilterOperator::GreaterThan, false),
+            Token::Le => (FilterOperator::LessThanOrEqual, false),
+            Token::Ge => (FilterOperator::GreaterThanOrEqual, false),
+            Token::Ident(word) if word.eq_ignore_ascii_case("CONTAINS") => {
+                (FilterOperator::Contains, false)
+            }
+            Token::Ident(word) if word.eq_ignore_ascii_case("STARTS") => {
+                self.advance();
+                self.expect_keyword("WITH")?;
+                return self.finish_comparison(variable, property, FilterOperator::StartsWith, offset);
+            }
+            Token::Ident(word) if word.eq_ignore_ascii_case("ENDS") => {
+                self.advance();
+                self.expect_keyword("WITH")?;
+                return self.finish_comparison(variable, property, FilterOperator::EndsWith, offset);
+            }
+            _ => return Err(self.unexpected("a comparison operator")),
+        };
+        self.advance();
+
+        let value = self.parse_value()?;
+        Ok(Condition::Compare {
+            variable,
+            property,
+            operator,
+            negated,
+            value,
+            offset,
+        })
+    }
+
+    fn finish_comparison(
+        &mut self,
+        variable: String,
+        property: String,
+        operator: FilterOperator,
+        offset: usize,
+    ) -> Result<Condition, ParseError> {
+        let value = self.parse_value()?;
+        Ok(Condition::Compare {
+            variable,
+            property,
+            operator,
+            negated: false,
+            value,
+            offset,
+        })
+    }
+
+    fn parse_value(&mut self) -> Result<PropertyValue, ParseError> {
+        let offset = self.offset();
+        match self.advance() {
+            Token::Str(s) => Ok(PropertyValue::String(s)),
+            Token::Number(n) => Ok(PropertyValue::Number(n)),
+            Token::Minus => match self.advance() {
+                Token::Number(n) => Ok(PropertyValue::Number(-n)),
+                _ => Err(ParseError::new(self.source, offset, "Expected a number after '-'")),
+            },
+            Token::LBracket => {
+                let mut items = Vec::new();
+                if *self.peek() != Token::RBracket {
+                    loop {
+                        items.push(self.parse_value()?);
+                        if *self.peek() != Token::Comma {
+                            break;
+                        }
+                        self.advance();
+                    }
+                }
+                self.expect(Token::RBracket)?;
+                Ok(PropertyValue::List(items))
+            }
+            Token::Ident(word) if word.eq_ignore_ascii_case("true") => Ok(PropertyValue::Boolean(true)),
+            Token::Ident(word) if word.eq_ignore_ascii_case("false") => {
+                Ok(PropertyValue::Boolean(false))
+            }
+            Token::Ident(function) => {
+                self.expect(Token::LParen)?;
+                let argument_offset = self.offset();
+                let argument = self.advance();
+                self.expect(Token::RParen)?;
+                let invalid = |message: &str| ParseError::new(self.source, argument_offset, message);
+
+                match (function.to_ascii_lowercase().as_str(), argument) {
+                    ("datetime" | "date", Token::Number(n)) => Ok(PropertyValue::DateTime(n as i64)),
+                    ("datetime" | "date", Token::Str(s)) => parse_datetime(&s)
+                        .map(PropertyValue::DateTime)
+                        .ok_or_else(|| invalid("Expected an RFC 3339 date-time or YYYY-MM-DD date")),
+                    ("ref", Token::Str(s)) => uuid::Uuid::parse_str(&s)
+                        .map(|id| PropertyValue::Reference(NodeId::from_uuid(id)))
+                        .map_err(|_| invalid("Expected a node id")),
+                    ("datetime" | "date" | "ref", _) => Err(invalid("Invalid function argument")),
+                    _ => Err(ParseError::new(
+                        self.source,
+                        offset,
+                        format!("Unknown function '{}'", function),
+                    )),
+                }
+            }
+            other => Err(ParseError::new(
+                self.source,
+                offset,
+                format!("Expected a value, found {}", other),
+            )),
+        }
+    }
+}
+
+fn parse_datetime(text: &str) -> Option<i64> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Some(dt.timestamp());
+    }
+    chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp())
+}
+
+impl TextQuery {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        parser.parse_query()
+    }
+
+    /// Parses and compiles in one step; errors point into `source`.
+    pub fn compile_str(source: &str) -> Result<CompiledQuery, ParseError> {
+        Self::parse(source)?.compile(source)
+    }
+
+    /// Translates the query into the engine's query model. `source` is only used to
+    /// position semantic errors.
+    pub fn compile(&self, source: &str) -> Result<CompiledQuery, ParseError> {
+        let error = |offset: usize, message: String| ParseError::new(source, offset, message);
+
+        // Name every position so that conditions and RETURN items can be resolved
+        let node_vars: Vec<String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| n.variable.clone().unwrap_or_else(|| format!("_n{}", i)))
+            .collect();
+        let rel_vars: Vec<String> = self
+            .relationships
+            .iter()
+            .enumerate()
+            .map(|(i, r)| r.variable.clone().unwrap_or_else(|| format!("_r{}", i)))
+            .collect();
+
+        let mut seen = HashSet::new();
+        for (variable, offset) in node_vars
+            .iter()
+            .zip(self.nodes.iter().map(|n| n.offset))
+            .chain(rel_vars.iter().zip(self.relationships.iter().map(|r| r.offset)))
+        {
+            if !seen.insert(variable.as_str()) {
+                return Err(error(offset, format!("Variable '{}' is bound more than once", variable)));
+            }
+        }
+
+        let mut filters: Vec<Vec<FilterExpr>> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut terms = Vec::new();
+                if let Some(type_name) = &node.type_name {
+                    terms.push(FilterExpr::Filter(NodeFilter::new(
+                        "type",
+                        FilterOperator::Equals,
+                        PropertyValue::String(type_name.clone()),
+                    )));
+                }
+                for (name, value) in &node.properties {
+                    terms.push(FilterExpr::Filter(NodeFilter::new(
+                        name.clone(),
+                        FilterOperator::Equals,
+                        value.clone(),
+                    )));
+                }
+                terms
+            })
+            .collect();
+
+        if let Some(condition) = &self.condition {
+            let conjuncts = match condition {
+                Condition::And(terms) => terms.clone(),
+                other => vec![other.clone()],
+            };
+            for conjunct in conjuncts {
+                let mut variables = Vec::new();
+                collect_variables(&conjunct, &mut variables);
+                let (first_var, first_offset) = variables[0].clone();
+                if let Some((other, offset)) = variables.iter().find(|(v, _)| *v != first_var) {
+                    return Err(error(
+                        *offset,
+                        format!(
+                            "Condition mixes variables '{}' and '{}'; each AND term must use one variable",
+                            first_var, other
+                        ),
+                    ));
+                }
+                if rel_vars.contains(&first_var) {
+                    return Err(error(
+                        first_offset,
+                        format!("Conditions on relationship '{}' are not supported", first_var),
+                    ));
+                }
+                let index = node_vars
+                    .iter()
+                    .position(|v| *v == first_var)
+                    .ok_or_else(|| error(first_offset, format!("Unknown variable '{}'", first_var)))?;
+                filters[index].push(to_filter_expr(&conjunct));
+            }
+        }
+
+        let mut columns = Vec::new();
+        let mut returns = Vec::new();
+        if self.returns.is_empty() {
+            for (i, variable) in node_vars.iter().enumerate() {
+                if self.nodes[i].variable.is_some() {
+                    columns.push(variable.clone());
+                    returns.push(Projection::Node(i, None));
+                }
+            }
+            for (i, variable) in rel_vars.iter().enumerate() {
+                if self.relationships[i].variable.is_some() {
+                    columns.push(variable.clone());
+                    returns.push(Projection::Edge(i));
+                }
+            }
+        } else {
+            for item in &self.returns {
+                let column = match &item.property {
+                    Some(property) => format!("{}.{}", item.variable, property),
+                    None => item.variable.clone(),
+                };
+                let projection = if let Some(i) = node_vars.iter().position(|v| *v == item.variable) {
+                    Projection::Node(i, item.property.clone())
+                } else if let Some(i) = rel_vars.iter().position(|v| *v == item.variable) {
+                    if item.property.is_some() {
+                        return Err(error(item.offset, "Returning relationship properties is not supported".to_string()));
+                    }
+                    Projection::Edge(i)
+                } else {
+                    return Err(error(item.offset, format!("Unknown variable '{}'", item.variable)));
+                };
+                columns.push(column);
+                returns.push(projection);
+            }
+        }
+
+        let mut filters: Vec<Option<FilterExpr>> = filters
+            .into_iter()
+            .map(|mut terms| match terms.len() {
+                0 => None,
+                1 => terms.pop(),
+                _ => Some(FilterExpr::And(terms)),
+            })
+            .collect();
+
+        let plan = if self.relationships.is_empty() {
+            Plan::Nodes(GraphPattern {
+                filter: filters.remove(0),
+                limit: self.limit,
+                ..Default::default()
+            })
+        } else {
+            let start_filter = Some(filters.remove(0).unwrap_or(FilterExpr::And(vec![])));
+            let steps = self
+                .relationships
+                .iter()
+                .zip(filters)
+                .map(|(rel, filter)| PathStep {
+                    label: rel.label.clone(),
+                    direction: rel.direction,
+                    filter,
+                })
+                .collect();
+            Plan::Paths(PathQuery {
+                start: None,
+                start_filter,
+                steps,
+                limit: self.limit.unwrap_or(DEFAULT_PATH_LIMIT),
+            })
+        };
+
+        Ok(CompiledQuery {
+            plan,
+            columns,
+            returns,
+        })
+    }
+}
+
+fn collect_variables(condition: &Condition, variables: &mut Vec<(String, usize)>) {
+    match condition {
+        Condition::Compare {
+            variable, offset, ..
+        } => variables.push((variable.clone(), *offset)),
+        Condition::And(terms) | Condition::Or(terms) => {
+            for term in terms {
+                collect_variables(term, variables);
+            }
+        }
+        Condition::Not(inner) => collect_variables(inner, variables),
+    }
+}
+
+fn to_filter_expr(condition: &Condition) -> FilterExpr {
+    match condition {
+        Condition::Compare {
+            property,
+            operator,
+            negated,
+            value,
+            ..
+        } => {
+            let filter = FilterExpr::Filter(NodeFilter::new(property.clone(), *operator, value.clone()));
+            if *negated {
+                FilterExpr::Not(Box::new(filter))
+            } else {
+                filter
+            }
+        }
+        Condition::And(terms) => FilterExpr::And(terms.iter().map(to_filter_expr).collect()),
+        Condition::Or(terms) => FilterExpr::Or(terms.iter().map(to_filter_expr).collect()),
+        Condition::Not(inner) => FilterExpr::Not(Box::new(to_filter_expr(inner))),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Plan {
+    Nodes(GraphPattern),
+    Paths(PathQuery),
+}
+
+#[derive(Debug, Clone)]
+enum Projection {
+    /// Node at a pattern position, optionally a single property of it.
+    Node(usize, Option<String>),
+    Edge(usize),
+}
+
+/// A text query translated to the engine's query model, ready to run.
+#[derive(Debug, Clone)]
+pub struct CompiledQuery {
+    pub plan: Plan,
+    pub columns: Vec<String>,
+    returns: Vec<Projection>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Binding {
+    Node(Entity),
+    Edge(Edge),
+    Value(Option<PropertyValue>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextQueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Binding>>,
+}
+
+impl CompiledQuery {
+    pub async fn execute(&self, engine: &(dyn GraphEngine + Send + Sync)) -> Result<TextQueryResult> {
+        let mut rows = Vec::new();
+        match &self.plan {
+            Plan::Nodes(pattern) => {
+                for node in engine.query(pattern).await?.nodes {
+                    rows.push(self.project(std::slice::from_ref(&node), &[]));
+                }
+            }
+            Plan::Paths(query) => {
+                for path in engine.find_paths(query).await? {
+                    rows.push(self.project(&path.nodes, &path.edges));
+                }
+            }
+        }
+        Ok(TextQueryResult {
+            columns: self.columns.clone(),
+            rows,
+        })
+    }
+
+    fn project(&self, nodes: &[Entity], edges: &[Edge]) -> Vec<Binding> {
+        self.returns
+            .iter()
+            .map(|projection| match projection {
+                Projection::Node(i, None) => Binding::Node(nodes[*i].clone()),
+                Projection::Node(i, Some(property)) => Binding::Value(
+                    resolve_property(&nodes[*i], property).map(|v| v.into_owned()),
+                ),
+                Projection::Edge(i) => Binding::Edge(edges[*i].clone()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path_query() {
+        let query = TextQuery::parse(
+            r#"MATCH (n:email {from: "x"})-[r:reply]->(m)<-[:authored]-(p)
+               WHERE n.size > 10 AND NOT m.archived = true
+               RETURN n, m.subject LIMIT 5"#,
+        )
+        .unwrap();
+
+        assert_eq!(query.nodes.len(), 3);
+        assert_eq!(query.nodes[0].type_name.as_deref(), Some("email"));
+        assert_eq!(query.relationships[0].direction, Direction::Outgoing);
+        assert_eq!(query.relationships[1].direction, Direction::Incoming);
+        assert_eq!(query.relationships[1].label.as_deref(), Some("authored"));
+        assert_eq!(query.returns.len(), 2);
+        assert_eq!(query.limit, Some(5));
+
+        let compiled = query.compile("").unwrap();
+        assert_eq!(compiled.columns, vec!["n", "m.subject"]);
+        match compiled.plan {
+            Plan::Paths(paths) => {
+                assert_eq!(paths.steps.len(), 2);
+                assert!(paths.steps[0].filter.is_some());
+                assert!(paths.steps[1].filter.is_none());
+            }
+            Plan::Nodes(_) => panic!("expected a path plan"),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let err = TextQuery::parse("MATCH (n)\nWHERE n.size >> 3").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, 15);
+
+        let err = TextQuery::compile_str("MATCH (n)-->(m) WHERE n.a = 1 OR m.b = 2").unwrap_err();
+        assert_eq!(err.column, 34);
+
+        let err = TextQuery::parse("MATCH (n {name: \"x)").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("Unterminated string literal", 17));
+        let err = TextQuery::parse("MATCH (n) RETURN n LIMIT -1").unwrap_err();
+        assert_eq!(err.column, 26);
+        let err = TextQuery::parse("MATCH (n) RETURN n LIMIT 5 n").unwrap_err();
+        assert_eq!(err.column, 28);
+    }
+
+    #[test]
+    fn test_or_and_not() {
+        let query = TextQuery::parse("MATCH (n) WHERE n.a = 1 OR NOT (n.b = 2 AND n.c <> 3)").unwrap();
+        match query.condition {
+            Some(Condition::Or(terms)) => {
+                assert_eq!(terms.len(), 2);
+                match &terms[1] {
+                    Condition::Not(inner) => match inner.as_ref() {
+                        Condition::And(terms) => {
+                            assert!(matches!(terms[1], Condition::Compare { negated: true, .. }))
+                        }
+                        other => panic!("expected AND, got {:?}", other),
+                    },
+                    other => panic!("expected NOT, got {:?}", other),
+                }
+            }
+            other => panic!("expected OR, got {:?}", other),
+        }
+
+        let compiled = TextQuery::compile_str("MATCH (n) WHERE n.a = 1 OR NOT n.b = 2").unwrap();
+        match compiled.plan {
+            Plan::Nodes(pattern) => match pattern.filter {
+                Some(FilterExpr::Or(terms)) => assert!(matches!(terms[1], FilterExpr::Not(_))),
+                other => panic!("expected an OR filter, got {:?}", other),
+            },
+            Plan::Paths(_) => panic!("expected a node plan"),
+        }
+    }
+
+    #[test]
+    fn test_conditions_on_several_variables_are_rejected() {
+        // Separate AND terms may use different variables, a single term may not
+        assert!(TextQuery::compile_str("MATCH (n)-->(m) WHERE n.a = 1 AND m.b = 2").is_ok());
+        let err = TextQuery::compile_str("MATCH (n)-->(m) WHERE NOT (n.a = 1 AND m.b = 2)").unwrap_err();
+        assert!(err.message.contains("mixes variables 'n' and 'm'"));
+        assert_eq!(err.column, 40);
+
+        let err = TextQuery::compile_str("MATCH (n)-[r]->(m) WHERE r.weight > 1").unwrap_err();
+        assert_eq!(err.column, 26);
+        let err = TextQuery::compile_str("MATCH (n) WHERE x.a = 1").unwrap_err();
+        assert_eq!(err.message, "Unknown variable 'x'");
+    }
+
+    #[test]
+    fn test_variable_length_relationships_are_rejected() {
+        for source in ["MATCH (n)-[:reply*]->(m)", "MATCH (n)-[r*2]->(m)"] {
+            let err = TextQuery::parse(source).unwrap_err();
+            assert!(err.message.starts_with("Variable-length relationships are not supported"));
+            assert_eq!(&source[err.offset..err.offset + 1], "*");
+        }
+        // A range does not even tokenize
+        let err = TextQuery::parse("MATCH (n)-[:reply*1..3]->(m)").unwrap_err();
+        assert_eq!(err.column, 19);
+    }
+
+    #[tokio::test]
+    async fn test_limit_and_missing_properties() {
+        use crate::engine::DefaultGraphEngine;
+        use crate::storage::GraphStorage;
+        use std::collections::HashMap;
+
+        let engine = DefaultGraphEngine::new(GraphStorage::in_memory().unwrap());
+        for i in 0..3 {
+            let mut properties = HashMap::new();
+            properties.insert("type".to_string(), PropertyValue::String("email".to_string()));
+            if i == 0 {
+                properties.insert("subject".to_string(), PropertyValue::String("hello".to_string()));
+            }
+            engine
+                .put_node(Entity {
+                    id: NodeId::new(),
+                    label: format!("mail {}", i),
+                    properties,
+                    created_at: 0,
+                    updated_at: 0,
+                    version: 1,
+                    graph: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let result = TextQuery::compile_str("MATCH (n:email) RETURN n.subject LIMIT 2")
+            .unwrap()
+            .execute(&engine)
+            .await
+            .unwrap();
+        assert_eq!(result.rows.len(), 2);
+
+        let result = TextQuery::compile_str("MATCH (n:email) RETURN n.subject, n.label")
+            .unwrap()
+            .execute(&engine)
+            .await
+            .unwrap();
+        assert_eq!(result.columns, vec!["n.subject", "n.label"]);
+        assert_eq!(result.rows.len(), 3);
+        let missing = result
+            .rows
+            .iter()
+            .filter(|row| matches!(row[0], Binding::Value(None)))
+            .count();
+        assert_eq!(missing, 2);
+        assert!(result.rows.iter().all(|row| matches!(row[1], Binding::Value(Some(_)))));
+        let json = serde_json::to_value(&result.rows).unwrap();
+        assert!(json.as_array().unwrap().iter().any(|row| row[0].is_null()));
+    }
+}
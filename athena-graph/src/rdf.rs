@@ -0,0 +1,415 @@
+use crate::entity::{Edge, Entity, NodeId, PropertyValue};
+use crate::named_graph::{graph_of, DEFAULT_GRAPH};
+use crate::storage::GraphStorage;
+use anyhow::Result;
+use oxigraph::model::vocab::{rdf, rdfs, xsd};
+use oxigraph::model::{GraphName, Literal, NamedNode, Quad, Subject, Term};
+use oxigraph::sparql::results::QueryResultsFormat;
+use oxigraph::sparql::{Query, QueryResults};
+use oxigraph::store::Store;
+use serde::{Deserialize, Serialize};
+
+pub const ATHENA_NS: &str = "https://athena-os.org/ns#";
+pub const PROPERTY_NS: &str = "https://athena-os.org/prop/";
+pub const RELATION_NS: &str = "https://athena-os.org/rel/";
+pub const GRAPH_NS: &str = "https://athena-os.org/graph/";
+/// GeoSPARQL datatype of points, written longitude first.
+const WKT_LITERAL: &str = "http://www.opengis.net/ont/geosparql#wktLiteral";
+
+/// Serialized result of a SPARQL query: SPARQL JSON results for SELECT/ASK,
+/// N-Triples for CONSTRUCT/DESCRIBE.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SparqlResponse {
+    pub content_type: String,
+    pub body: String,
+}
+
+/// Read-only RDF view of the property graph, kept in an in-memory oxigraph store.
+///
+/// Nodes and edges are identified by `urn:uuid:` IRIs. Every node is an `athena:Node` with
+/// an `rdfs:label`, timestamps and one triple per property under `PROPERTY_NS`. Every edge
+/// yields a direct `from <RELATION_NS + label> to` triple plus an `athena:Edge` resource
+/// carrying its endpoints and properties.
+///
+/// Records of a named graph go into the RDF named graph `GRAPH_NS + name`, the default graph
+/// into the default graph. Queries without `FROM` see the union of all of them.
+pub struct RdfProjection {
+    store: Store,
+}
+
+impl RdfProjection {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            store: Store::new()?,
+        })
+    }
+
+    pub fn load(storage: &GraphStorage) -> Result<Self> {
+        let projection = Self::new()?;
+        for node in storage.iter_nodes() {
+            projection.insert(&entity_quads(&node?))?;
+        }
+        for edge in storage.iter_edges() {
+            projection.insert(&edge_quads(&edge?))?;
+        }
+        Ok(projection)
+    }
+
+    pub fn put_node(&self, old: Option<&Entity>, new: &Entity) -> Result<()> {
+        if let Some(old) = old {
+            self.remove(&entity_quads(old))?;
+        }
+        self.insert(&entity_quads(new))
+    }
+
+    pub fn delete_node(&self, old: &Entity) -> Result<()> {
+        self.remove(&entity_quads(old))
+    }
+
+    pub fn put_edge(&self, old: Option<&Edge>, new: &Edge) -> Result<()> {
+        if let Some(old) = old {
+            self.remove(&edge_quads(old))?;
+        }
+        self.insert(&edge_quads(new))
+    }
+
+    pub fn delete_edge(&self, old: &Edge) -> Result<()> {
+        self.remove(&edge_quads(old))
+    }
+
+    pub fn query(&self, query: &str) -> Result<SparqlResponse> {
+        let mut query = Query::parse(query, None).map_err(|e| anyhow::anyhow!("SPARQL error: {}", e))?;
+        if query.dataset().is_default_dataset() {
+            query.dataset_mut().set_default_graph_as_union();
+        }
+        let results = self
+            .store
+            .query(query)
+            .map_err(|e| anyhow::anyhow!("SPARQL error: {}", e))?;
+
+        let (content_type, body) = match results {
+            QueryResults::Graph(_) => (
+                "application/n-triples",
+                results.write_graph(Vec::new(), oxigraph::io::RdfFormat::NTriples)?,
+            ),
+            _ => (
+                "application/sparql-results+json",
+                results.write(Vec::new(), QueryResultsFormat::Json)?,
+            ),
+        };
+
+        Ok(SparqlResponse {
+            content_type: content_type.to_string(),
+            body: String::from_utf8(body)?,
+        })
+    }
+
+    fn insert(&self, quads: &[Quad]) -> Result<()> {
+        for quad in quads {
+            self.store.insert(quad)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, quads: &[Quad]) -> Result<()> {
+        for quad in quads {
+            self.store.remove(quad)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn node_iri(id: &NodeId) -> NamedNode {
+    NamedNode::new_unchecked(format!("urn:uuid:{}", id.0))
+}
+
+pub fn edge_iri(id: &uuid::Uuid) -> NamedNode {
+    NamedNode::new_unchecked(format!("urn:uuid:{}", id))
+}
+
+pub fn property_iri(name: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{}{}", PROPERTY_NS, encode_iri_segment(name)))
+}
+
+pub fn relation_iri(label: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{}{}", RELATION_NS, encode_iri_segment(label)))
+}
+
+pub fn graph_iri(name: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{}{}", GRAPH_NS, encode_iri_segment(name)))
+}
+
+/// Moves quads built in the default graph into the record's named graph.
+fn in_graph(quads: Vec<Quad>, graph: Option<&str>) -> Vec<Quad> {
+    match graph_of(graph) {
+        DEFAULT_GRAPH => quads,
+        name => {
+            let graph = GraphName::NamedNode(graph_iri(name));
+            quads
+                .into_iter()
+                .map(|quad| Quad {
+                    graph_name: graph.clone(),
+                    ..quad
+                })
+                .collect()
+        }
+    }
+}
+
+fn athena(term: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{}{}", ATHENA_NS, term))
+}
+
+pub fn entity_quads(entity: &Entity) -> Vec<Quad> {
+    let subject = node_iri(&entity.id);
+    let mut quads = vec![
+        quad(&subject, rdf::TYPE.into(), athena("Node").into()),
+        quad(&subject, rdfs::LABEL.into(), Literal::new_simple_literal(&entity.label).into()),
+        quad(&subject, athena("createdAt"), datetime_literal(entity.created_at).into()),
+        quad(&subject, athena("updatedAt"), datetime_literal(entity.updated_at).into()),
+        quad(&subject, athena("version"), integer_literal(entity.version).into()),
+    ];
+    for (name, value) in &entity.properties {
+        property_quads(&subject, name, value, &mut quads);
+    }
+    in_graph(quads, entity.graph.as_deref())
+}
+
+pub fn edge_quads(edge: &Edge) -> Vec<Quad> {
+    let subject = edge_iri(&edge.id);
+    let from = node_iri(&edge.from);
+    let to = node_iri(&edge.to);
+    let mut quads = vec![
+        quad(&from, relation_iri(&edge.label), to.clone().into()),
+        quad(&subject, rdf::TYPE.into(), athena("Edge").into()),
+        quad(&subject, athena("from"), from.into()),
+        quad(&subject, athena("to"), to.into()),
+        quad(&subject, rdfs::LABEL.into(), Literal::new_simple_literal(&edge.label).into()),
+        quad(&subject, athena("createdAt"), datetime_literal(edge.created_at).into()),
+        quad(&subject, athena("version"), integer_literal(edge.version).into()),
+    ];
+    for (name, value) in &edge.properties {
+        property_quads(&subject, name, value, &mut quads);
+    }
+    in_graph(quads, edge.graph.as_deref())
+}
+
+fn property_quads(subject: &NamedNode, name: &str, value: &PropertyValue, quads: &mut Vec<Quad>) {
+    let predicate = property_iri(name);
+    match value {
+        PropertyValue::List(items) => {
+            for item in items {
+                property_quads(subject, name, item, quads);
+            }
+        }
+        PropertyValue::Map(map) => {
+            // Nested maps become a resource with a deterministic IRI so that the exact same
+            // quads can be regenerated (and removed) from the entity alone
+            let nested = NamedNode::new_unchecked(format!(
+                "{}/{}",
+                subject.as_str(),
+                encode_iri_segment(name)
+            ));
+            quads.push(quad(subject, predicate, nested.clone().into()));
+            for (key, value) in map {
+                property_quads(&nested, key, value, quads);
+            }
+        }
+        scalar => {
+            if let Some(term) = scalar_term(scalar) {
+                quads.push(quad(subject, predicate, term));
+            }
+        }
+    }
+}
+
+fn scalar_term(value: &PropertyValue) -> Option<Term> {
+    match value {
+        PropertyValue::String(s) => Some(Literal::new_simple_literal(s).into()),
+        PropertyValue::Number(n) => Some(Literal::from(*n).into()),
+        PropertyValue::Boolean(b) => Some(Literal::from(*b).into()),
+        PropertyValue::DateTime(t) => Some(datetime_literal(*t).into()),
+        PropertyValue::Reference(id) => Some(node_iri(id).into()),
+        PropertyValue::GeoPoint(point) => Some(
+            Literal::new_typed_literal(
+                format!("POINT({} {})", point.lon, point.lat),
+                NamedNode::new_unchecked(WKT_LITERAL),
+            )
+            .into(),
+        ),
+        PropertyValue::List(_) | PropertyValue::Map(_) => None,
+    }
+}
+
+fn quad(subject: &NamedNode, predicate: NamedNode, object: Term) -> Quad {
+    Quad::new(
+        Subject::NamedNode(subject.clone()),
+        predicate,
+        object,
+        GraphName::DefaultGraph,
+    )
+}
+
+fn datetime_literal(timestamp: i64) -> Literal {
+    let formatted = chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| timestamp.to_string());
+    Literal::new_typed_literal(formatted, xsd::DATE_TIME)
+}
+
+fn integer_literal(value: u64) -> Literal {
+    Literal::new_typed_literal(value.to_string(), xsd::INTEGER)
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters.
+fn encode_iri_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn node(label: &str) -> Entity {
+        Entity {
+            id: NodeId::new(),
+            label: label.to_string(),
+            properties: HashMap::from([
+                ("size".to_string(), PropertyValue::Number(1024.0)),
+                (
+                    "tags".to_string(),
+                    PropertyValue::List(vec![
+                        PropertyValue::String("work".to_string()),
+                        PropertyValue::String("tax".to_string()),
+                    ]),
+                ),
+                (
+                    "headers".to_string(),
+                    PropertyValue::Map(HashMap::from([(
+                        "from".to_string(),
+                        PropertyValue::String("a@example.com".to_string()),
+                    )])),
+                ),
+            ]),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    fn select(projection: &RdfProjection, query: &str) -> Vec<serde_json::Value> {
+        let response = projection.query(query).unwrap();
+        assert_eq!(response.content_type, "application/sparql-results+json");
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        body["results"]["bindings"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_writes_and_deletes_remove_exactly_their_quads() {
+        let projection = RdfProjection::new().unwrap();
+        let (a, b) = (node("a"), node("b"));
+        projection.put_node(None, &a).unwrap();
+        projection.put_node(None, &b).unwrap();
+        let edge = Edge {
+            id: uuid::Uuid::new_v4(),
+            from: a.id.clone(),
+            to: b.id.clone(),
+            label: "reply to".to_string(),
+            properties: HashMap::from([("weight".to_string(), PropertyValue::Number(0.5))]),
+            created_at: 0,
+            version: 1,
+            graph: None,
+        };
+        projection.put_edge(None, &edge).unwrap();
+        let nodes_only = entity_quads(&a).len() + entity_quads(&b).len();
+        assert_eq!(projection.store.len().unwrap(), nodes_only + edge_quads(&edge).len());
+
+        // Rewriting a node swaps its quads, leaving the other records alone
+        let mut renamed = a.clone();
+        renamed.label = "renamed".to_string();
+        renamed.properties.remove("tags");
+        projection.put_node(Some(&a), &renamed).unwrap();
+        let labels = select(
+            &projection,
+            &format!("SELECT ?l WHERE {{ <urn:uuid:{}> <{}> ?l }}", a.id.0, rdfs::LABEL.as_str()),
+        );
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0]["l"]["value"], "renamed");
+
+        projection.delete_edge(&edge).unwrap();
+        assert_eq!(
+            projection.store.len().unwrap(),
+            entity_quads(&renamed).len() + entity_quads(&b).len()
+        );
+        projection.delete_node(&renamed).unwrap();
+        projection.delete_node(&b).unwrap();
+        assert!(projection.store.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_nested_maps_graphs_and_content_types() {
+        let projection = RdfProjection::new().unwrap();
+        let mut work = node("report");
+        work.graph = Some("work".to_string());
+        projection.put_node(None, &work).unwrap();
+        // A user property of the same name is just a property
+        let mut home = node("recipe");
+        home.properties
+            .insert("graph".to_string(), PropertyValue::String("work".to_string()));
+        projection.put_node(None, &home).unwrap();
+
+        // A nested map is a resource under the node's IRI, one segment per property name
+        let nested = select(
+            &projection,
+            &format!(
+                "SELECT ?map ?from WHERE {{ <urn:uuid:{}> <{}headers> ?map . ?map <{}from> ?from }}",
+                work.id.0, PROPERTY_NS, PROPERTY_NS
+            ),
+        );
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0]["map"]["value"], format!("urn:uuid:{}/headers", work.id.0));
+        assert_eq!(nested[0]["from"]["value"], "a@example.com");
+        let tags = select(
+            &projection,
+            &format!("SELECT ?tag WHERE {{ <urn:uuid:{}> <{}tags> ?tag }}", work.id.0, PROPERTY_NS),
+        );
+        assert_eq!(tags.len(), 2);
+
+        // Named graph records land in their own graph; queries without FROM see all of them
+        let graphs = select(
+            &projection,
+            &format!("SELECT ?g WHERE {{ GRAPH ?g {{ <urn:uuid:{}> ?p ?o }} }} LIMIT 1", work.id.0),
+        );
+        assert_eq!(graphs[0]["g"]["value"], format!("{}work", GRAPH_NS));
+        let in_named = select(
+            &projection,
+            &format!("SELECT ?s WHERE {{ GRAPH ?g {{ <urn:uuid:{}> ?p ?o }} }}", home.id.0),
+        );
+        assert!(in_named.is_empty());
+        let all = select(&projection, &format!("SELECT DISTINCT ?s WHERE {{ ?s a <{}Node> }}", ATHENA_NS));
+        assert_eq!(all.len(), 2);
+        let scoped = select(
+            &projection,
+            &format!("SELECT DISTINCT ?s FROM <{}work> WHERE {{ ?s a <{}Node> }}", GRAPH_NS, ATHENA_NS),
+        );
+        assert_eq!(scoped.len(), 1);
+
+        let response = projection
+            .query(&format!("CONSTRUCT {{ ?s a <{}Node> }} WHERE {{ ?s a <{}Node> }}", ATHENA_NS, ATHENA_NS))
+            .unwrap();
+        assert_eq!(response.content_type, "application/n-triples");
+        assert_eq!(response.body.lines().count(), 2);
+        assert!(projection.query("SELECT WHERE").is_err());
+    }
+}
@@ -0,0 +1,547 @@
+use crate::entity::{Edge, Entity, NodeId, PropertyValue};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Property naming a node's entity type, e.g. `"type": "email"`. Nodes without it are untyped.
+pub const TYPE_PROPERTY: &str = "type";
+/// Entity type of the nodes that store user-defined schemas.
+pub const SCHEMA_TYPE: &str = "Schema";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PropertyType {
+    String,
+    Number,
+    Boolean,
+    DateTime,
+    Reference,
+    List,
+    Map,
+    GeoPoint,
+    Any,
+}
+
+impl PropertyType {
+    pub fn accepts(&self, value: &PropertyValue) -> bool {
+        matches!(
+            (self, value),
+            (PropertyType::Any, _)
+                | (PropertyType::String, PropertyValue::String(_))
+                | (PropertyType::Number, PropertyValue::Number(_))
+                | (PropertyType::Boolean, PropertyValue::Boolean(_))
+                | (PropertyType::DateTime, PropertyValue::DateTime(_))
+                | (PropertyType::Reference, PropertyValue::Reference(_))
+                | (PropertyType::List, PropertyValue::List(_))
+                | (PropertyType::Map, PropertyValue::Map(_))
+                | (PropertyType::GeoPoint, PropertyValue::GeoPoint(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertySchema {
+    #[serde(rename = "type")]
+    pub value_type: PropertyType,
+    #[serde(default)]
+    pub required: bool,
+    /// The value is a list whose items all have `value_type`.
+    #[serde(default)]
+    pub list: bool,
+}
+
+/// Allows edges labelled `label` from this type to nodes of type `target` or its subtypes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeRule {
+    pub label: String,
+    pub target: String,
+}
+
+/// A named entity type. Properties and edge rules are inherited from `parent`; a type may
+/// redeclare an inherited property to change it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityType {
+    pub name: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Other values of the `type` property meaning this type, e.g. `email` for `EmailMessage`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// IRIs of the same class in public vocabularies such as schema.org and FOAF.
+    #[serde(default)]
+    pub same_as: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub properties: BTreeMap<String, PropertySchema>,
+    /// Edge labels with a rule must point at one of the rule targets. Other labels are
+    /// allowed unless the type is strict.
+    #[serde(default)]
+    pub edges: Vec<EdgeRule>,
+    /// Rejects properties and outgoing edge labels that the type and its ancestors do not declare.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// A stored record that does not conform to the schema of its type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaViolation {
+    pub node: Uuid,
+    /// Set when the violation is an edge of `node`.
+    #[serde(default)]
+    pub edge: Option<Uuid>,
+    pub message: String,
+}
+
+/// Built-in and stored entity types, looked up by name or alias.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    types: BTreeMap<String, EntityType>,
+    aliases: HashMap<String, String>,
+}
+
+impl SchemaRegistry {
+    /// The built-in types: a schema.org/FOAF core plus the types the importers write.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        for entity_type in builtin_types() {
+            registry.insert(entity_type);
+        }
+        registry
+    }
+
+    /// Adds or replaces a type. Stored types replace built-in ones of the same name.
+    pub fn insert(&mut self, entity_type: EntityType) {
+        self.remove(&entity_type.name);
+        for alias in &entity_type.aliases {
+            self.aliases.insert(alias.clone(), entity_type.name.clone());
+        }
+        self.types.insert(entity_type.name.clone(), entity_type);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<EntityType> {
+        let removed = self.types.remove(name)?;
+        self.aliases.retain(|_, target| target != name);
+        Some(removed)
+    }
+
+    /// Looks a type up by name, then by alias.
+    pub fn get(&self, name: &str) -> Option<&EntityType> {
+        self.types
+            .get(name)
+            .or_else(|| self.aliases.get(name).and_then(|name| self.types.get(name)))
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = &EntityType> {
+        self.types.values()
+    }
+
+    /// Checks that every parent and edge target exists and that inheritance has no cycles.
+    pub fn check(&self) -> std::result::Result<(), String> {
+        for entity_type in self.types.values() {
+            if let Some(parent) = &entity_type.parent {
+                if self.get(parent).is_none() {
+                    return Err(format!("{} extends unknown type {}", entity_type.name, parent));
+                }
+            }
+            for rule in &entity_type.edges {
+                if self.get(&rule.target).is_none() {
+                    return Err(format!(
+                        "{} allows {} edges to unknown type {}",
+                        entity_type.name, rule.label, rule.target
+                    ));
+                }
+            }
+            if self.ancestry(entity_type).len() > self.types.len() {
+                return Err(format!("{} inherits from itself", entity_type.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `name` is `ancestor` or one of its subtypes.
+    pub fn is_a(&self, name: &str, ancestor: &str) -> bool {
+        match (self.get(name), self.get(ancestor)) {
+            (Some(entity_type), Some(ancestor)) => {
+                self.ancestry(entity_type).iter().any(|t| t.name == ancestor.name)
+            }
+            _ => false,
+        }
+    }
+
+    /// The type followed by its ancestors. Stops one step into a cycle, which `check` reports.
+    fn ancestry<'a>(&'a self, entity_type: &'a EntityType) -> Vec<&'a EntityType> {
+        let mut chain = vec![entity_type];
+        let mut current = entity_type;
+        while let Some(parent) = current.parent.as_deref().and_then(|p| self.get(p)) {
+            chain.push(parent);
+            if chain.len() > self.types.len() {
+                break;
+            }
+            current = parent;
+        }
+        chain
+    }
+
+    /// Validates a node against its type. Untyped nodes always pass; nodes of unknown types
+    /// pass unless `reject_unknown` is set.
+    pub fn validate_node(&self, entity: &Entity, reject_unknown: bool) -> std::result::Result<(), String> {
+        let Some(name) = type_of(entity) else {
+            return Ok(());
+        };
+        let Some(entity_type) = self.get(name) else {
+            return if reject_unknown {
+                Err(format!("unknown type {}", name))
+            } else {
+                Ok(())
+            };
+        };
+
+        let chain = self.ancestry(entity_type);
+        let mut declared: BTreeMap<&str, &PropertySchema> = BTreeMap::new();
+        for ancestor in &chain {
+            for (property, schema) in &ancestor.properties {
+                declared.entry(property.as_str()).or_insert(schema);
+            }
+        }
+
+        for (property, schema) in &declared {
+            let Some(value) = entity.properties.get(*property) else {
+                if schema.required {
+                    return Err(format!("{} requires property {}", entity_type.name, property));
+                }
+                continue;
+            };
+            let valid = match value {
+                PropertyValue::List(items) if schema.list => items.iter().all(|item| schema.value_type.accepts(item)),
+                _ if schema.list => false,
+                value => schema.value_type.accepts(value),
+            };
+            if !valid {
+                return Err(format!(
+                    "property {} of {} must be {}{:?}",
+                    property,
+                    entity_type.name,
+                    if schema.list { "a list of " } else { "" },
+                    schema.value_type
+                ));
+            }
+        }
+
+        if entity_type.strict {
+            if let Some(extra) = entity
+                .properties
+                .keys()
+                .find(|p| p.as_str() != TYPE_PROPERTY && !declared.contains_key(p.as_str()))
+            {
+                return Err(format!("{} does not declare property {}", entity_type.name, extra));
+            }
+        }
+
+        if entity_type.name == SCHEMA_TYPE {
+            let defined = parse_schema_node(entity).map_err(|e| format!("invalid schema definition: {}", e))?;
+            if entity.id != schema_node_id(&defined.name) {
+                return Err(format!("schema {} is stored under the wrong id", defined.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates an edge against the rules of its source node's type.
+    pub fn validate_edge(&self, edge: &Edge, from: &Entity, to: &Entity) -> std::result::Result<(), String> {
+        let Some(source) = type_of(from).and_then(|name| self.get(name)) else {
+            return Ok(());
+        };
+        let chain = self.ancestry(source);
+        let rules: Vec<&EdgeRule> = chain
+            .iter()
+            .flat_map(|t| &t.edges)
+            .filter(|rule| rule.label == edge.label)
+            .collect();
+
+        if rules.is_empty() {
+            if source.strict {
+                return Err(format!("{} does not allow {} edges", source.name, edge.label));
+            }
+            return Ok(());
+        }
+        let target = type_of(to);
+        if rules
+            .iter()
+            .any(|rule| target.is_some_and(|target| self.is_a(target, &rule.target)))
+        {
+            return Ok(());
+        }
+        let allowed: Vec<&str> = rules.iter().map(|rule| rule.target.as_str()).collect();
+        Err(format!(
+            "{} edges from {} must point at {}, not {}",
+            edge.label,
+            source.name,
+            allowed.join(" or "),
+            target.unwrap_or("an untyped node")
+        ))
+    }
+}
+
+pub fn type_of(entity: &Entity) -> Option<&str> {
+    match entity.properties.get(TYPE_PROPERTY) {
+        Some(PropertyValue::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Schema nodes get ids derived from the type name, so each type is stored exactly once.
+pub fn schema_node_id(name: &str) -> NodeId {
+    let digest = Sha256::new().chain_update(b"athena-schema:").chain_update(name).finalize();
+    let bytes: [u8; 16] = digest[..16].try_into().expect("digest has 32 bytes");
+    NodeId::from_uuid(uuid::Builder::from_custom_bytes(bytes).into_uuid())
+}
+
+/// The node a type is stored as: labelled with the type name, with the definition as JSON.
+pub fn schema_node(entity_type: &EntityType) -> Result<Entity> {
+    let properties = HashMap::from([
+        (TYPE_PROPERTY.to_string(), PropertyValue::String(SCHEMA_TYPE.to_string())),
+        ("name".to_string(), PropertyValue::String(entity_type.name.clone())),
+        (
+            "definition".to_string(),
+            PropertyValue::String(serde_json::to_string(entity_type)?),
+        ),
+    ]);
+    Ok(Entity {
+        id: schema_node_id(&entity_type.name),
+        label: entity_type.name.clone(),
+        properties,
+        created_at: 0,
+        updated_at: 0,
+        version: 1,
+        graph: None,
+    })
+}
+
+pub fn parse_schema_node(entity: &Entity) -> Result<EntityType> {
+    match entity.properties.get("definition") {
+        Some(PropertyValue::String(definition)) => Ok(serde_json::from_str(definition)?),
+        _ => anyhow::bail!("Schema node {} has no definition", entity.id.0),
+    }
+}
+
+pub fn is_schema_node(entity: &Entity) -> bool {
+    type_of(entity) == Some(SCHEMA_TYPE)
+}
+
+fn builtin_types() -> Vec<EntityType> {
+    use PropertyType::*;
+    let schema_org = |name: &str| format!("https://schema.org/{}", name);
+    let foaf = |name: &str| format!("http://xmlns.com/foaf/0.1/{}", name);
+    let define = |name: &str, parent: Option<&str>, same_as: Vec<std::string::String>, aliases: &[&str]| EntityType {
+        name: name.to_string(),
+        parent: parent.map(str::to_string),
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        same_as,
+        description: None,
+        properties: BTreeMap::new(),
+        edges: Vec::new(),
+        strict: false,
+    };
+    let with = |mut entity_type: EntityType, properties: &[(&str, PropertyType)], edges: &[(&str, &str)]| {
+        for (name, value_type) in properties {
+            entity_type.properties.insert(
+                name.to_string(),
+                PropertySchema {
+                    value_type: *value_type,
+                    required: false,
+                    list: false,
+                },
+            );
+        }
+        entity_type.edges.extend(edges.iter().map(|(label, target)| EdgeRule {
+            label: label.to_string(),
+            target: target.to_string(),
+        }));
+        entity_type
+    };
+
+    let mut schema = with(
+        define(SCHEMA_TYPE, None, vec![], &[]),
+        &[("name", String), ("definition", String)],
+        &[],
+    );
+    schema.strict = true;
+    schema.description = Some("Stored definition of an entity type".to_string());
+    for property in schema.properties.values_mut() {
+        property.required = true;
+    }
+
+    let mut web_page = with(
+        define("WebPage", Some("CreativeWork"), vec![schema_org("WebPage")], &["web_page"]),
+        &[("url", String), ("clipped_at", DateTime), ("html_content", String)],
+        &[],
+    );
+    web_page.properties.get_mut("url").expect("declared above").required = true;
+
+    let mut creative_work = with(
+        define("CreativeWork", Some("Thing"), vec![schema_org("CreativeWork"), foaf("Document")], &[]),
+        &[("content", String), ("dateCreated", DateTime), ("dateModified", DateTime)],
+        &[
+            ("author", "Person"),
+            ("author", "Organization"),
+            ("about", "Thing"),
+            ("mentions", "Thing"),
+        ],
+    );
+    creative_work.properties.insert(
+        "keywords".to_string(),
+        PropertySchema {
+            value_type: String,
+            required: false,
+            list: true,
+        },
+    );
+
+    vec![
+        schema,
+        with(
+            define("Thing", None, vec![schema_org("Thing")], &[]),
+            &[("name", String), ("description", String), ("url", String)],
+            &[],
+        ),
+        with(
+            define("Person", Some("Thing"), vec![schema_org("Person"), foaf("Person")], &["person"]),
+            &[
+                ("givenName", String),
+                ("familyName", String),
+                ("nick", String),
+                ("email", String),
+                ("telephone", String),
+                ("birthDate", DateTime),
+            ],
+            &[
+                ("knows", "Person"),
+                ("memberOf", "Organization"),
+                ("worksFor", "Organization"),
+            ],
+        ),
+        with(
+            define(
+                "Organization",
+                Some("Thing"),
+                vec![schema_org("Organization"), foaf("Organization")],
+                &["organization"],
+            ),
+            &[("legalName", String), ("email", String)],
+            &[("member", "Person")],
+        ),
+        with(
+            define("Place", Some("Thing"), vec![schema_org("Place")], &["place"]),
+            &[("address", String), ("location", GeoPoint)],
+            &[("containedInPlace", "Place")],
+        ),
+        with(
+            define("Event", Some("Thing"), vec![schema_org("Event")], &["event"]),
+            &[("startDate", DateTime), ("endDate", DateTime), ("location", GeoPoint)],
+            &[
+                ("attendee", "Person"),
+                ("organizer", "Person"),
+                ("organizer", "Organization"),
+                ("location", "Place"),
+            ],
+        ),
+        creative_work,
+        with(
+            define("DigitalDocument", Some("CreativeWork"), vec![schema_org("DigitalDocument")], &["file", "text"]),
+            &[
+                ("path", String),
+                ("file_path", String),
+                ("extension", String),
+                ("size", Number),
+                ("modified_at", DateTime),
+            ],
+            &[],
+        ),
+        define(
+            "NoteDigitalDocument",
+            Some("DigitalDocument"),
+            vec![schema_org("NoteDigitalDocument")],
+            &["markdown", "note"],
+        ),
+        with(
+            define(
+                "ImageObject",
+                Some("DigitalDocument"),
+                vec![schema_org("ImageObject"), foaf("Image")],
+                &["image", "photo"],
+            ),
+            &[("location", GeoPoint), ("width", Number), ("height", Number)],
+            &[],
+        ),
+        with(
+            define("EmailMessage", Some("CreativeWork"), vec![schema_org("EmailMessage")], &["email"]),
+            &[("subject", String), ("source", String), ("file_path", String), ("dateSent", DateTime)],
+            &[("sender", "Person"), ("recipient", "Person")],
+        ),
+        web_page,
+        with(
+            define("Collection", Some("CreativeWork"), vec![schema_org("Collection")], &["directory"]),
+            &[("path", String)],
+            &[("hasPart", "CreativeWork")],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(type_name: &str, properties: Vec<(&str, PropertyValue)>) -> Entity {
+        let mut entity = Entity {
+            id: NodeId::new(),
+            label: type_name.to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        };
+        entity
+            .properties
+            .insert(TYPE_PROPERTY.to_string(), PropertyValue::String(type_name.to_string()));
+        for (name, value) in properties {
+            entity.properties.insert(name.to_string(), value);
+        }
+        entity
+    }
+
+    #[test]
+    fn test_builtin_types_validate_importer_nodes() {
+        let registry = SchemaRegistry::builtin();
+        registry.check().unwrap();
+        assert!(registry.is_a("email", "CreativeWork"));
+        assert!(registry.is_a("markdown", "DigitalDocument"));
+
+        let page = typed("web_page", vec![("url", PropertyValue::String("https://example.org".into()))]);
+        registry.validate_node(&page, true).unwrap();
+        assert!(registry.validate_node(&typed("web_page", vec![]), true).is_err());
+        let sized = typed("file", vec![("size", PropertyValue::String("large".into()))]);
+        assert!(registry.validate_node(&sized, true).is_err());
+        assert!(registry.validate_node(&typed("gadget", vec![]), false).is_ok());
+        assert!(registry.validate_node(&typed("gadget", vec![]), true).is_err());
+
+        let email = typed("email", vec![]);
+        let edge = |label: &str| Edge {
+            id: Uuid::new_v4(),
+            from: email.id.clone(),
+            to: NodeId::new(),
+            label: label.to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            version: 1,
+            graph: None,
+        };
+        let person = typed("Person", vec![]);
+        registry.validate_edge(&edge("sender"), &email, &person).unwrap();
+        assert!(registry.validate_edge(&edge("sender"), &email, &page).is_err());
+        // Undeclared labels are free on non-strict types
+        registry.validate_edge(&edge("reply"), &email, &page).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use crate::entity::{Entity, PropertyValue};
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// Words shown in a snippet, and how many of them precede the first match.
+const SNIPPET_WORDS: usize = 24;
+const SNIPPET_CONTEXT: usize = 6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub node: Entity,
+    pub score: f64,
+    pub snippet: Option<Snippet>,
+}
+
+/// Excerpt of the property that matched best.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub property: String,
+    pub text: String,
+    /// Byte ranges of the matched words within `text`.
+    pub highlights: Vec<(usize, usize)>,
+}
+
+/// A word of the source text and the term it is indexed under.
+#[derive(Debug, Clone)]
+pub struct SearchToken {
+    pub term: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub fn tokenize(text: &str) -> Vec<SearchToken> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            push_token(&mut tokens, text, s, i);
+        }
+    }
+    if let Some(s) = start {
+        push_token(&mut tokens, text, s, text.len());
+    }
+    tokens
+}
+
+fn push_token(tokens: &mut Vec<SearchToken>, text: &str, start: usize, end: usize) {
+    if let Some(term) = normalize(&text[start..end]) {
+        tokens.push(SearchToken { term, start, end });
+    }
+}
+
+/// Lowercases and stems a word. Words with Cyrillic letters go through the Russian
+/// stemmer, everything else through the English one. Single letters are dropped.
+fn normalize(word: &str) -> Option<String> {
+    let lower: String = word
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c == 'ё' { 'е' } else { c })
+        .collect();
+    if lower.chars().count() < 2 && !lower.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let stemmer = if lower.chars().any(|c| ('\u{0400}'..='\u{04FF}').contains(&c)) {
+        static RUSSIAN: OnceLock<Stemmer> = OnceLock::new();
+        RUSSIAN.get_or_init(|| Stemmer::create(Algorithm::Russian))
+    } else {
+        static ENGLISH: OnceLock<Stemmer> = OnceLock::new();
+        ENGLISH.get_or_init(|| Stemmer::create(Algorithm::English))
+    };
+    Some(stemmer.stem(&lower).into_owned())
+}
+
+/// Distinct terms of a search query, in query order.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tokenize(query)
+        .into_iter()
+        .map(|token| token.term)
+        .filter(|term| seen.insert(term.clone()))
+        .collect()
+}
+
+/// The text of a node that is searchable: its label and every string property (including
+/// strings inside lists), with markup removed from properties whose name mentions `html`.
+pub fn indexed_texts(entity: &Entity) -> Vec<(String, String)> {
+    let mut texts = vec![("label".to_string(), entity.label.clone())];
+
+    let mut names: Vec<&String> = entity.properties.keys().collect();
+    names.sort();
+    for name in names {
+        let mut strings = Vec::new();
+        collect_strings(&entity.properties[name], &mut strings);
+        for text in strings {
+            let text = if name.contains("html") {
+                strip_html(text)
+            } else {
+                text.to_string()
+            };
+            texts.push((name.clone(), text));
+        }
+    }
+    texts
+}
+
+fn collect_strings<'a>(value: &'a PropertyValue, out: &mut Vec<&'a str>) {
+    match value {
+        PropertyValue::String(s) => out.push(s),
+        PropertyValue::List(items) => {
+            for item in items {
+                collect_strings(item, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Term frequencies of a node and its length in terms.
+pub fn document_terms(entity: &Entity) -> (HashMap<String, u32>, u32) {
+    let mut terms = HashMap::new();
+    let mut length = 0;
+    for (_, text) in indexed_texts(entity) {
+        for token in tokenize(&text) {
+            *terms.entry(token.term).or_insert(0) += 1;
+            length += 1;
+        }
+    }
+    (terms, length)
+}
+
+/// Plain text of an HTML fragment: tags are dropped, `script`/`style` bodies skipped and
+/// the common entities decoded.
+pub fn strip_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        let tag = &rest[open..];
+        let Some(close) = tag.find('>') else {
+            out.push_str(tag);
+            rest = "";
+            break;
+        };
+        let name = tag[1..close]
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        rest = &tag[close + 1..];
+
+        if name == "script" || name == "style" {
+            // ASCII lowercasing keeps byte offsets intact
+            rest = match rest.to_ascii_lowercase().find(&format!("</{}", name)) {
+                Some(end) => &rest[end..],
+                None => "",
+            };
+        }
+        out.push(' ');
+    }
+    out.push_str(rest);
+
+    let text = out
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// BM25 contribution of one term to one document.
+pub fn bm25(term_frequency: u32, document_frequency: usize, documents: u64, length: u32, average_length: f64) -> f64 {
+    let tf = term_frequency as f64;
+    let df = document_frequency as f64;
+    let idf = ((documents as f64 - df + 0.5) / (df + 0.5) + 1.0).ln();
+    let norm = 1.0 - BM25_B + BM25_B * length as f64 / average_length.max(1.0);
+    idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm)
+}
+
+/// Excerpt around the first match in the text with the most matches.
+pub fn snippet(entity: &Entity, terms: &HashSet<String>) -> Option<Snippet> {
+    let mut best: Option<(usize, String, String, Vec<SearchToken>)> = None;
+    for (property, text) in indexed_texts(entity) {
+        let tokens = tokenize(&text);
+        let matches = tokens.iter().filter(|t| terms.contains(&t.term)).count();
+        if matches > 0 && best.as_ref().is_none_or(|(most, ..)| matches > *most) {
+            best = Some((matches, property, text, tokens));
+        }
+    }
+    let (_, property, text, tokens) = best?;
+
+    let first = tokens.iter().position(|t| terms.contains(&t.term))?;
+    let from = first.saturating_sub(SNIPPET_CONTEXT);
+    let to = (from + SNIPPET_WORDS).min(tokens.len());
+    let start = if from == 0 { 0 } else { tokens[from].start };
+    let end = if to == tokens.len() { text.len() } else { tokens[to - 1].end };
+
+    let mut excerpt = String::new();
+    if start > 0 {
+        excerpt.push('…');
+    }
+    let shift = excerpt.len();
+    excerpt.push_str(&text[start..end]);
+    if end < text.len() {
+        excerpt.push('…');
+    }
+
+    let highlights = tokens[from..to]
+        .iter()
+        .filter(|t| terms.contains(&t.term))
+        .map(|t| (t.start - start + shift, t.end - start + shift))
+        .collect();
+    Some(Snippet {
+        property,
+        text: excerpt,
+        highlights,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stemming_and_snippet() {
+        assert_eq!(query_terms("Running runs"), vec!["run".to_string()]);
+        assert_eq!(query_terms("Встречи встреча"), query_terms("встречу"));
+        assert_eq!(strip_html("<p>Hello&nbsp;<b>world</b></p><script>x()</script>"), "Hello world");
+
+        let entity = Entity {
+            id: crate::entity::NodeId::new(),
+            label: "Weekly notes".to_string(),
+            properties: HashMap::from([(
+                "content".to_string(),
+                PropertyValue::String(
+                    "one two three four five six seven eight nine ten the meetings went well".to_string(),
+                ),
+            )]),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        };
+        let terms: HashSet<String> = query_terms("meeting").into_iter().collect();
+        let snippet = snippet(&entity, &terms).unwrap();
+        assert_eq!(snippet.property, "content");
+        assert!(snippet.text.starts_with('…'));
+        let (start, end) = snippet.highlights[0];
+        assert_eq!(&snippet.text[start..end], "meetings");
+    }
+}
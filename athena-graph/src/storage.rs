@@ -1,79 +1,215 @@
-use crate::entity::{Edge, Entity, NodeId};
+use crate::encryption::{unlock, StorageKey};
+use crate::entity::{Edge, Entity, NodeId, PropertyValue};
+use crate::query::{Direction, FilterOperator};
+use crate::merkle::{bucket_of, hash_edge, hash_node, BucketHasher, MerkleTree, BUCKET_COUNT, EDGE_LEAF, NODE_LEAF};
+use crate::geo::{geohash, GeoBox};
+use crate::named_graph::graph_of;
+use crate::search::document_terms;
+use crate::store::{GraphStore, KeyValue, MemoryStore, StorageBackend, WriteBatch};
+use crate::timeline::time_fields;
+use crate::vector::{hnsw_insert, hnsw_remove, VectorNode, VectorStore, VectorStoreMut};
+use crate::version::{Checkpoint, Revision, RevisionLogEntry, VersionId};
 use anyhow::Result;
-use rocksdb::{IteratorMode, Options, DB};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 
 const NODE_PREFIX: &[u8] = b"node:";
 const EDGE_PREFIX: &[u8] = b"edge:";
 const INDEX_PREFIX: &[u8] = b"idx:";
+const LABEL_INDEX_PREFIX: &[u8] = b"idx:label:";
+const PROP_INDEX_PREFIX: &[u8] = b"idx:prop:";
+const TIME_INDEX_PREFIX: &[u8] = b"idx:time:";
+const GEO_INDEX_PREFIX: &[u8] = b"idx:geo:";
+const GRAPH_INDEX_PREFIX: &[u8] = b"idx:graph:";
+const GRAPH_NODE_TAG: u8 = b'n';
+const GRAPH_EDGE_TAG: u8 = b'e';
+const ADJACENCY_PREFIX: &[u8] = b"adj:";
+const OUT_ADJACENCY_PREFIX: &[u8] = b"adj:out:";
+const IN_ADJACENCY_PREFIX: &[u8] = b"adj:in:";
+const REV_NODE_PREFIX: &[u8] = b"rev:node:";
+const REV_EDGE_PREFIX: &[u8] = b"rev:edge:";
+const REV_LOG_PREFIX: &[u8] = b"rev:log:";
+const MERKLE_PREFIX: &[u8] = b"mrk:";
+const MERKLE_LEAF_PREFIX: &[u8] = b"mrk:leaf:";
+const MERKLE_BUCKET_PREFIX: &[u8] = b"mrk:bucket:";
+const MERKLE_DIRTY_PREFIX: &[u8] = b"mrk:dirty:";
+const CHECKPOINT_PREFIX: &[u8] = b"ckpt:";
+const TEXT_PREFIX: &[u8] = b"fts:";
+const TEXT_TERM_PREFIX: &[u8] = b"fts:term:";
+const TEXT_STATS_KEY: &[u8] = b"fts:stats";
+const VECTOR_PREFIX: &[u8] = b"vec:";
+const VECTOR_NODE_PREFIX: &[u8] = b"vec:node:";
+const VECTOR_ENTRY_KEY: &[u8] = b"vec:entry";
+const VECTOR_MODEL_KEY: &[u8] = b"vec:model";
+const META_VERSION_KEY: &[u8] = b"meta:version";
+const META_FORMAT_KEY: &[u8] = b"meta:format";
+const META_CREATED_AT_KEY: &[u8] = b"meta:created_at";
+const META_INSTANCE_KEY: &[u8] = b"meta:instance_id";
+
+// Bump whenever the storage layout changes so existing databases get reindexed on open
+const STORAGE_FORMAT: u32 = 9;
+const KEY_SEPARATOR: u8 = 0;
+
+type TimeEntries<'a> = Box<dyn Iterator<Item = Result<(i64, String, NodeId)>> + 'a>;
+
+/// One edge seen from one of its endpoints.
+#[derive(Debug, Clone)]
+pub struct Adjacency {
+    pub edge_id: uuid::Uuid,
+    pub neighbor: NodeId,
+    pub label: String,
+    pub direction: Direction,
+}
+
+/// Database-wide state kept in the `meta:` keyspace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphMetadata {
+    /// Last committed graph version, written in the same batch as every commit.
+    pub version: VersionId,
+    /// Storage layout the indexes were built with.
+    pub format: u32,
+    pub created_at: i64,
+    /// Identifies this database, e.g. towards sync peers. Snapshots and restores keep it.
+    pub instance_id: uuid::Uuid,
+}
 
 pub struct GraphStorage {
-    db: Arc<DB>,
+    db: Arc<dyn GraphStore>,
 }
 
 impl GraphStorage {
+    /// Opens (or creates) a RocksDB-backed database at `path`.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.set_max_open_files(10000);
-        opts.set_write_buffer_size(64 * 1024 * 1024);
+        Self::open_with(StorageBackend::RocksDb, path)
+    }
+
+    pub fn open_with<P: AsRef<Path>>(backend: StorageBackend, path: P) -> Result<Self> {
+        Self::open_with_key(backend, path, None)
+    }
+
+    /// Opens a database encrypted with `key`, encrypting it first if it is still in
+    /// plaintext. Opening an encrypted database without a key fails with
+    /// `GraphError::StorageLocked`.
+    pub fn open_with_key<P: AsRef<Path>>(backend: StorageBackend, path: P, key: Option<&StorageKey>) -> Result<Self> {
+        Self::from_store(unlock(backend.open(path.as_ref())?, key)?)
+    }
 
-        let db = DB::open(&opts, path)?;
-        Ok(Self { db: Arc::new(db) })
+    /// A database that lives only as long as the returned value.
+    pub fn in_memory() -> Result<Self> {
+        Self::from_store(Arc::new(MemoryStore::new()))
+    }
+
+    /// Builds graph storage on top of any key-value store, checking its indexes, history
+    /// and metadata like `open` does. An encrypted store must be unlocked first.
+    pub fn from_store(db: Arc<dyn GraphStore>) -> Result<Self> {
+        let storage = Self { db };
+        storage.ensure_indexes()?;
+        storage.ensure_history()?;
+        storage.ensure_metadata()?;
+        Ok(storage)
+    }
+
+    pub fn metadata(&self) -> Result<GraphMetadata> {
+        let instance_id = self
+            .db
+            .get(META_INSTANCE_KEY)?
+            .ok_or_else(|| anyhow::anyhow!("Graph metadata is missing"))?;
+        Ok(GraphMetadata {
+            version: VersionId(read_u64(self.db.as_ref(), META_VERSION_KEY)?.unwrap_or(1)),
+            format: read_u32(self.db.as_ref(), META_FORMAT_KEY)?.unwrap_or_default(),
+            created_at: read_u64(self.db.as_ref(), META_CREATED_AT_KEY)?.unwrap_or_default() as i64,
+            instance_id: uuid::Uuid::from_slice(&instance_id)?,
+        })
+    }
+
+    /// Opens a snapshot taken by `create_snapshot` for reading its records. Indexes are
+    /// neither checked nor rebuilt, so only `get_*` and `iter_*` are meaningful.
+    pub fn open_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<Self> {
+        Ok(Self {
+            db: self.db.open_snapshot(path.as_ref())?,
+        })
+    }
+
+    /// Writes a consistent copy of the whole database to `path`, which must not exist yet,
+    /// using the backend's own snapshot mechanism.
+    pub fn create_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.db.snapshot(path.as_ref())
+    }
+
+    /// Starts a set of writes that are committed atomically.
+    pub fn batch(&self) -> StorageBatch<'_> {
+        StorageBatch {
+            storage: self,
+            batch: WriteBatch::default(),
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+            text_documents: 0,
+            text_length: 0,
+            vectors: HashMap::new(),
+            vector_entry: None,
+        }
     }
 
     pub fn put_node(&self, entity: &Entity) -> Result<()> {
-        let key = self.node_key(&entity.id);
-        let value = bincode::serialize(entity)?;
-        self.db.put(key, value)?;
-        Ok(())
+        let mut batch = self.batch();
+        batch.put_node(entity)?;
+        batch.commit()
     }
 
     pub fn get_node(&self, id: &NodeId) -> Result<Option<Entity>> {
         let key = self.node_key(id);
-        match self.db.get(key)? {
+        match self.db.get(&key)? {
             Some(data) => Ok(Some(bincode::deserialize(&data)?)),
             None => Ok(None),
         }
     }
 
     pub fn delete_node(&self, id: &NodeId) -> Result<()> {
-        let key = self.node_key(id);
-        self.db.delete(key)?;
-        Ok(())
+        let mut batch = self.batch();
+        batch.delete_node(id)?;
+        batch.commit()
     }
 
     pub fn put_edge(&self, edge: &Edge) -> Result<()> {
-        let key = self.edge_key(&edge.id);
-        let value = bincode::serialize(edge)?;
-        self.db.put(key, value)?;
-        Ok(())
+        let mut batch = self.batch();
+        batch.put_edge(edge)?;
+        batch.commit()
     }
 
     pub fn get_edge(&self, id: &uuid::Uuid) -> Result<Option<Edge>> {
         let key = self.edge_key(id);
-        match self.db.get(key)? {
+        match self.db.get(&key)? {
             Some(data) => Ok(Some(bincode::deserialize(&data)?)),
             None => Ok(None),
         }
     }
 
     pub fn delete_edge(&self, id: &uuid::Uuid) -> Result<()> {
-        let key = self.edge_key(id);
-        self.db.delete(key)?;
-        Ok(())
+        let mut batch = self.batch();
+        batch.delete_edge(id)?;
+        batch.commit()
     }
 
     pub fn iter_nodes(&self) -> impl Iterator<Item = Result<Entity>> + '_ {
+        self.db.scan_prefix(NODE_PREFIX).map(|item| {
+            let (_, value) = item?;
+            Ok(bincode::deserialize(&value)?)
+        })
+    }
+
+    /// Nodes with an id greater than `after`, in id order.
+    pub fn iter_nodes_after(&self, after: &NodeId) -> impl Iterator<Item = Result<Entity>> + '_ {
+        let mut start = self.node_key(after);
+        start.push(0);
         self.db
-            .iterator(IteratorMode::From(NODE_PREFIX, rocksdb::Direction::Forward))
-            .take_while(|item| {
-                item.as_ref()
-                    .map(|(k, _)| k.starts_with(NODE_PREFIX))
-                    .unwrap_or(false)
-            })
+            .iter_from(&start)
+            .take_while(|item| item.as_ref().map_or(true, |(key, _)| key.starts_with(NODE_PREFIX)))
             .map(|item| {
                 let (_, value) = item?;
                 Ok(bincode::deserialize(&value)?)
@@ -81,19 +217,809 @@ impl GraphStorage {
     }
 
     pub fn iter_edges(&self) -> impl Iterator<Item = Result<Edge>> + '_ {
+        self.db.scan_prefix(EDGE_PREFIX).map(|item| {
+            let (_, value) = item?;
+            Ok(bincode::deserialize(&value)?)
+        })
+    }
+
+    /// Edges with an id greater than `after`, in id order.
+    pub fn iter_edges_after(&self, after: &uuid::Uuid) -> impl Iterator<Item = Result<Edge>> + '_ {
+        let mut start = self.edge_key(after);
+        start.push(0);
         self.db
-            .iterator(IteratorMode::From(EDGE_PREFIX, rocksdb::Direction::Forward))
-            .take_while(|item| {
-                item.as_ref()
-                    .map(|(k, _)| k.starts_with(EDGE_PREFIX))
-                    .unwrap_or(false)
-            })
+            .iter_from(&start)
+            .take_while(|item| item.as_ref().map_or(true, |(key, _)| key.starts_with(EDGE_PREFIX)))
             .map(|item| {
                 let (_, value) = item?;
                 Ok(bincode::deserialize(&value)?)
             })
     }
 
+    /// Ids of nodes whose label is exactly `label`.
+    pub fn find_by_label(&self, label: &str) -> Result<Vec<NodeId>> {
+        let mut prefix = LABEL_INDEX_PREFIX.to_vec();
+        prefix.extend_from_slice(label.as_bytes());
+        prefix.push(KEY_SEPARATOR);
+        self.scan_index(&prefix, true)
+    }
+
+    /// Ids of nodes whose label starts with `prefix`.
+    pub fn find_by_label_prefix(&self, prefix: &str) -> Result<Vec<NodeId>> {
+        let mut key = LABEL_INDEX_PREFIX.to_vec();
+        key.extend_from_slice(prefix.as_bytes());
+        self.scan_index(&key, false)
+    }
+
+    /// Ids of nodes whose `property` satisfies `operator` against `value`, answered from the
+    /// property index. Returns `None` when the index cannot serve this operator/value pair.
+    /// The result may contain false positives and must be re-checked against the filter.
+    pub fn find_by_property(
+        &self,
+        property: &str,
+        operator: FilterOperator,
+        value: &PropertyValue,
+    ) -> Result<Option<Vec<NodeId>>> {
+        let encoded = match encode_index_value(value) {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+        let base = self.property_index_prefix(property);
+        let with = |suffix: &[u8]| {
+            let mut key = base.clone();
+            key.extend_from_slice(suffix);
+            key
+        };
+        // Encoded values start with a type tag, so [tag, tag + 1) bounds one type
+        let type_start = with(&encoded[..1]);
+        let type_end = with(&[encoded[0] + 1]);
+        let mut value_key = with(&encoded);
+
+        let ids = match operator {
+            FilterOperator::Equals => {
+                value_key.push(KEY_SEPARATOR);
+                self.scan_index(&value_key, true)?
+            }
+            FilterOperator::StartsWith if matches!(value, PropertyValue::String(_)) => {
+                self.scan_index(&value_key, false)?
+            }
+            FilterOperator::GreaterThan => {
+                value_key.push(KEY_SEPARATOR + 1);
+                self.scan_index_range(&value_key, &type_end)?
+            }
+            FilterOperator::GreaterThanOrEqual => self.scan_index_range(&value_key, &type_end)?,
+            FilterOperator::LessThan => self.scan_index_range(&type_start, &value_key)?,
+            FilterOperator::LessThanOrEqual => {
+                value_key.push(KEY_SEPARATOR + 1);
+                self.scan_index_range(&type_start, &value_key)?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(ids))
+    }
+
+    /// Ids of the nodes in a named graph.
+    pub fn graph_nodes(&self, graph: &str) -> Result<Vec<NodeId>> {
+        self.scan_index(&graph_index_prefix(graph, GRAPH_NODE_TAG), true)
+    }
+
+    /// Ids of the edges in a named graph.
+    pub fn graph_edges(&self, graph: &str) -> Result<Vec<uuid::Uuid>> {
+        let ids = self.scan_index(&graph_index_prefix(graph, GRAPH_EDGE_TAG), true)?;
+        Ok(ids.into_iter().map(|id| id.0).collect())
+    }
+
+    /// Every graph holding at least one record, with its node and edge counts, by name.
+    pub fn graph_counts(&self) -> Result<Vec<(String, u64, u64)>> {
+        let mut counts: Vec<(String, u64, u64)> = Vec::new();
+        for item in self
+            .db
+            .iter_from(GRAPH_INDEX_PREFIX)
+        {
+            let (key, _) = item?;
+            if !key.starts_with(GRAPH_INDEX_PREFIX) {
+                break;
+            }
+            let rest = &key[GRAPH_INDEX_PREFIX.len()..];
+            let Some(end) = rest.iter().position(|&b| b == KEY_SEPARATOR) else {
+                continue;
+            };
+            let name = String::from_utf8_lossy(&rest[..end]);
+            if counts.last().is_none_or(|(last, _, _)| *last != name) {
+                counts.push((name.into_owned(), 0, 0));
+            }
+            let entry = counts.last_mut().expect("pushed above");
+            match rest.get(end + 1) {
+                Some(&GRAPH_NODE_TAG) => entry.1 += 1,
+                Some(&GRAPH_EDGE_TAG) => entry.2 += 1,
+                _ => {}
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Ids of nodes with a point in `property` whose geohash falls in a cell covering
+    /// `bounds`. The result contains points outside `bounds` and must be re-checked.
+    pub fn find_within(&self, property: &str, bounds: &GeoBox) -> Result<Vec<NodeId>> {
+        let prefix = geo_index_prefix(property);
+        let mut ids = Vec::new();
+        for (start, end) in bounds.geohash_ranges() {
+            let mut from = prefix.clone();
+            from.extend_from_slice(&start.to_be_bytes());
+            // Sorts after every key of the last hash, whatever its node id
+            let mut to = prefix.clone();
+            to.extend_from_slice(&end.to_be_bytes());
+            to.extend_from_slice(&[0xff; 17]);
+            ids.extend(self.scan_index_range(&from, &to)?);
+        }
+        ids.sort_by_key(|id| id.0);
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Adjacency entries of `node` in the given direction, optionally restricted to one edge label.
+    pub fn adjacent(
+        &self,
+        node: &NodeId,
+        direction: Direction,
+        label: Option<&str>,
+    ) -> Result<Vec<Adjacency>> {
+        let prefixes: &[&[u8]] = match direction {
+            Direction::Outgoing => &[OUT_ADJACENCY_PREFIX],
+            Direction::Incoming => &[IN_ADJACENCY_PREFIX],
+            Direction::Both => &[OUT_ADJACENCY_PREFIX, IN_ADJACENCY_PREFIX],
+        };
+
+        let mut result = Vec::new();
+        for base in prefixes {
+            let mut prefix = base.to_vec();
+            prefix.extend_from_slice(node.0.as_bytes());
+            if let Some(label) = label {
+                prefix.extend_from_slice(label.as_bytes());
+                prefix.push(KEY_SEPARATOR);
+            }
+
+            for item in self.db.iter_from(&prefix) {
+                let (key, value) = item?;
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                let label_start = base.len() + 16;
+                if key.len() < label_start + 17 {
+                    continue;
+                }
+                let label_end = key.len() - 17;
+                // A label lookup must not match longer labels sharing the prefix
+                if label.is_some() && label_end + 1 != prefix.len() {
+                    continue;
+                }
+                result.push(Adjacency {
+                    edge_id: uuid::Uuid::from_slice(&key[key.len() - 16..])?,
+                    neighbor: NodeId::from_uuid(uuid::Uuid::from_slice(&value)?),
+                    label: String::from_utf8_lossy(&key[label_start..label_end]).into_owned(),
+                    direction: if *base == OUT_ADJACENCY_PREFIX {
+                        Direction::Outgoing
+                    } else {
+                        Direction::Incoming
+                    },
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Nodes containing a search term, with the term's frequency in the node and the node's
+    /// length in terms.
+    pub fn text_postings(&self, term: &str) -> Result<Vec<(NodeId, u32, u32)>> {
+        let mut prefix = TEXT_TERM_PREFIX.to_vec();
+        prefix.extend_from_slice(term.as_bytes());
+        prefix.push(KEY_SEPARATOR);
+
+        let mut postings = Vec::new();
+        for item in self.db.iter_from(&prefix) {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            if key.len() != prefix.len() + 16 || value.len() != 8 {
+                continue;
+            }
+            postings.push((
+                NodeId::from_uuid(uuid::Uuid::from_slice(&key[prefix.len()..])?),
+                u32::from_be_bytes(value[..4].try_into()?),
+                u32::from_be_bytes(value[4..].try_into()?),
+            ));
+        }
+        Ok(postings)
+    }
+
+    /// Number of nodes in the full-text index and their total length in terms.
+    pub fn text_stats(&self) -> Result<(u64, u64)> {
+        match self.db.get(TEXT_STATS_KEY)? {
+            Some(value) if value.len() == 16 => Ok((
+                u64::from_be_bytes(value[..8].try_into()?),
+                u64::from_be_bytes(value[8..].try_into()?),
+            )),
+            _ => Ok((0, 0)),
+        }
+    }
+
+    /// Entries of the time index with `from <= timestamp < to`, as `(timestamp, field, node)`
+    /// in time order. Each node appears once per timestamp field.
+    pub fn time_entries(&self, from: Option<i64>, to: Option<i64>, newest_first: bool) -> TimeEntries<'_> {
+        let bound = |timestamp: Option<i64>, open: u8| {
+            let mut key = TIME_INDEX_PREFIX.to_vec();
+            match timestamp {
+                Some(t) => key.extend_from_slice(&encode_timestamp(t)),
+                None => key.extend_from_slice(&[open; 9]),
+            }
+            key
+        };
+        let (start, end) = (bound(from, 0), bound(to, 0xff));
+
+        let entries = if newest_first {
+            // Reverse iteration starts at the last key <= `end`, which lies before `to`
+            Box::new(
+                self.db
+                    .iter_back_from(&end)
+                    .take_while(move |item| item.as_ref().map_or(true, |(key, _)| key.as_slice() >= start.as_slice())),
+            ) as Box<dyn Iterator<Item = _>>
+        } else {
+            Box::new(
+                self.db
+                    .iter_from(&start)
+                    .take_while(move |item| item.as_ref().map_or(true, |(key, _)| key.as_slice() < end.as_slice())),
+            )
+        };
+        Box::new(entries.filter_map(|item| match item {
+            Ok((key, _)) => decode_time_key(&key).transpose(),
+            Err(e) => Some(Err(e)),
+        }))
+    }
+
+    /// Name of the embedder the vector index was built with.
+    pub fn vector_model(&self) -> Result<Option<String>> {
+        match self.db.get(VECTOR_MODEL_KEY)? {
+            Some(value) => Ok(Some(String::from_utf8(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Drops the whole vector index, e.g. before re-embedding with another model.
+    pub fn clear_vectors(&self) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for item in self.db.iter_from(VECTOR_PREFIX) {
+            let (key, _) = item?;
+            if !key.starts_with(VECTOR_PREFIX) {
+                break;
+            }
+            batch.delete(key);
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Every recorded revision of a node, oldest first.
+    pub fn node_history(&self, id: &NodeId) -> Result<Vec<Revision<Entity>>> {
+        self.history(REV_NODE_PREFIX, &id.0)
+    }
+
+    pub fn edge_history(&self, id: &uuid::Uuid) -> Result<Vec<Revision<Edge>>> {
+        self.history(REV_EDGE_PREFIX, id)
+    }
+
+    /// State of a node right after `version` was committed.
+    pub fn node_as_of(&self, id: &NodeId, version: VersionId) -> Result<Option<Entity>> {
+        self.record_as_of(REV_NODE_PREFIX, &id.0, version)
+    }
+
+    pub fn edge_as_of(&self, id: &uuid::Uuid, version: VersionId) -> Result<Option<Edge>> {
+        self.record_as_of(REV_EDGE_PREFIX, id, version)
+    }
+
+    /// All nodes that existed right after `version` was committed.
+    pub fn nodes_as_of(&self, version: VersionId) -> Result<Vec<Entity>> {
+        self.records_as_of(REV_NODE_PREFIX, version)
+    }
+
+    pub fn edges_as_of(&self, version: VersionId) -> Result<Vec<Edge>> {
+        self.records_as_of(REV_EDGE_PREFIX, version)
+    }
+
+    /// Last version committed at or before `timestamp`.
+    pub fn version_at(&self, timestamp: i64) -> Result<Option<VersionId>> {
+        let mut found = None;
+        for entry in self.revision_log() {
+            let entry = entry?;
+            if entry.timestamp > timestamp {
+                break;
+            }
+            found = Some(entry.version);
+        }
+        Ok(found)
+    }
+
+    pub fn latest_version(&self) -> Result<Option<VersionId>> {
+        let mut end = REV_LOG_PREFIX.to_vec();
+        end.extend_from_slice(&u64::MAX.to_be_bytes());
+        match self
+            .db
+            .iter_back_from(&end)
+            .next()
+        {
+            Some(item) => {
+                let (key, _) = item?;
+                if !key.starts_with(REV_LOG_PREFIX) {
+                    return Ok(None);
+                }
+                Ok(Some(VersionId(decode_version(&key)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn revision_log(&self) -> impl Iterator<Item = Result<RevisionLogEntry>> + '_ {
+        self.db.scan_prefix(REV_LOG_PREFIX).map(|item| {
+            let (_, value) = item?;
+            Ok(bincode::deserialize(&value)?)
+        })
+    }
+
+    /// Calls `f` with every node that has a history, deleted nodes included, in id order.
+    pub fn for_each_node_history(&self, mut f: impl FnMut(NodeId, Vec<Revision<Entity>>) -> Result<()>) -> Result<()> {
+        self.for_each_history(REV_NODE_PREFIX, |id, revisions| f(NodeId::from_uuid(id), revisions))
+    }
+
+    pub fn for_each_edge_history(&self, f: impl FnMut(uuid::Uuid, Vec<Revision<Edge>>) -> Result<()>) -> Result<()> {
+        self.for_each_history(REV_EDGE_PREFIX, f)
+    }
+
+    fn for_each_history<T: DeserializeOwned>(
+        &self,
+        prefix: &[u8],
+        mut f: impl FnMut(uuid::Uuid, Vec<Revision<T>>) -> Result<()>,
+    ) -> Result<()> {
+        // Revisions are grouped by record id, so each group is handed over once complete
+        let mut current: Option<(uuid::Uuid, Vec<Revision<T>>)> = None;
+        for item in self.db.scan_prefix(prefix) {
+            let (key, value) = item?;
+            if key.len() < prefix.len() + 16 {
+                continue;
+            }
+            let id = uuid::Uuid::from_slice(&key[prefix.len()..prefix.len() + 16])?;
+            if current.as_ref().is_some_and(|(current, _)| *current != id) {
+                if let Some((id, revisions)) = current.take() {
+                    f(id, revisions)?;
+                }
+            }
+            current
+                .get_or_insert_with(|| (id, Vec::new()))
+                .1
+                .push(bincode::deserialize(&value)?);
+        }
+        if let Some((id, revisions)) = current {
+            f(id, revisions)?;
+        }
+        Ok(())
+    }
+
+    fn history<T: DeserializeOwned>(&self, prefix: &[u8], id: &uuid::Uuid) -> Result<Vec<Revision<T>>> {
+        let mut start = prefix.to_vec();
+        start.extend_from_slice(id.as_bytes());
+
+        let mut revisions = Vec::new();
+        for item in self.db.iter_from(&start) {
+            let (key, value) = item?;
+            if !key.starts_with(&start) {
+                break;
+            }
+            revisions.push(bincode::deserialize(&value)?);
+        }
+        Ok(revisions)
+    }
+
+    fn record_as_of<T: DeserializeOwned>(
+        &self,
+        prefix: &[u8],
+        id: &uuid::Uuid,
+        version: VersionId,
+    ) -> Result<Option<T>> {
+        let mut record = prefix.to_vec();
+        record.extend_from_slice(id.as_bytes());
+        let key = revision_key(prefix, id, version);
+
+        // Reverse iteration from `key` lands on the newest revision at or before `version`
+        match self
+            .db
+            .iter_back_from(&key)
+            .next()
+        {
+            Some(item) => {
+                let (key, value) = item?;
+                if !key.starts_with(&record) {
+                    return Ok(None);
+                }
+                let revision: Revision<T> = bincode::deserialize(&value)?;
+                Ok(revision.value)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn records_as_of<T: DeserializeOwned>(&self, prefix: &[u8], version: VersionId) -> Result<Vec<T>> {
+        // Revisions are grouped by record id and sorted by version within a group, so the
+        // last revision at or before `version` is kept while walking each group
+        let mut records = Vec::new();
+        let mut current: Option<(Vec<u8>, Option<T>)> = None;
+        for item in self.db.iter_from(prefix) {
+            let (key, value) = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let record = &key[..prefix.len() + 16];
+            if current.as_ref().is_none_or(|(id, _)| id.as_slice() != record) {
+                if let Some((_, Some(value))) = current.take() {
+                    records.push(value);
+                }
+                current = Some((record.to_vec(), None));
+            }
+            if decode_version(&key)? > version.0 {
+                continue;
+            }
+            let revision: Revision<T> = bincode::deserialize(&value)?;
+            if let Some((_, state)) = current.as_mut() {
+                *state = revision.value;
+            }
+        }
+        if let Some((_, Some(value))) = current {
+            records.push(value);
+        }
+        Ok(records)
+    }
+
+    /// Databases written before revisions were recorded get their current state recorded as
+    /// the first version, so that history and time-travel reads cover every record.
+    fn ensure_history(&self) -> Result<()> {
+        if self.latest_version()?.is_some() {
+            return Ok(());
+        }
+        let version = VersionId::new();
+        let timestamp = chrono::Utc::now().timestamp();
+
+        let mut batch = WriteBatch::default();
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for node in self.iter_nodes() {
+            let node = node?;
+            batch.put(
+                revision_key(REV_NODE_PREFIX, &node.id.0, version),
+                bincode::serialize(&Revision { version, timestamp, value: Some(&node) })?,
+            );
+            nodes.push(node.id);
+        }
+        for edge in self.iter_edges() {
+            let edge = edge?;
+            batch.put(
+                revision_key(REV_EDGE_PREFIX, &edge.id, version),
+                bincode::serialize(&Revision { version, timestamp, value: Some(&edge) })?,
+            );
+            edges.push(edge.id);
+        }
+        if nodes.is_empty() && edges.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!("Recording {} nodes and {} edges as the initial revision", nodes.len(), edges.len());
+        let entry = RevisionLogEntry {
+            version,
+            timestamp,
+            nodes,
+            edges,
+        };
+        batch.put(revision_log_key(version), bincode::serialize(&entry)?);
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Drops every index entry (including Merkle leaves and full-text postings) and rebuilds
+    /// them from the stored nodes and edges.
+    pub fn rebuild_indexes(&self) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for prefix in [INDEX_PREFIX, ADJACENCY_PREFIX, MERKLE_PREFIX, TEXT_PREFIX] {
+            for item in self.db.iter_from(prefix) {
+                let (key, _) = item?;
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                batch.delete(key);
+            }
+        }
+        self.db.write(batch)?;
+
+        let mut batch = WriteBatch::default();
+        let (mut text_documents, mut text_length) = (0u64, 0u64);
+        for node in self.iter_nodes() {
+            let node = node?;
+            for key in self.index_keys(&node) {
+                batch.put(key, b"");
+            }
+            let (entries, length) = self.text_entries(&node);
+            for (key, value) in entries {
+                batch.put(key, value);
+            }
+            if length > 0 {
+                text_documents += 1;
+                text_length += length as u64;
+            }
+            batch.put(merkle_leaf_key(NODE_LEAF, &node.id.0), hash_node(&node));
+            if batch.len() >= 10_000 {
+                self.db.write(std::mem::take(&mut batch))?;
+            }
+        }
+        for edge in self.iter_edges() {
+            let edge = edge?;
+            for (key, value) in self.adjacency_entries(&edge) {
+                batch.put(key, value);
+            }
+            batch.put(edge_graph_key(&edge), b"");
+            batch.put(merkle_leaf_key(EDGE_LEAF, &edge.id), hash_edge(&edge));
+            if batch.len() >= 10_000 {
+                self.db.write(std::mem::take(&mut batch))?;
+            }
+        }
+        for bucket in 0..BUCKET_COUNT {
+            batch.put(merkle_bucket_key(MERKLE_DIRTY_PREFIX, bucket as u8), b"");
+        }
+        batch.put(TEXT_STATS_KEY, encode_text_stats(text_documents, text_length));
+        batch.put(META_FORMAT_KEY, STORAGE_FORMAT.to_be_bytes());
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Merkle tree over the stored records. Buckets touched since the last call are rehashed
+    /// from their leaves; the rest come from cache. Must not run concurrently with writes.
+    pub fn merkle_tree(&self) -> Result<MerkleTree> {
+        let mut dirty = Vec::new();
+        for item in self
+            .db
+            .iter_from(MERKLE_DIRTY_PREFIX)
+        {
+            let (key, _) = item?;
+            if !key.starts_with(MERKLE_DIRTY_PREFIX) {
+                break;
+            }
+            if let Some(&bucket) = key.get(MERKLE_DIRTY_PREFIX.len()) {
+                dirty.push(bucket);
+            }
+        }
+
+        let mut batch = WriteBatch::default();
+        for bucket in dirty {
+            let mut prefix = MERKLE_LEAF_PREFIX.to_vec();
+            prefix.push(bucket);
+            let mut hasher = BucketHasher::new();
+            for item in self.db.iter_from(&prefix) {
+                let (key, leaf) = item?;
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                // prefix + kind + id
+                if key.len() != prefix.len() + 17 {
+                    continue;
+                }
+                let id = uuid::Uuid::from_slice(&key[prefix.len() + 1..])?;
+                hasher.add(key[prefix.len()], &id, &leaf);
+            }
+            batch.put(merkle_bucket_key(MERKLE_BUCKET_PREFIX, bucket), hasher.finish());
+            batch.delete(merkle_bucket_key(MERKLE_DIRTY_PREFIX, bucket));
+        }
+        self.db.write(batch)?;
+
+        let empty = BucketHasher::new().finish();
+        let mut buckets = Vec::with_capacity(BUCKET_COUNT);
+        for bucket in 0..BUCKET_COUNT {
+            let hash = match self.db.get(&merkle_bucket_key(MERKLE_BUCKET_PREFIX, bucket as u8))? {
+                Some(hash) => hash
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Corrupt Merkle bucket {}", bucket))?,
+                None => empty,
+            };
+            buckets.push(hash);
+        }
+        Ok(MerkleTree { buckets })
+    }
+
+    pub fn put_checkpoint(&self, checkpoint: &Checkpoint, tree: &MerkleTree) -> Result<()> {
+        self.db.put(
+            &checkpoint_key(&checkpoint.id),
+            &bincode::serialize(&(checkpoint, tree))?,
+        )?;
+        Ok(())
+    }
+
+    pub fn get_checkpoint(&self, id: &uuid::Uuid) -> Result<Option<(Checkpoint, MerkleTree)>> {
+        match self.db.get(&checkpoint_key(id))? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete_checkpoint(&self, id: &uuid::Uuid) -> Result<()> {
+        self.db.delete(&checkpoint_key(id))?;
+        Ok(())
+    }
+
+    /// Stored checkpoints, oldest first.
+    pub fn list_checkpoints(&self) -> Result<Vec<Checkpoint>> {
+        let mut checkpoints = Vec::new();
+        for item in self
+            .db
+            .iter_from(CHECKPOINT_PREFIX)
+        {
+            let (key, value) = item?;
+            if !key.starts_with(CHECKPOINT_PREFIX) {
+                break;
+            }
+            let (checkpoint, _): (Checkpoint, MerkleTree) = bincode::deserialize(&value)?;
+            checkpoints.push(checkpoint);
+        }
+        checkpoints.sort_by_key(|c: &Checkpoint| (c.version, c.timestamp));
+        Ok(checkpoints)
+    }
+
+    fn ensure_indexes(&self) -> Result<()> {
+        let current = read_u32(self.db.as_ref(), META_FORMAT_KEY)?;
+        if current != Some(STORAGE_FORMAT) {
+            tracing::info!("Rebuilding graph indexes (format {:?} -> {})", current, STORAGE_FORMAT);
+            self.rebuild_indexes()?;
+        }
+        Ok(())
+    }
+
+    /// New databases get an identity; databases written before the `meta:` keyspace existed
+    /// also take their current version from the revision log.
+    fn ensure_metadata(&self) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        if self.db.get(META_INSTANCE_KEY)?.is_none() {
+            batch.put(META_INSTANCE_KEY, uuid::Uuid::new_v4().as_bytes());
+            batch.put(META_CREATED_AT_KEY, chrono::Utc::now().timestamp().to_be_bytes());
+        }
+        if self.db.get(META_VERSION_KEY)?.is_none() {
+            let version = self.latest_version()?.unwrap_or_else(VersionId::new);
+            batch.put(META_VERSION_KEY, version.0.to_be_bytes());
+        }
+        if !batch.is_empty() {
+            self.db.write(batch)?;
+        }
+        Ok(())
+    }
+
+    fn scan_index_range(&self, start: &[u8], end: &[u8]) -> Result<Vec<NodeId>> {
+        let mut ids = Vec::new();
+        for item in self.db.iter_from(start) {
+            let (key, _) = item?;
+            if key.as_slice() >= end {
+                break;
+            }
+            if key.len() < 16 {
+                continue;
+            }
+            let id = uuid::Uuid::from_slice(&key[key.len() - 16..])?;
+            ids.push(NodeId::from_uuid(id));
+        }
+        Ok(ids)
+    }
+
+    fn scan_index(&self, prefix: &[u8], exact: bool) -> Result<Vec<NodeId>> {
+        let mut ids = Vec::new();
+        for item in self.db.iter_from(prefix) {
+            let (key, _) = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            // An exact lookup must not pick up longer values sharing the prefix
+            if exact && key.len() != prefix.len() + 16 {
+                continue;
+            }
+            if key.len() < 16 {
+                continue;
+            }
+            let id = uuid::Uuid::from_slice(&key[key.len() - 16..])?;
+            ids.push(NodeId::from_uuid(id));
+        }
+        Ok(ids)
+    }
+
+    fn index_keys(&self, entity: &Entity) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+
+        let mut label_key = LABEL_INDEX_PREFIX.to_vec();
+        label_key.extend_from_slice(entity.label.as_bytes());
+        label_key.push(KEY_SEPARATOR);
+        label_key.extend_from_slice(entity.id.0.as_bytes());
+        keys.push(label_key);
+
+        for (name, value) in &entity.properties {
+            let values: Vec<&PropertyValue> = match value {
+                PropertyValue::List(items) => items.iter().collect(),
+                other => vec![other],
+            };
+            for value in values {
+                if let PropertyValue::GeoPoint(point) = value {
+                    let mut key = geo_index_prefix(name);
+                    key.extend_from_slice(&geohash(point).to_be_bytes());
+                    key.extend_from_slice(entity.id.0.as_bytes());
+                    keys.push(key);
+                } else if let Some(encoded) = encode_index_value(value) {
+                    let mut key = self.property_index_prefix(name);
+                    key.extend_from_slice(&encoded);
+                    key.push(KEY_SEPARATOR);
+                    key.extend_from_slice(entity.id.0.as_bytes());
+                    keys.push(key);
+                }
+            }
+        }
+
+        let mut graph_key = graph_index_prefix(graph_of(entity.graph.as_deref()), GRAPH_NODE_TAG);
+        graph_key.extend_from_slice(entity.id.0.as_bytes());
+        keys.push(graph_key);
+
+        for (field, timestamp) in time_fields(entity) {
+            let mut key = TIME_INDEX_PREFIX.to_vec();
+            key.extend_from_slice(&encode_timestamp(timestamp));
+            key.extend_from_slice(field.as_bytes());
+            key.push(KEY_SEPARATOR);
+            key.extend_from_slice(entity.id.0.as_bytes());
+            keys.push(key);
+        }
+
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Posting entries of a node (`term + id` -> term frequency and node length) and its
+    /// length in terms.
+    fn text_entries(&self, entity: &Entity) -> (Vec<KeyValue>, u32) {
+        let (terms, length) = document_terms(entity);
+        let entries = terms
+            .into_iter()
+            .map(|(term, frequency)| {
+                let mut key = TEXT_TERM_PREFIX.to_vec();
+                key.extend_from_slice(term.as_bytes());
+                key.push(KEY_SEPARATOR);
+                key.extend_from_slice(entity.id.0.as_bytes());
+                let mut value = frequency.to_be_bytes().to_vec();
+                value.extend_from_slice(&length.to_be_bytes());
+                (key, value)
+            })
+            .collect();
+        (entries, length)
+    }
+
+    fn adjacency_entries(&self, edge: &Edge) -> [(Vec<u8>, Vec<u8>); 2] {
+        let entry = |prefix: &[u8], node: &NodeId, other: &NodeId| {
+            let mut key = prefix.to_vec();
+            key.extend_from_slice(node.0.as_bytes());
+            key.extend_from_slice(edge.label.as_bytes());
+            key.push(KEY_SEPARATOR);
+            key.extend_from_slice(edge.id.as_bytes());
+            (key, other.0.as_bytes().to_vec())
+        };
+        [
+            entry(OUT_ADJACENCY_PREFIX, &edge.from, &edge.to),
+            entry(IN_ADJACENCY_PREFIX, &edge.to, &edge.from),
+        ]
+    }
+
+    fn property_index_prefix(&self, property: &str) -> Vec<u8> {
+        let mut key = PROP_INDEX_PREFIX.to_vec();
+        key.extend_from_slice(property.as_bytes());
+        key.push(KEY_SEPARATOR);
+        key
+    }
+
     fn node_key(&self, id: &NodeId) -> Vec<u8> {
         let mut key = NODE_PREFIX.to_vec();
         key.extend_from_slice(&id.0.as_bytes());
@@ -107,3 +1033,567 @@ impl GraphStorage {
     }
 }
 
+/// Node and edge writes staged in a single `WriteBatch`, together with their index
+/// and adjacency entries. Reads through the batch see staged records, so the same record can
+/// be written several times before `commit` and the index entries still line up.
+pub struct StorageBatch<'a> {
+    storage: &'a GraphStorage,
+    batch: WriteBatch,
+    // Staged state per record; `None` marks a staged delete
+    nodes: HashMap<NodeId, Option<Entity>>,
+    edges: HashMap<uuid::Uuid, Option<Edge>>,
+    // Change to the full-text index statistics, applied on commit
+    text_documents: i64,
+    text_length: i64,
+    // Staged HNSW nodes and entry point, `None` marking a delete like above
+    vectors: HashMap<uuid::Uuid, Option<VectorNode>>,
+    vector_entry: Option<Option<uuid::Uuid>>,
+}
+
+impl<'a> StorageBatch<'a> {
+    pub fn get_node(&self, id: &NodeId) -> Result<Option<Entity>> {
+        match self.nodes.get(id) {
+            Some(staged) => Ok(staged.clone()),
+            None => self.storage.get_node(id),
+        }
+    }
+
+    pub fn get_edge(&self, id: &uuid::Uuid) -> Result<Option<Edge>> {
+        match self.edges.get(id) {
+            Some(staged) => Ok(staged.clone()),
+            None => self.storage.get_edge(id),
+        }
+    }
+
+    pub fn put_node(&mut self, entity: &Entity) -> Result<()> {
+        if let Some(old) = self.get_node(&entity.id)? {
+            self.unindex_node(&old);
+        }
+        for key in self.storage.index_keys(entity) {
+            self.batch.put(key, b"");
+        }
+        let (entries, length) = self.storage.text_entries(entity);
+        for (key, value) in entries {
+            self.batch.put(key, value);
+        }
+        if length > 0 {
+            self.text_documents += 1;
+            self.text_length += length as i64;
+        }
+        self.batch
+            .put(self.storage.node_key(&entity.id), bincode::serialize(entity)?);
+        self.batch
+            .put(merkle_leaf_key(NODE_LEAF, &entity.id.0), hash_node(entity));
+        self.mark_dirty(&entity.id.0);
+        self.nodes.insert(entity.id.clone(), Some(entity.clone()));
+        Ok(())
+    }
+
+    pub fn delete_node(&mut self, id: &NodeId) -> Result<()> {
+        if let Some(old) = self.get_node(id)? {
+            self.unindex_node(&old);
+        }
+        self.delete_vector(id)?;
+        self.batch.delete(self.storage.node_key(id));
+        self.batch.delete(merkle_leaf_key(NODE_LEAF, &id.0));
+        self.mark_dirty(&id.0);
+        self.nodes.insert(id.clone(), None);
+        Ok(())
+    }
+
+    pub fn put_edge(&mut self, edge: &Edge) -> Result<()> {
+        if let Some(old) = self.get_edge(&edge.id)? {
+            for (key, _) in self.storage.adjacency_entries(&old) {
+                self.batch.delete(key);
+            }
+            self.batch.delete(edge_graph_key(&old));
+        }
+        for (key, value) in self.storage.adjacency_entries(edge) {
+            self.batch.put(key, value);
+        }
+        self.batch.put(edge_graph_key(edge), b"");
+        self.batch
+            .put(self.storage.edge_key(&edge.id), bincode::serialize(edge)?);
+        self.batch
+            .put(merkle_leaf_key(EDGE_LEAF, &edge.id), hash_edge(edge));
+        self.mark_dirty(&edge.id);
+        self.edges.insert(edge.id, Some(edge.clone()));
+        Ok(())
+    }
+
+    pub fn delete_edge(&mut self, id: &uuid::Uuid) -> Result<()> {
+        if let Some(old) = self.get_edge(id)? {
+            for (key, _) in self.storage.adjacency_entries(&old) {
+                self.batch.delete(key);
+            }
+            self.batch.delete(edge_graph_key(&old));
+        }
+        self.batch.delete(self.storage.edge_key(id));
+        self.batch.delete(merkle_leaf_key(EDGE_LEAF, id));
+        self.mark_dirty(id);
+        self.edges.insert(*id, None);
+        Ok(())
+    }
+
+    /// Adds or replaces the vector of a node in the HNSW index. `vector` must be normalized.
+    pub fn put_vector(&mut self, id: &NodeId, vector: Vec<f32>) -> Result<()> {
+        hnsw_insert(self, id.0, vector)
+    }
+
+    pub fn delete_vector(&mut self, id: &NodeId) -> Result<()> {
+        hnsw_remove(self, &id.0)
+    }
+
+    pub fn set_vector_model(&mut self, name: &str) {
+        self.batch.put(VECTOR_MODEL_KEY, name.as_bytes());
+    }
+
+    fn unindex_node(&mut self, old: &Entity) {
+        for key in self.storage.index_keys(old) {
+            self.batch.delete(key);
+        }
+        let (entries, length) = self.storage.text_entries(old);
+        for (key, _) in entries {
+            self.batch.delete(key);
+        }
+        if length > 0 {
+            self.text_documents -= 1;
+            self.text_length -= length as i64;
+        }
+    }
+
+    fn mark_dirty(&mut self, id: &uuid::Uuid) {
+        self.batch
+            .put(merkle_bucket_key(MERKLE_DIRTY_PREFIX, bucket_of(id)), b"");
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        if self.text_documents != 0 || self.text_length != 0 {
+            let (documents, length) = self.storage.text_stats()?;
+            self.batch.put(
+                TEXT_STATS_KEY,
+                encode_text_stats(
+                    documents.saturating_add_signed(self.text_documents),
+                    length.saturating_add_signed(self.text_length),
+                ),
+            );
+        }
+        self.storage.db.write(self.batch)?;
+        Ok(())
+    }
+
+    /// Writes revisions of a node as they are, e.g. when restoring an archived history.
+    pub fn put_node_revisions(&mut self, id: &NodeId, revisions: &[Revision<Entity>]) -> Result<()> {
+        for revision in revisions {
+            self.batch.put(
+                revision_key(REV_NODE_PREFIX, &id.0, revision.version),
+                bincode::serialize(revision)?,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn put_edge_revisions(&mut self, id: &uuid::Uuid, revisions: &[Revision<Edge>]) -> Result<()> {
+        for revision in revisions {
+            self.batch.put(
+                revision_key(REV_EDGE_PREFIX, id, revision.version),
+                bincode::serialize(revision)?,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn put_revision_log(&mut self, entry: &RevisionLogEntry) -> Result<()> {
+        self.batch
+            .put(revision_log_key(entry.version), bincode::serialize(entry)?);
+        Ok(())
+    }
+
+    /// Sets the current graph version without recording a revision.
+    pub fn set_version(&mut self, version: VersionId) {
+        self.batch.put(META_VERSION_KEY, version.0.to_be_bytes());
+    }
+
+    /// Commits and records the final state of every touched record as a revision of
+    /// `version`, in the same atomic write.
+    pub fn commit_version(mut self, version: VersionId, timestamp: i64) -> Result<()> {
+        let mut nodes: Vec<NodeId> = self.nodes.keys().cloned().collect();
+        let mut edges: Vec<uuid::Uuid> = self.edges.keys().copied().collect();
+        nodes.sort_by_key(|id| id.0);
+        edges.sort();
+
+        for id in &nodes {
+            let revision = Revision {
+                version,
+                timestamp,
+                value: self.nodes[id].as_ref(),
+            };
+            self.batch.put(
+                revision_key(REV_NODE_PREFIX, &id.0, version),
+                bincode::serialize(&revision)?,
+            );
+        }
+        for id in &edges {
+            let revision = Revision {
+                version,
+                timestamp,
+                value: self.edges[id].as_ref(),
+            };
+            self.batch.put(
+                revision_key(REV_EDGE_PREFIX, id, version),
+                bincode::serialize(&revision)?,
+            );
+        }
+
+        let entry = RevisionLogEntry {
+            version,
+            timestamp,
+            nodes,
+            edges,
+        };
+        self.batch
+            .put(revision_log_key(version), bincode::serialize(&entry)?);
+        self.batch.put(META_VERSION_KEY, version.0.to_be_bytes());
+        self.commit()
+    }
+}
+
+fn encode_text_stats(documents: u64, length: u64) -> Vec<u8> {
+    let mut value = documents.to_be_bytes().to_vec();
+    value.extend_from_slice(&length.to_be_bytes());
+    value
+}
+
+fn read_u64(db: &dyn GraphStore, key: &[u8]) -> Result<Option<u64>> {
+    match db.get(key)? {
+        Some(value) => Ok(Some(u64::from_be_bytes(value.as_slice().try_into()?))),
+        None => Ok(None),
+    }
+}
+
+fn read_u32(db: &dyn GraphStore, key: &[u8]) -> Result<Option<u32>> {
+    match db.get(key)? {
+        Some(value) => Ok(Some(u32::from_be_bytes(value.as_slice().try_into()?))),
+        None => Ok(None),
+    }
+}
+
+impl VectorStore for GraphStorage {
+    fn vector_node(&self, id: &uuid::Uuid) -> Result<Option<VectorNode>> {
+        match self.db.get(&vector_node_key(id))? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn vector_entry(&self) -> Result<Option<uuid::Uuid>> {
+        match self.db.get(VECTOR_ENTRY_KEY)? {
+            Some(data) => Ok(Some(uuid::Uuid::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl VectorStore for StorageBatch<'_> {
+    fn vector_node(&self, id: &uuid::Uuid) -> Result<Option<VectorNode>> {
+        match self.vectors.get(id) {
+            Some(staged) => Ok(staged.clone()),
+            None => self.storage.vector_node(id),
+        }
+    }
+
+    fn vector_entry(&self) -> Result<Option<uuid::Uuid>> {
+        match self.vector_entry {
+            Some(staged) => Ok(staged),
+            None => self.storage.vector_entry(),
+        }
+    }
+}
+
+impl VectorStoreMut for StorageBatch<'_> {
+    fn put_vector_node(&mut self, id: &uuid::Uuid, node: &VectorNode) -> Result<()> {
+        self.batch.put(vector_node_key(id), bincode::serialize(node)?);
+        self.vectors.insert(*id, Some(node.clone()));
+        Ok(())
+    }
+
+    fn delete_vector_node(&mut self, id: &uuid::Uuid) -> Result<()> {
+        self.batch.delete(vector_node_key(id));
+        self.vectors.insert(*id, None);
+        Ok(())
+    }
+
+    fn set_vector_entry(&mut self, entry: Option<uuid::Uuid>) -> Result<()> {
+        match entry {
+            Some(id) => self.batch.put(VECTOR_ENTRY_KEY, id.as_bytes()),
+            None => self.batch.delete(VECTOR_ENTRY_KEY),
+        }
+        self.vector_entry = Some(entry);
+        Ok(())
+    }
+}
+
+/// `idx:graph:<graph>\0<tag>`, followed by the 16-byte id of a node (`n`) or edge (`e`).
+fn graph_index_prefix(graph: &str, tag: u8) -> Vec<u8> {
+    let mut key = GRAPH_INDEX_PREFIX.to_vec();
+    key.extend_from_slice(graph.as_bytes());
+    key.push(KEY_SEPARATOR);
+    key.push(tag);
+    key
+}
+
+fn edge_graph_key(edge: &Edge) -> Vec<u8> {
+    let mut key = graph_index_prefix(graph_of(edge.graph.as_deref()), GRAPH_EDGE_TAG);
+    key.extend_from_slice(edge.id.as_bytes());
+    key
+}
+
+fn geo_index_prefix(property: &str) -> Vec<u8> {
+    let mut key = GEO_INDEX_PREFIX.to_vec();
+    key.extend_from_slice(property.as_bytes());
+    key.push(KEY_SEPARATOR);
+    key
+}
+
+fn vector_node_key(id: &uuid::Uuid) -> Vec<u8> {
+    let mut key = VECTOR_NODE_PREFIX.to_vec();
+    key.extend_from_slice(id.as_bytes());
+    key
+}
+
+/// `prefix + record id + version`, big-endian so that a record's revisions sort by version.
+fn revision_key(prefix: &[u8], id: &uuid::Uuid, version: VersionId) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(id.as_bytes());
+    key.extend_from_slice(&version.0.to_be_bytes());
+    key
+}
+
+fn revision_log_key(version: VersionId) -> Vec<u8> {
+    let mut key = REV_LOG_PREFIX.to_vec();
+    key.extend_from_slice(&version.0.to_be_bytes());
+    key
+}
+
+/// `prefix + bucket + kind + id`, so that a bucket's leaves are contiguous and sorted.
+fn merkle_leaf_key(kind: u8, id: &uuid::Uuid) -> Vec<u8> {
+    let mut key = MERKLE_LEAF_PREFIX.to_vec();
+    key.push(bucket_of(id));
+    key.push(kind);
+    key.extend_from_slice(id.as_bytes());
+    key
+}
+
+fn merkle_bucket_key(prefix: &[u8], bucket: u8) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.push(bucket);
+    key
+}
+
+fn checkpoint_key(id: &uuid::Uuid) -> Vec<u8> {
+    let mut key = CHECKPOINT_PREFIX.to_vec();
+    key.extend_from_slice(id.as_bytes());
+    key
+}
+
+fn decode_version(key: &[u8]) -> Result<u64> {
+    let bytes: [u8; 8] = key
+        .get(key.len().saturating_sub(8)..)
+        .and_then(|tail| tail.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Malformed revision key"))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Big-endian with the sign bit flipped, so that byte order matches numeric order.
+fn encode_timestamp(timestamp: i64) -> [u8; 8] {
+    ((timestamp as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+/// Splits a time index key into timestamp, field and node. Malformed keys yield `None`.
+fn decode_time_key(key: &[u8]) -> Result<Option<(i64, String, NodeId)>> {
+    let Some(rest) = key.strip_prefix(TIME_INDEX_PREFIX) else {
+        return Ok(None);
+    };
+    if rest.len() < 8 + 17 {
+        return Ok(None);
+    }
+    let timestamp = (u64::from_be_bytes(rest[..8].try_into()?) ^ (1 << 63)) as i64;
+    let field = String::from_utf8_lossy(&rest[8..rest.len() - 17]).into_owned();
+    let id = uuid::Uuid::from_slice(&rest[rest.len() - 16..])?;
+    Ok(Some((timestamp, field, NodeId::from_uuid(id))))
+}
+
+/// Order-preserving encoding of a scalar value for index keys: a type tag followed by a
+/// payload whose byte order matches the value order. Lists and maps are not encodable, and
+/// points go to the geohash index instead.
+fn encode_index_value(value: &PropertyValue) -> Option<Vec<u8>> {
+    let mut encoded = Vec::new();
+    match value {
+        PropertyValue::String(s) => {
+            encoded.push(b's');
+            encoded.extend_from_slice(s.as_bytes());
+        }
+        PropertyValue::Number(n) => {
+            if n.is_nan() {
+                return None;
+            }
+            // -0.0 and 0.0 compare equal, so they must share a key
+            let bits = if *n == 0.0 { 0u64 } else { n.to_bits() };
+            let ordered = if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) };
+            encoded.push(b'n');
+            encoded.extend_from_slice(&ordered.to_be_bytes());
+        }
+        PropertyValue::Boolean(b) => {
+            encoded.push(b'b');
+            encoded.push(*b as u8);
+        }
+        PropertyValue::DateTime(t) => {
+            encoded.push(b't');
+            encoded.extend_from_slice(&encode_timestamp(*t));
+        }
+        PropertyValue::Reference(id) => {
+            encoded.push(b'r');
+            encoded.extend_from_slice(id.0.as_bytes());
+        }
+        PropertyValue::List(_) | PropertyValue::Map(_) | PropertyValue::GeoPoint(_) => return None,
+    }
+    Some(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_node_properties_round_trip() {
+        let storage = GraphStorage::in_memory().unwrap();
+        let node = Entity {
+            id: NodeId::new(),
+            label: "email".to_string(),
+            properties: HashMap::from([
+                ("subject".to_string(), PropertyValue::String("Invoice".to_string())),
+                ("size".to_string(), PropertyValue::Number(1024.0)),
+                ("read".to_string(), PropertyValue::Boolean(false)),
+                ("sent_at".to_string(), PropertyValue::DateTime(1_700_000_000)),
+                ("thread".to_string(), PropertyValue::Reference(NodeId::new())),
+                (
+                    "tags".to_string(),
+                    PropertyValue::List(vec![PropertyValue::String("work".to_string())]),
+                ),
+                (
+                    "headers".to_string(),
+                    PropertyValue::Map(HashMap::from([(
+                        "from".to_string(),
+                        PropertyValue::String("a@example.com".to_string()),
+                    )])),
+                ),
+            ]),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        };
+        storage.put_node(&node).unwrap();
+
+        let stored = storage.get_node(&node.id).unwrap().unwrap();
+        assert_eq!(stored.properties, node.properties);
+        let listed: Vec<Entity> = storage.iter_nodes().collect::<Result<_>>().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].properties, node.properties);
+    }
+
+    fn note(label: &str, properties: Vec<(&str, PropertyValue)>) -> Entity {
+        Entity {
+            id: NodeId::new(),
+            label: label.to_string(),
+            properties: properties
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    #[test]
+    fn test_indexes_follow_updates_and_deletes() {
+        let storage = GraphStorage::in_memory().unwrap();
+        let by_size = |operator, size: f64| {
+            storage
+                .find_by_property("size", operator, &PropertyValue::Number(size))
+                .unwrap()
+                .unwrap()
+        };
+        let mut node = note("email", vec![("size", PropertyValue::Number(100.0))]);
+        storage.put_node(&node).unwrap();
+        assert_eq!(storage.find_by_label("email").unwrap(), vec![node.id.clone()]);
+        assert_eq!(by_size(FilterOperator::Equals, 100.0), vec![node.id.clone()]);
+
+        // Relabelling and changing a property move the entries instead of adding to them
+        node.label = "draft".to_string();
+        node.properties.insert("size".to_string(), PropertyValue::Number(250.0));
+        storage.put_node(&node).unwrap();
+        assert!(storage.find_by_label("email").unwrap().is_empty());
+        assert_eq!(storage.find_by_label("draft").unwrap(), vec![node.id.clone()]);
+        assert!(by_size(FilterOperator::Equals, 100.0).is_empty());
+        assert_eq!(by_size(FilterOperator::GreaterThan, 200.0), vec![node.id.clone()]);
+
+        // A removed property leaves no entry behind
+        node.properties.clear();
+        storage.put_node(&node).unwrap();
+        assert!(by_size(FilterOperator::GreaterThanOrEqual, 0.0).is_empty());
+
+        node.properties.insert("size".to_string(), PropertyValue::Number(250.0));
+        storage.put_node(&node).unwrap();
+        storage.delete_node(&node.id).unwrap();
+        assert!(storage.find_by_label("draft").unwrap().is_empty());
+        assert!(storage.find_by_label_prefix("").unwrap().is_empty());
+        assert!(by_size(FilterOperator::GreaterThanOrEqual, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_adjacency_follows_edge_updates_and_deletes() {
+        let storage = GraphStorage::in_memory().unwrap();
+        let (a, b, c) = (NodeId::new(), NodeId::new(), NodeId::new());
+        let mut edge = Edge {
+            id: uuid::Uuid::new_v4(),
+            from: a.clone(),
+            to: b.clone(),
+            label: "reply".to_string(),
+            properties: HashMap::new(),
+            created_at: 0,
+            version: 1,
+            graph: None,
+        };
+        let edges = |node: &NodeId, direction, label| -> Vec<(uuid::Uuid, NodeId)> {
+            storage
+                .adjacent(node, direction, label)
+                .unwrap()
+                .into_iter()
+                .map(|adjacency| (adjacency.edge_id, adjacency.neighbor))
+                .collect()
+        };
+        storage.put_edge(&edge).unwrap();
+        assert_eq!(edges(&a, Direction::Outgoing, Some("reply")), vec![(edge.id, b.clone())]);
+        assert_eq!(edges(&b, Direction::Incoming, None), vec![(edge.id, a.clone())]);
+        assert!(edges(&a, Direction::Incoming, None).is_empty());
+
+        // Relabelling and re-pointing the edge drops the old entries on both ends
+        edge.label = "forward".to_string();
+        edge.to = c.clone();
+        storage.put_edge(&edge).unwrap();
+        assert!(edges(&a, Direction::Outgoing, Some("reply")).is_empty());
+        assert_eq!(edges(&a, Direction::Outgoing, Some("forward")), vec![(edge.id, c.clone())]);
+        assert!(edges(&b, Direction::Both, None).is_empty());
+        assert_eq!(edges(&c, Direction::Incoming, Some("forward")), vec![(edge.id, a.clone())]);
+
+        storage.delete_edge(&edge.id).unwrap();
+        for node in [&a, &b, &c] {
+            assert!(edges(node, Direction::Both, None).is_empty());
+        }
+    }
+
+}
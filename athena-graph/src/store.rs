@@ -0,0 +1,389 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+pub type StoreIter<'a> = Box<dyn Iterator<Item = Result<KeyValue>> + 'a>;
+
+/// Entries the in-memory store copies out per lock acquisition while iterating.
+const MEMORY_CHUNK: usize = 256;
+
+/// Key-value engine under `GraphStorage`. Keys are ordered bytewise; every layout decision
+/// (prefixes, index encodings) stays in `GraphStorage`, so a backend only has to store bytes.
+pub trait GraphStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
+    fn delete(&self, key: &[u8]) -> Result<()>;
+    /// Entries with keys `>= start`, ascending.
+    fn iter_from(&self, start: &[u8]) -> StoreIter<'_>;
+    /// Entries with keys `<= start`, descending.
+    fn iter_back_from(&self, start: &[u8]) -> StoreIter<'_>;
+    /// Applies every operation of the batch atomically.
+    fn write(&self, batch: WriteBatch) -> Result<()>;
+    /// Writes a consistent copy of the store to `path`, which must not exist yet.
+    fn snapshot(&self, path: &Path) -> Result<()>;
+    /// Opens a copy written by `snapshot` for reading.
+    fn open_snapshot(&self, path: &Path) -> Result<Arc<dyn GraphStore>>;
+
+    /// Entries whose key starts with `prefix`, ascending.
+    fn scan_prefix<'a>(&'a self, prefix: &'a [u8]) -> StoreIter<'a> {
+        Box::new(
+            self.iter_from(prefix)
+                .take_while(move |item| item.as_ref().map_or(true, |(key, _)| key.starts_with(prefix))),
+        )
+    }
+}
+
+/// Which `GraphStore` implementation backs the graph database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    RocksDb,
+    Sled,
+    /// Nothing is persisted; meant for tests and throwaway instances.
+    Memory,
+}
+
+impl StorageBackend {
+    /// Opens (or creates) a store of this kind at `path`. `Memory` ignores the path.
+    pub fn open(self, path: &Path) -> Result<Arc<dyn GraphStore>> {
+        Ok(match self {
+            StorageBackend::RocksDb => Arc::new(RocksStore::open(path)?),
+            StorageBackend::Sled => Arc::new(SledStore::open(path)?),
+            StorageBackend::Memory => Arc::new(MemoryStore::new()),
+        })
+    }
+}
+
+/// Puts and deletes applied together by `GraphStore::write`, in order.
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) {
+        self.ops.push((key.as_ref().to_vec(), Some(value.as_ref().to_vec())));
+    }
+
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.ops.push((key.as_ref().to_vec(), None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    /// A key with its new value, or `None` for a delete.
+    type Item = (Vec<u8>, Option<Vec<u8>>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
+pub struct RocksStore {
+    db: rocksdb::DB,
+}
+
+impl RocksStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.set_max_open_files(10000);
+        opts.set_write_buffer_size(64 * 1024 * 1024);
+        Ok(Self {
+            db: rocksdb::DB::open(&opts, path)?,
+        })
+    }
+
+    fn iter(&self, start: &[u8], direction: rocksdb::Direction) -> StoreIter<'_> {
+        Box::new(
+            self.db
+                .iterator(rocksdb::IteratorMode::From(start, direction))
+                .map(|item| {
+                    let (key, value) = item?;
+                    Ok((key.to_vec(), value.to_vec()))
+                }),
+        )
+    }
+}
+
+impl GraphStore for RocksStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key)?)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        Ok(self.db.put(key, value)?)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        Ok(self.db.delete(key)?)
+    }
+
+    fn iter_from(&self, start: &[u8]) -> StoreIter<'_> {
+        self.iter(start, rocksdb::Direction::Forward)
+    }
+
+    fn iter_back_from(&self, start: &[u8]) -> StoreIter<'_> {
+        self.iter(start, rocksdb::Direction::Reverse)
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut rocks = rocksdb::WriteBatch::default();
+        for (key, value) in batch {
+            match value {
+                Some(value) => rocks.put(key, value),
+                None => rocks.delete(key),
+            }
+        }
+        Ok(self.db.write(rocks)?)
+    }
+
+    /// Files are hard-linked where possible, so a snapshot costs little until the data diverges.
+    fn snapshot(&self, path: &Path) -> Result<()> {
+        rocksdb::checkpoint::Checkpoint::new(&self.db)?.create_checkpoint(path)?;
+        Ok(())
+    }
+
+    fn open_snapshot(&self, path: &Path) -> Result<Arc<dyn GraphStore>> {
+        let db = rocksdb::DB::open_for_read_only(&rocksdb::Options::default(), path, false)?;
+        Ok(Arc::new(Self { db }))
+    }
+}
+
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self { db: sled::open(path)? })
+    }
+}
+
+fn sled_entry(item: sled::Result<(sled::IVec, sled::IVec)>) -> Result<KeyValue> {
+    let (key, value) = item?;
+    Ok((key.to_vec(), value.to_vec()))
+}
+
+impl GraphStore for SledStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.db.insert(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.db.remove(key)?;
+        Ok(())
+    }
+
+    fn iter_from(&self, start: &[u8]) -> StoreIter<'_> {
+        Box::new(self.db.range(start.to_vec()..).map(sled_entry))
+    }
+
+    fn iter_back_from(&self, start: &[u8]) -> StoreIter<'_> {
+        Box::new(self.db.range(..=start.to_vec()).rev().map(sled_entry))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut sled_batch = sled::Batch::default();
+        for (key, value) in batch {
+            match value {
+                Some(value) => sled_batch.insert(key, value),
+                None => sled_batch.remove(key),
+            }
+        }
+        self.db.apply_batch(sled_batch)?;
+        Ok(())
+    }
+
+    /// sled has no checkpoints; the entries are copied into a new database. Concurrent
+    /// writes must be held off by the caller for the copy to be consistent.
+    fn snapshot(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            anyhow::bail!("Snapshot path {} already exists", path.display());
+        }
+        let copy = sled::open(path)?;
+        let mut batch = sled::Batch::default();
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            batch.insert(key, value);
+        }
+        copy.apply_batch(batch)?;
+        copy.flush()?;
+        Ok(())
+    }
+
+    fn open_snapshot(&self, path: &Path) -> Result<Arc<dyn GraphStore>> {
+        Ok(Arc::new(Self::open(path)?))
+    }
+}
+
+/// Keeps everything in a sorted map. Iterators copy entries out in chunks instead of
+/// holding the lock, so writing while iterating (as index rebuilds do) cannot deadlock;
+/// like the other backends, entries written behind the cursor are simply not seen.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.data.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_lock(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.data.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl GraphStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.read().get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write_lock().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.write_lock().remove(key);
+        Ok(())
+    }
+
+    fn iter_from(&self, start: &[u8]) -> StoreIter<'_> {
+        Box::new(MemoryIter {
+            store: self,
+            bound: Bound::Included(start.to_vec()),
+            reverse: false,
+            buffer: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn iter_back_from(&self, start: &[u8]) -> StoreIter<'_> {
+        Box::new(MemoryIter {
+            store: self,
+            bound: Bound::Included(start.to_vec()),
+            reverse: true,
+            buffer: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut data = self.write_lock();
+        for (key, value) in batch {
+            match value {
+                Some(value) => data.insert(key, value),
+                None => data.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self, _path: &Path) -> Result<()> {
+        anyhow::bail!("The in-memory graph store does not support snapshots")
+    }
+
+    fn open_snapshot(&self, _path: &Path) -> Result<Arc<dyn GraphStore>> {
+        anyhow::bail!("The in-memory graph store does not support snapshots")
+    }
+}
+
+struct MemoryIter<'a> {
+    store: &'a MemoryStore,
+    // Where the next chunk starts: the original start, then just past the last key returned
+    bound: Bound<Vec<u8>>,
+    reverse: bool,
+    buffer: VecDeque<KeyValue>,
+    done: bool,
+}
+
+impl MemoryIter<'_> {
+    fn fill(&mut self) {
+        let data = self.store.read();
+        let chunk: VecDeque<KeyValue> = if self.reverse {
+            data.range((Bound::Unbounded, self.bound.clone()))
+                .rev()
+                .take(MEMORY_CHUNK)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        } else {
+            data.range((self.bound.clone(), Bound::Unbounded))
+                .take(MEMORY_CHUNK)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        };
+        self.done = chunk.len() < MEMORY_CHUNK;
+        if let Some((key, _)) = chunk.back() {
+            self.bound = Bound::Excluded(key.clone());
+        }
+        self.buffer = chunk;
+    }
+}
+
+impl Iterator for MemoryIter<'_> {
+    type Item = Result<KeyValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.is_empty() && !self.done {
+            self.fill();
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_iteration_across_chunks() {
+        let store = MemoryStore::new();
+        let mut batch = WriteBatch::default();
+        for i in 0..1000u32 {
+            batch.put(format!("a:{:04}", i), i.to_be_bytes());
+        }
+        batch.put("b:0", b"");
+        store.write(batch).unwrap();
+
+        let keys: Vec<Vec<u8>> = store.scan_prefix(b"a:").map(|item| item.unwrap().0).collect();
+        assert_eq!(keys.len(), 1000);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Reverse iteration starts at the last key at or before the seek key
+        let (last, _) = store.iter_back_from(b"a:0500~").next().unwrap().unwrap();
+        assert_eq!(last, b"a:0500".to_vec());
+        assert_eq!(store.iter_back_from(b"a:0999").count(), 1000);
+
+        // Writes during iteration must not block
+        for item in store.scan_prefix(b"a:") {
+            let (key, _) = item.unwrap();
+            store.delete(&key).unwrap();
+        }
+        assert_eq!(store.iter_from(b"").count(), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::entity::{Edge, Entity, GraphUpdate};
+use crate::query::{FilterExpr, GraphPattern};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use uuid::Uuid;
+
+/// Updates buffered per subscriber before new ones start being dropped.
+pub const SUBSCRIPTION_BUFFER: usize = 256;
+
+/// Consecutive dropped updates after which a subscriber is disconnected.
+pub const MAX_LAGGED_UPDATES: u64 = 1024;
+
+/// Live feed of the graph changes matching a pattern. Dropping the receiver (or calling
+/// `GraphEngine::unsubscribe` with `id`) ends the subscription.
+#[derive(Debug)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub receiver: mpsc::Receiver<GraphUpdate>,
+}
+
+/// Everything a single write changed, with the previous state of modified and deleted
+/// records so that patterns can be matched against what the subscriber last saw.
+#[derive(Debug, Default)]
+pub struct ChangeSet {
+    pub nodes: Vec<(Option<Entity>, Entity)>,
+    pub edges: Vec<(Option<Edge>, Edge)>,
+    pub deleted_nodes: Vec<Entity>,
+    pub deleted_edges: Vec<Edge>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+            && self.edges.is_empty()
+            && self.deleted_nodes.is_empty()
+            && self.deleted_edges.is_empty()
+    }
+}
+
+struct Subscriber {
+    node_expr: FilterExpr,
+    pattern: GraphPattern,
+    sender: mpsc::Sender<GraphUpdate>,
+    lagged: u64,
+}
+
+impl Subscriber {
+    fn node_matches(&self, node: &Entity) -> bool {
+        self.node_expr.matches(node)
+    }
+
+    fn edge_matches(&self, edge: &Edge) -> bool {
+        self.pattern.edge_matches(edge)
+    }
+
+    /// Same selection rules as `GraphEngine::query`; a modified record is included when
+    /// either its old or its new state matches, so subscribers also see it leave the pattern.
+    fn select(&self, changes: &ChangeSet) -> GraphUpdate {
+        GraphUpdate {
+            nodes: changes
+                .nodes
+                .iter()
+                .filter(|(old, new)| {
+                    self.node_matches(new) || old.as_ref().is_some_and(|old| self.node_matches(old))
+                })
+                .map(|(_, new)| new.clone())
+                .collect(),
+            edges: changes
+                .edges
+                .iter()
+                .filter(|(old, new)| {
+                    self.edge_matches(new) || old.as_ref().is_some_and(|old| self.edge_matches(old))
+                })
+                .map(|(_, new)| new.clone())
+                .collect(),
+            deleted_nodes: changes
+                .deleted_nodes
+                .iter()
+                .filter(|node| self.node_matches(node))
+                .map(|node| node.id.clone())
+                .collect(),
+            deleted_edges: changes
+                .deleted_edges
+                .iter()
+                .filter(|edge| self.edge_matches(edge))
+                .map(|edge| edge.id)
+                .collect(),
+        }
+    }
+}
+
+/// Fan-out of graph changes to pattern subscribers.
+///
+/// Publishing never blocks a write: updates are handed over with `try_send`, a full buffer
+/// drops the update for that subscriber only, and a subscriber that keeps its buffer full
+/// for `MAX_LAGGED_UPDATES` updates in a row is disconnected.
+pub struct SubscriptionRegistry {
+    subscribers: Mutex<HashMap<Uuid, Subscriber>>,
+    buffer: usize,
+}
+
+impl SubscriptionRegistry {
+    pub fn new() -> Self {
+        Self::with_buffer(SUBSCRIPTION_BUFFER)
+    }
+
+    pub fn with_buffer(buffer: usize) -> Self {
+        Self {
+            subscribers: Mutex::new(HashMap::new()),
+            buffer: buffer.max(1),
+        }
+    }
+
+    pub fn subscribe(&self, pattern: &GraphPattern) -> Subscription {
+        let (sender, receiver) = mpsc::channel(self.buffer);
+        let id = Uuid::new_v4();
+        self.lock().insert(
+            id,
+            Subscriber {
+                node_expr: pattern.node_expr(),
+                pattern: pattern.clone(),
+                sender,
+                lagged: 0,
+            },
+        );
+        Subscription { id, receiver }
+    }
+
+    pub fn unsubscribe(&self, id: &Uuid) -> bool {
+        self.lock().remove(id).is_some()
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.lock().is_empty()
+    }
+
+    pub fn publish(&self, changes: &ChangeSet) {
+        if changes.is_empty() {
+            return;
+        }
+
+        self.lock().retain(|id, subscriber| {
+            let update = subscriber.select(changes);
+            if update.nodes.is_empty()
+                && update.edges.is_empty()
+                && update.deleted_nodes.is_empty()
+                && update.deleted_edges.is_empty()
+            {
+                return !subscriber.sender.is_closed();
+            }
+
+            match subscriber.sender.try_send(update) {
+                Ok(()) => {
+                    subscriber.lagged = 0;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    subscriber.lagged += 1;
+                    if subscriber.lagged >= MAX_LAGGED_UPDATES {
+                        tracing::warn!("Disconnecting subscription {} after {} dropped updates", id, subscriber.lagged);
+                        false
+                    } else {
+                        tracing::debug!("Subscription {} is lagging, dropped an update", id);
+                        true
+                    }
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Subscriber>> {
+        self.subscribers.lock().expect("subscription registry lock poisoned")
+    }
+}
+
+impl Default for SubscriptionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{NodeId, PropertyValue};
+    use crate::query::{EdgeFilter, FilterOperator, NodeFilter};
+
+    fn entity(kind: &str) -> Entity {
+        Entity {
+            id: NodeId::new(),
+            label: kind.to_string(),
+            properties: HashMap::from([(
+                "type".to_string(),
+                PropertyValue::String(kind.to_string()),
+            )]),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            graph: None,
+        }
+    }
+
+    #[test]
+    fn test_publish_matches_pattern_and_drops_lagging() {
+        let registry = SubscriptionRegistry::with_buffer(1);
+        let pattern = GraphPattern {
+            node_filters: vec![NodeFilter::new(
+                "type",
+                FilterOperator::Equals,
+                PropertyValue::String("email".to_string()),
+            )],
+            edge_filters: vec![EdgeFilter {
+                from: None,
+                to: None,
+                label: Some("reply".to_string()),
+            }],
+            ..Default::default()
+        };
+        let mut subscription = registry.subscribe(&pattern);
+
+        let email = entity("email");
+        registry.publish(&ChangeSet {
+            nodes: vec![(None, entity("file")), (None, email.clone())],
+            ..Default::default()
+        });
+        let update = subscription.receiver.try_recv().unwrap();
+        assert_eq!(update.nodes.len(), 1);
+        assert_eq!(update.nodes[0].id, email.id);
+
+        // Non-matching changes are not delivered at all
+        registry.publish(&ChangeSet {
+            nodes: vec![(None, entity("file"))],
+            ..Default::default()
+        });
+        assert!(subscription.receiver.try_recv().is_err());
+
+        // Buffer of one: keep it full until the subscriber is cut off
+        for _ in 0..=MAX_LAGGED_UPDATES {
+            registry.publish(&ChangeSet {
+                deleted_nodes: vec![email.clone()],
+                ..Default::default()
+            });
+        }
+        assert!(!registry.has_subscribers());
+        assert!(subscription.receiver.try_recv().is_ok());
+
+        let other = registry.subscribe(&GraphPattern::default());
+        assert!(registry.unsubscribe(&other.id));
+        assert!(!registry.unsubscribe(&other.id));
+    }
+}
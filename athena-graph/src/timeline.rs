@@ -0,0 +1,163 @@
+use crate::entity::{Entity, PropertyValue};
+use serde::{Deserialize, Serialize};
+
+const DAY: i64 = 86_400;
+
+/// Selects timestamps (unix seconds) from the time index. `from` is inclusive, `to`
+/// exclusive; a missing bound leaves that side open.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeQuery {
+    #[serde(default)]
+    pub from: Option<i64>,
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Timestamp fields to include: `created_at`, `updated_at` or a `DateTime` property.
+    /// Empty means all of them.
+    #[serde(default)]
+    pub fields: Vec<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub newest_first: bool,
+}
+
+impl TimeQuery {
+    pub fn between(from: i64, to: i64) -> Self {
+        Self {
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        }
+    }
+
+    /// Everything from `days` days ago until now, newest first.
+    pub fn last_days(days: u32) -> Self {
+        Self {
+            from: Some(chrono::Utc::now().timestamp() - days as i64 * DAY),
+            newest_first: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn includes_field(&self, field: &str) -> bool {
+        self.fields.is_empty() || self.fields.iter().any(|f| f == field)
+    }
+}
+
+/// One timestamp of a node: when it was created, updated, or a `DateTime` property.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    pub timestamp: i64,
+    pub field: String,
+    pub node: Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BucketSize {
+    Hour,
+    Day,
+    /// Weeks start on Monday.
+    Week,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub start: i64,
+    pub count: u64,
+}
+
+/// Start of the bucket holding `timestamp`, with bucket boundaries at local midnight for a
+/// timezone `utc_offset` seconds east of UTC.
+pub fn bucket_start(timestamp: i64, size: BucketSize, utc_offset: i64) -> i64 {
+    let local = timestamp + utc_offset;
+    let start = match size {
+        BucketSize::Hour => local - local.rem_euclid(3_600),
+        BucketSize::Day => local - local.rem_euclid(DAY),
+        BucketSize::Week => {
+            // 1970-01-01 was a Thursday, three days after a Monday
+            let days = local.div_euclid(DAY);
+            (days - (days + 3).rem_euclid(7)) * DAY
+        }
+    };
+    start - utc_offset
+}
+
+fn bucket_end(start: i64, size: BucketSize, utc_offset: i64) -> i64 {
+    let length = match size {
+        BucketSize::Hour => 3_600,
+        BucketSize::Day => DAY,
+        BucketSize::Week => 7 * DAY,
+    };
+    bucket_start(start + length, size, utc_offset)
+}
+
+/// Counts timestamps per bucket, oldest first. Buckets between the first and the last
+/// non-empty one are included with a count of zero, so the result plots as is.
+pub fn histogram<I>(timestamps: I, size: BucketSize, utc_offset: i64) -> Vec<HistogramBucket>
+where
+    I: IntoIterator<Item = i64>,
+{
+    let mut counts = std::collections::BTreeMap::new();
+    for timestamp in timestamps {
+        *counts.entry(bucket_start(timestamp, size, utc_offset)).or_insert(0u64) += 1;
+    }
+    let (Some(&first), Some(&last)) = (counts.keys().next(), counts.keys().next_back()) else {
+        return Vec::new();
+    };
+
+    let mut buckets = Vec::new();
+    let mut start = first;
+    while start <= last {
+        buckets.push(HistogramBucket {
+            start,
+            count: counts.get(&start).copied().unwrap_or(0),
+        });
+        start = bucket_end(start, size, utc_offset);
+    }
+    buckets
+}
+
+/// The timestamps a node is indexed under, as `(field, timestamp)`. `DateTime` values
+/// inside lists count under the list's property name.
+pub fn time_fields(entity: &Entity) -> Vec<(String, i64)> {
+    let mut fields = vec![
+        ("created_at".to_string(), entity.created_at),
+        ("updated_at".to_string(), entity.updated_at),
+    ];
+    for (name, value) in &entity.properties {
+        match value {
+            PropertyValue::DateTime(t) => fields.push((name.clone(), *t)),
+            PropertyValue::List(items) => fields.extend(items.iter().filter_map(|item| match item {
+                PropertyValue::DateTime(t) => Some((name.clone(), *t)),
+                _ => None,
+            })),
+            _ => {}
+        }
+    }
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_boundaries() {
+        // 2024-03-06 (a Wednesday) 10:30 UTC
+        let t = 1_709_721_000;
+        assert_eq!(bucket_start(t, BucketSize::Day, 0), 1_709_683_200);
+        // Monday 2024-03-04
+        assert_eq!(bucket_start(t, BucketSize::Week, 0), 1_709_510_400);
+        // UTC+3: the local day started at 21:00 UTC the evening before
+        assert_eq!(bucket_start(t, BucketSize::Day, 3 * 3_600), 1_709_672_400);
+        assert_eq!(bucket_start(-1, BucketSize::Day, 0), -DAY);
+
+        let buckets = histogram([t, t + 60, t + 2 * DAY], BucketSize::Day, 0);
+        let counts: Vec<u64> = buckets.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![2, 0, 1]);
+        assert_eq!(buckets[1].start, buckets[0].start + DAY);
+    }
+}
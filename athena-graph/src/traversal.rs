@@ -0,0 +1,302 @@
+use crate::entity::{Edge, Entity, NodeId};
+use crate::query::{Direction, FilterExpr};
+use crate::storage::{Adjacency, GraphStorage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+fn default_depth() -> usize {
+    3
+}
+
+fn default_limit() -> usize {
+    1000
+}
+
+fn default_direction() -> Direction {
+    Direction::Outgoing
+}
+
+/// k-hop neighborhood of a start node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpandQuery {
+    pub start: NodeId,
+    #[serde(default = "default_depth")]
+    pub max_depth: usize,
+    #[serde(default = "default_direction")]
+    pub direction: Direction,
+    /// Edge labels that may be followed; empty means any label.
+    #[serde(default)]
+    pub edge_labels: Vec<String>,
+    /// Maximum number of nodes returned, the start node included.
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+/// One hop of a path pattern: follow an edge with `label` (any label if `None`) in
+/// `direction`, landing on a node that matches `filter`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathStep {
+    pub label: Option<String>,
+    #[serde(default = "default_direction")]
+    pub direction: Direction,
+    #[serde(default)]
+    pub filter: Option<FilterExpr>,
+}
+
+/// Label-constrained path pattern such as `a -[authored]-> b -[mentions]-> c`.
+/// Paths start at `start` if given, otherwise at every node matching `start_filter`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathQuery {
+    #[serde(default)]
+    pub start: Option<NodeId>,
+    #[serde(default)]
+    pub start_filter: Option<FilterExpr>,
+    pub steps: Vec<PathStep>,
+    /// Maximum number of paths returned.
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortestPathQuery {
+    pub from: NodeId,
+    pub to: NodeId,
+    #[serde(default = "default_depth")]
+    pub max_depth: usize,
+    #[serde(default = "default_direction")]
+    pub direction: Direction,
+    #[serde(default)]
+    pub edge_labels: Vec<String>,
+    /// Maximum number of nodes visited before the search gives up.
+    #[serde(default = "default_visit_limit")]
+    pub max_visited: usize,
+}
+
+fn default_visit_limit() -> usize {
+    100_000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeighborhoodNode {
+    pub entity: Entity,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Neighborhood {
+    pub nodes: Vec<NeighborhoodNode>,
+    pub edges: Vec<Edge>,
+}
+
+/// Alternating node/edge sequence: `edges[i]` connects `nodes[i]` and `nodes[i + 1]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphPath {
+    pub nodes: Vec<Entity>,
+    pub edges: Vec<Edge>,
+}
+
+pub struct Traversal<'a> {
+    storage: &'a GraphStorage,
+}
+
+impl<'a> Traversal<'a> {
+    pub fn new(storage: &'a GraphStorage) -> Self {
+        Self { storage }
+    }
+
+    pub fn expand(&self, query: &ExpandQuery) -> Result<Neighborhood> {
+        let start = match self.storage.get_node(&query.start)? {
+            Some(start) => start,
+            None => return Ok(Neighborhood { nodes: vec![], edges: vec![] }),
+        };
+
+        let mut visited = HashSet::from([start.id.clone()]);
+        let mut seen_edges = HashSet::new();
+        let mut nodes = vec![NeighborhoodNode { entity: start, depth: 0 }];
+        let mut edges = Vec::new();
+        let mut frontier = vec![query.start.clone()];
+
+        'outer: for depth in 1..=query.max_depth {
+            let mut next = Vec::new();
+            for node in &frontier {
+                for adjacency in self.adjacent(node, query.direction, &query.edge_labels)? {
+                    if nodes.len() >= query.limit && !visited.contains(&adjacency.neighbor) {
+                        break 'outer;
+                    }
+                    if !seen_edges.insert(adjacency.edge_id) {
+                        continue;
+                    }
+                    let edge = match self.storage.get_edge(&adjacency.edge_id)? {
+                        Some(edge) => edge,
+                        None => continue,
+                    };
+                    if visited.insert(adjacency.neighbor.clone()) {
+                        match self.storage.get_node(&adjacency.neighbor)? {
+                            Some(entity) => nodes.push(NeighborhoodNode { entity, depth }),
+                            // Dangling edge: don't report it or walk past it
+                            None => continue,
+                        }
+                        next.push(adjacency.neighbor.clone());
+                    }
+                    edges.push(edge);
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+
+        Ok(Neighborhood { nodes, edges })
+    }
+
+    pub fn find_paths(&self, starts: &[NodeId], query: &PathQuery) -> Result<Vec<GraphPath>> {
+        let mut paths = Vec::new();
+        for start in starts {
+            if paths.len() >= query.limit {
+                break;
+            }
+            if let Some(entity) = self.storage.get_node(start)? {
+                let mut current = GraphPath {
+                    nodes: vec![entity],
+                    edges: vec![],
+                };
+                self.extend_path(&mut current, &query.steps, query.limit, &mut paths)?;
+            }
+        }
+        Ok(paths)
+    }
+
+    fn extend_path(
+        &self,
+        current: &mut GraphPath,
+        steps: &[PathStep],
+        limit: usize,
+        paths: &mut Vec<GraphPath>,
+    ) -> Result<()> {
+        let (step, rest) = match steps.split_first() {
+            Some(split) => split,
+            None => {
+                paths.push(current.clone());
+                return Ok(());
+            }
+        };
+
+        let tail = current.nodes.last().map(|n| n.id.clone()).expect("path is never empty");
+        for adjacency in self.storage.adjacent(&tail, step.direction, step.label.as_deref())? {
+            if paths.len() >= limit {
+                break;
+            }
+            // Only simple paths: never revisit a node
+            if current.nodes.iter().any(|n| n.id == adjacency.neighbor) {
+                continue;
+            }
+            let (edge, node) = match (
+                self.storage.get_edge(&adjacency.edge_id)?,
+                self.storage.get_node(&adjacency.neighbor)?,
+            ) {
+                (Some(edge), Some(node)) => (edge, node),
+                _ => continue,
+            };
+            if let Some(filter) = &step.filter {
+                if !filter.matches(&node) {
+                    continue;
+                }
+            }
+
+            current.edges.push(edge);
+            current.nodes.push(node);
+            self.extend_path(current, rest, limit, paths)?;
+            current.edges.pop();
+            current.nodes.pop();
+        }
+        Ok(())
+    }
+
+    /// Breadth-first search, so the first path found has the fewest hops.
+    pub fn shortest_path(&self, query: &ShortestPathQuery) -> Result<Option<GraphPath>> {
+        if self.storage.get_node(&query.from)?.is_none()
+            || self.storage.get_node(&query.to)?.is_none()
+        {
+            return Ok(None);
+        }
+
+        // node -> (previous node, edge used to reach it)
+        let mut parents: HashMap<NodeId, Option<(NodeId, Uuid)>> = HashMap::new();
+        parents.insert(query.from.clone(), None);
+        let mut queue = VecDeque::from([(query.from.clone(), 0usize)]);
+
+        let mut found = query.from == query.to;
+        while let Some((node, depth)) = queue.pop_front() {
+            if found || depth >= query.max_depth {
+                break;
+            }
+            for adjacency in self.adjacent(&node, query.direction, &query.edge_labels)? {
+                if parents.contains_key(&adjacency.neighbor) {
+                    continue;
+                }
+                parents.insert(
+                    adjacency.neighbor.clone(),
+                    Some((node.clone(), adjacency.edge_id)),
+                );
+                if adjacency.neighbor == query.to {
+                    found = true;
+                    break;
+                }
+                queue.push_back((adjacency.neighbor, depth + 1));
+            }
+            if parents.len() >= query.max_visited {
+                break;
+            }
+        }
+
+        if !found {
+            return Ok(None);
+        }
+
+        let mut node_ids = vec![query.to.clone()];
+        let mut edge_ids = Vec::new();
+        while let Some(Some((previous, edge_id))) = parents.get(node_ids.last().unwrap()) {
+            edge_ids.push(*edge_id);
+            node_ids.push(previous.clone());
+        }
+        node_ids.reverse();
+        edge_ids.reverse();
+
+        let mut path = GraphPath {
+            nodes: Vec::with_capacity(node_ids.len()),
+            edges: Vec::with_capacity(edge_ids.len()),
+        };
+        for id in &node_ids {
+            match self.storage.get_node(id)? {
+                Some(node) => path.nodes.push(node),
+                None => return Ok(None),
+            }
+        }
+        for id in &edge_ids {
+            match self.storage.get_edge(id)? {
+                Some(edge) => path.edges.push(edge),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(path))
+    }
+
+    fn adjacent(
+        &self,
+        node: &NodeId,
+        direction: Direction,
+        labels: &[String],
+    ) -> Result<Vec<Adjacency>> {
+        if labels.is_empty() {
+            return self.storage.adjacent(node, direction, None);
+        }
+        let mut result = Vec::new();
+        for label in labels {
+            result.extend(self.storage.adjacent(node, direction, Some(label))?);
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,393 @@
+use crate::entity::Entity;
+use crate::search::{indexed_texts, tokenize};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use uuid::Uuid;
+
+/// Links per node on the upper layers of the HNSW graph; layer 0 keeps twice as many.
+const HNSW_M: usize = 16;
+const HNSW_EF_CONSTRUCTION: usize = 64;
+pub const HNSW_EF_SEARCH: usize = 64;
+/// Most hits a nearest-neighbor search returns, whatever `k` asks for.
+pub const MAX_VECTOR_HITS: usize = 1000;
+const HNSW_MAX_LEVEL: usize = 16;
+
+/// Turns text into a vector. Implementations must be deterministic: the same text always
+/// yields the same vector, or the stored index stops matching what queries embed.
+pub trait Embedder: Send + Sync {
+    /// Identifies the model and its settings. It is stored with the index, and a different
+    /// name makes the engine re-embed every node.
+    fn name(&self) -> String;
+    fn dimensions(&self) -> usize;
+    fn embed(&self, text: &str) -> Result<Vec<f32>>;
+}
+
+/// Built-in embedder that needs no model: stemmed words are hashed into a fixed number of
+/// dimensions (the "hashing trick"). Finds texts sharing vocabulary rather than meaning,
+/// but is fully offline and deterministic.
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn name(&self) -> String {
+        format!("hashing-v1-{}", self.dimensions)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let mut vector = vec![0.0; self.dimensions];
+        for token in tokenize(text) {
+            // FNV-1a, stable across platforms and releases unlike `DefaultHasher`
+            let hash = token
+                .term
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dimensions as u64) as usize] += sign;
+        }
+        Ok(normalize(vector))
+    }
+}
+
+/// The text a node is embedded from: the same label and string properties full-text
+/// search indexes.
+pub fn embedding_text(entity: &Entity) -> String {
+    indexed_texts(entity)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scales a vector to unit length, so that cosine similarity is a dot product.
+/// A zero vector stays zero.
+pub fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0 - a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorHit {
+    pub node: Entity,
+    /// Cosine similarity to the query, 1.0 being identical.
+    pub similarity: f32,
+}
+
+/// One point of the HNSW graph. `neighbors[level]` are its links on that layer, so the
+/// node lives on layers `0..neighbors.len()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorNode {
+    pub vector: Vec<f32>,
+    pub neighbors: Vec<Vec<Uuid>>,
+}
+
+/// Where the HNSW graph is read from.
+pub trait VectorStore {
+    fn vector_node(&self, id: &Uuid) -> Result<Option<VectorNode>>;
+    fn vector_entry(&self) -> Result<Option<Uuid>>;
+}
+
+/// Where HNSW changes are written to.
+pub trait VectorStoreMut: VectorStore {
+    fn put_vector_node(&mut self, id: &Uuid, node: &VectorNode) -> Result<()>;
+    fn delete_vector_node(&mut self, id: &Uuid) -> Result<()>;
+    fn set_vector_entry(&mut self, entry: Option<Uuid>) -> Result<()>;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, Uuid);
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Layer of a new node, drawn from the usual exponential distribution. The random draw
+/// comes from the (random) node id, so rebuilding an index reproduces the same graph.
+fn level_for(id: &Uuid) -> usize {
+    let bits = u64::from_be_bytes(id.as_bytes()[8..].try_into().expect("uuid has 16 bytes"));
+    let uniform = ((bits >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let level = -uniform.ln() / (HNSW_M as f64).ln();
+    (level as usize).min(HNSW_MAX_LEVEL)
+}
+
+fn max_links(level: usize) -> usize {
+    if level == 0 {
+        HNSW_M * 2
+    } else {
+        HNSW_M
+    }
+}
+
+/// Greedy beam search on one layer; returns up to `ef` nodes, closest first.
+fn search_layer<S: VectorStore + ?Sized>(
+    store: &S,
+    query: &[f32],
+    entry: &[Scored],
+    ef: usize,
+    level: usize,
+) -> Result<Vec<Scored>> {
+    let mut visited: HashSet<Uuid> = entry.iter().map(|s| s.1).collect();
+    let mut candidates: BinaryHeap<Reverse<Scored>> = entry.iter().copied().map(Reverse).collect();
+    let mut found: BinaryHeap<Scored> = entry.iter().copied().collect();
+
+    while let Some(Reverse(closest)) = candidates.pop() {
+        if found.len() >= ef && found.peek().is_some_and(|worst| closest.0 > worst.0) {
+            break;
+        }
+        let Some(node) = store.vector_node(&closest.1)? else {
+            continue;
+        };
+        for neighbor in node.neighbors.get(level).into_iter().flatten() {
+            if !visited.insert(*neighbor) {
+                continue;
+            }
+            // Links to removed nodes are skipped rather than eagerly cleaned up
+            let Some(other) = store.vector_node(neighbor)? else {
+                continue;
+            };
+            let scored = Scored(distance(query, &other.vector), *neighbor);
+            if found.len() < ef || found.peek().is_some_and(|worst| scored.0 < worst.0) {
+                candidates.push(Reverse(scored));
+                found.push(scored);
+                if found.len() > ef {
+                    found.pop();
+                }
+            }
+        }
+    }
+
+    Ok(found.into_sorted_vec())
+}
+
+/// Nodes closest to `query`, closest first, with their cosine distance.
+pub fn hnsw_search<S: VectorStore + ?Sized>(store: &S, query: &[f32], ef: usize) -> Result<Vec<(Uuid, f32)>> {
+    let Some(entry) = store.vector_entry()? else {
+        return Ok(Vec::new());
+    };
+    let Some(entry_node) = store.vector_node(&entry)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut current = vec![Scored(distance(query, &entry_node.vector), entry)];
+    for level in (1..entry_node.neighbors.len()).rev() {
+        current = search_layer(store, query, &current, 1, level)?;
+    }
+    Ok(search_layer(store, query, &current, ef.max(1), 0)?
+        .into_iter()
+        .map(|s| (s.1, s.0))
+        .collect())
+}
+
+/// Adds a node to the graph, replacing its previous vector if it had one.
+pub fn hnsw_insert<S: VectorStoreMut + ?Sized>(store: &mut S, id: Uuid, vector: Vec<f32>) -> Result<()> {
+    if store.vector_node(&id)?.is_some() {
+        hnsw_remove(store, &id)?;
+    }
+    let level = level_for(&id);
+
+    let entry = match store.vector_entry()? {
+        Some(entry) => store.vector_node(&entry)?.map(|node| (entry, node)),
+        None => None,
+    };
+    let Some((entry, entry_node)) = entry else {
+        store.put_vector_node(&id, &VectorNode { vector, neighbors: vec![Vec::new(); level + 1] })?;
+        return store.set_vector_entry(Some(id));
+    };
+    let top = entry_node.neighbors.len() - 1;
+
+    let mut current = vec![Scored(distance(&vector, &entry_node.vector), entry)];
+    for layer in (level + 1..=top).rev() {
+        current = search_layer(store, &vector, &current, 1, layer)?;
+    }
+    let mut neighbors = vec![Vec::new(); level + 1];
+    for layer in (0..=level.min(top)).rev() {
+        current = search_layer(store, &vector, &current, HNSW_EF_CONSTRUCTION, layer)?;
+        neighbors[layer] = current.iter().take(max_links(layer)).map(|s| s.1).collect();
+    }
+    store.put_vector_node(&id, &VectorNode { vector, neighbors: neighbors.clone() })?;
+
+    // Link back, dropping the farthest links of nodes that now have too many
+    for (layer, linked) in neighbors.iter().enumerate() {
+        for neighbor in linked {
+            let Some(mut node) = store.vector_node(neighbor)? else {
+                continue;
+            };
+            if let Some(links) = node.neighbors.get_mut(layer) {
+                links.push(id);
+            }
+            prune_links(store, &mut node, layer)?;
+            store.put_vector_node(neighbor, &node)?;
+        }
+    }
+
+    if level > top {
+        store.set_vector_entry(Some(id))?;
+    }
+    Ok(())
+}
+
+/// Removes a node and reconnects its former neighbors among themselves so the graph stays
+/// navigable.
+pub fn hnsw_remove<S: VectorStoreMut + ?Sized>(store: &mut S, id: &Uuid) -> Result<()> {
+    let Some(removed) = store.vector_node(id)? else {
+        return Ok(());
+    };
+    store.delete_vector_node(id)?;
+
+    for (layer, linked) in removed.neighbors.iter().enumerate() {
+        for neighbor in linked {
+            let Some(mut node) = store.vector_node(neighbor)? else {
+                continue;
+            };
+            if let Some(links) = node.neighbors.get_mut(layer) {
+                links.retain(|link| link != id);
+                for candidate in linked {
+                    if candidate != neighbor && !links.contains(candidate) {
+                        links.push(*candidate);
+                    }
+                }
+            }
+            prune_links(store, &mut node, layer)?;
+            store.put_vector_node(neighbor, &node)?;
+        }
+    }
+
+    if store.vector_entry()? == Some(*id) {
+        // The highest-reaching former neighbor takes over as entry point
+        let replacement = removed
+            .neighbors
+            .iter()
+            .rev()
+            .find_map(|linked| linked.first().copied());
+        store.set_vector_entry(replacement)?;
+    }
+    Ok(())
+}
+
+fn prune_links<S: VectorStore + ?Sized>(store: &S, node: &mut VectorNode, layer: usize) -> Result<()> {
+    let Some(links) = node.neighbors.get(layer) else {
+        return Ok(());
+    };
+    if links.len() <= max_links(layer) {
+        return Ok(());
+    }
+    let mut scored = Vec::with_capacity(links.len());
+    for link in links {
+        if let Some(other) = store.vector_node(link)? {
+            scored.push(Scored(distance(&node.vector, &other.vector), *link));
+        }
+    }
+    scored.sort();
+    node.neighbors[layer] = scored.into_iter().take(max_links(layer)).map(|s| s.1).collect();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        nodes: HashMap<Uuid, VectorNode>,
+        entry: Option<Uuid>,
+    }
+
+    impl VectorStore for MemoryStore {
+        fn vector_node(&self, id: &Uuid) -> Result<Option<VectorNode>> {
+            Ok(self.nodes.get(id).cloned())
+        }
+        fn vector_entry(&self) -> Result<Option<Uuid>> {
+            Ok(self.entry)
+        }
+    }
+
+    impl VectorStoreMut for MemoryStore {
+        fn put_vector_node(&mut self, id: &Uuid, node: &VectorNode) -> Result<()> {
+            self.nodes.insert(*id, node.clone());
+            Ok(())
+        }
+        fn delete_vector_node(&mut self, id: &Uuid) -> Result<()> {
+            self.nodes.remove(id);
+            Ok(())
+        }
+        fn set_vector_entry(&mut self, entry: Option<Uuid>) -> Result<()> {
+            self.entry = entry;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hnsw_matches_exact_search() {
+        // Deterministic pseudo-random unit vectors
+        let mut seed = 42u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as f32 / (1u64 << 31) as f32 - 0.5
+        };
+        let mut store = MemoryStore::default();
+        let mut points = Vec::new();
+        for _ in 0..500 {
+            let vector = normalize((0..16).map(|_| next()).collect());
+            let id = Uuid::new_v4();
+            hnsw_insert(&mut store, id, vector.clone()).unwrap();
+            points.push((id, vector));
+        }
+        for (id, _) in points.drain(..100) {
+            hnsw_remove(&mut store, &id).unwrap();
+        }
+
+        let mut found = 0;
+        for _ in 0..20 {
+            let query = normalize((0..16).map(|_| next()).collect());
+            let mut exact: Vec<(f32, Uuid)> = points.iter().map(|(id, v)| (distance(&query, v), *id)).collect();
+            exact.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let approximate: HashSet<Uuid> = hnsw_search(&store, &query, HNSW_EF_SEARCH)
+                .unwrap()
+                .into_iter()
+                .take(10)
+                .map(|(id, _)| id)
+                .collect();
+            found += exact.iter().take(10).filter(|(_, id)| approximate.contains(id)).count();
+        }
+        // Recall@10 well above 90% on this small set
+        assert!(found >= 180, "recall {}/200", found);
+    }
+}
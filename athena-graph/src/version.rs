@@ -1,7 +1,9 @@
+use crate::entity::{Edge, Entity, NodeId};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct VersionId(pub u64);
 
 impl VersionId {
@@ -19,6 +21,48 @@ pub struct Checkpoint {
     pub id: Uuid,
     pub version: VersionId,
     pub timestamp: i64,
+    /// Merkle root over all nodes and edges at `version`.
     pub hash: Vec<u8>,
+    /// On-disk copy of the database taken with the checkpoint, until it is pruned.
+    pub snapshot: Option<PathBuf>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointVerification {
+    pub checkpoint: Checkpoint,
+    pub valid: bool,
+    /// Root recomputed from the records themselves.
+    pub actual_hash: Vec<u8>,
+    /// Buckets (first byte of the record id) whose content differs from the checkpoint.
+    pub mismatched_buckets: Vec<u8>,
+}
+
+/// State of one record as written by the update that produced `version`.
+/// `value` is `None` when that update deleted the record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision<T> {
+    pub version: VersionId,
+    pub timestamp: i64,
+    pub value: Option<T>,
+}
+
+pub type NodeRevision = Revision<Entity>;
+pub type EdgeRevision = Revision<Edge>;
+
+/// Records touched by one update, kept per global version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionLogEntry {
+    pub version: VersionId,
+    pub timestamp: i64,
+    pub nodes: Vec<NodeId>,
+    pub edges: Vec<Uuid>,
+}
+
+/// Point in history for time-travel reads.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AsOf {
+    Version(VersionId),
+    /// Unix seconds; resolves to the last version committed at or before it.
+    Timestamp(i64),
+}
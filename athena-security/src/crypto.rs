@@ -1,6 +1,6 @@
 use anyhow::Result;
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     ChaCha20Poly1305, Key, Nonce,
 };
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
@@ -101,20 +101,35 @@ impl Cipher {
         }
     }
 
+    /// A fresh random key for `Cipher::new`.
+    pub fn generate_key() -> [u8; 32] {
+        ChaCha20Poly1305::generate_key(&mut OsRng).into()
+    }
+
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_with_aad(plaintext, &[])
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt_with_aad(ciphertext, &[])
+    }
+
+    /// Encrypts `plaintext` bound to `aad`: the result only decrypts with the same `aad`,
+    /// which itself is not encrypted or stored.
+    pub fn encrypt_with_aad(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
         let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
-        let ciphertext = self.cipher.encrypt(&nonce, plaintext)?;
+        let ciphertext = self.cipher.encrypt(&nonce, Payload { msg: plaintext, aad })?;
         let mut result = nonce.to_vec();
         result.extend_from_slice(&ciphertext);
         Ok(result)
     }
 
-    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+    pub fn decrypt_with_aad(&self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
         if ciphertext.len() < 12 {
             return Err(anyhow::anyhow!("Ciphertext too short"));
         }
         let nonce = Nonce::from_slice(&ciphertext[..12]);
-        let plaintext = self.cipher.decrypt(nonce, &ciphertext[12..])?;
+        let plaintext = self.cipher.decrypt(nonce, Payload { msg: &ciphertext[12..], aad })?;
         Ok(plaintext)
     }
 }
@@ -152,5 +167,14 @@ mod tests {
         let decrypted = cipher.decrypt(&ciphertext).unwrap();
         assert_eq!(plaintext, decrypted.as_slice());
     }
+
+    #[test]
+    fn test_encrypt_with_aad() {
+        let cipher = Cipher::new(&[0u8; 32]);
+        let ciphertext = cipher.encrypt_with_aad(b"secret message", b"node:1").unwrap();
+        assert_eq!(cipher.decrypt_with_aad(&ciphertext, b"node:1").unwrap(), b"secret message");
+        assert!(cipher.decrypt_with_aad(&ciphertext, b"node:2").is_err());
+        assert!(cipher.decrypt(&ciphertext).is_err());
+    }
 }
 
@@ -1,9 +1,15 @@
-use crate::{PrivateKey, PublicKey};
+use crate::{Cipher, PrivateKey, PublicKey};
 use anyhow::Result;
+use hkdf::Hkdf;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
+// What private keys are encrypted with until the key manager has a master key
+const PLACEHOLDER_MASTER_KEY: [u8; 32] = [0; 32];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyStore {
     keys: HashMap<String, KeyEntry>,
@@ -20,7 +26,8 @@ struct KeyEntry {
 pub struct KeyManager {
     store_path: PathBuf,
     store: KeyStore,
-    master_key: [u8; 32],
+    /// `None` until one is generated or supplied.
+    master_key: Option<[u8; 32]>,
 }
 
 impl KeyManager {
@@ -37,7 +44,17 @@ impl KeyManager {
         };
 
         // In production, master key should be derived from user password
-        let master_key = [0u8; 32]; // Placeholder
+        let master_key_path = master_key_path(&store_path);
+        let master_key = if master_key_path.exists() {
+            let data = std::fs::read(&master_key_path)?;
+            Some(
+                data.as_slice()
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Invalid master key in {}", master_key_path.display()))?,
+            )
+        } else {
+            None
+        };
 
         Ok(Self {
             store_path,
@@ -51,8 +68,7 @@ impl KeyManager {
         let public = private.public_key();
 
         // Encrypt private key with master key
-        let cipher = crate::Cipher::new(&self.master_key);
-        let encrypted = cipher.encrypt(&private.to_bytes())?;
+        let encrypted = self.cipher().encrypt(&private.to_bytes())?;
 
         let entry = KeyEntry {
             public_key: public.to_bytes().to_vec(),
@@ -89,12 +105,70 @@ impl KeyManager {
             None => return Ok(None),
         };
 
-        let cipher = crate::Cipher::new(&self.master_key);
-        let decrypted = cipher.decrypt(&entry.encrypted_private_key)?;
+        let decrypted = self.cipher().decrypt(&entry.encrypted_private_key)?;
         let private = PrivateKey::from_bytes(&decrypted)?;
         Ok(Some(private))
     }
 
+    /// Whether a master key was generated or supplied. Keys can only be derived once it was.
+    pub fn has_master_key(&self) -> bool {
+        self.master_key.is_some()
+    }
+
+    /// Generates a random master key and saves it next to the key store, readable only by
+    /// its owner. Fails if the key manager already has one.
+    pub fn generate_master_key(&mut self) -> Result<()> {
+        if self.master_key.is_some() {
+            anyhow::bail!("The key manager already has a master key");
+        }
+        let master_key = Cipher::generate_key();
+        let path = master_key_path(&self.store_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&path)?.write_all(&master_key)?;
+        self.set_master_key(master_key)
+    }
+
+    /// Uses `master_key` from now on, e.g. one derived from a password, without saving it.
+    /// Private keys in the store are re-encrypted with it.
+    pub fn set_master_key(&mut self, master_key: [u8; 32]) -> Result<()> {
+        let (old, new) = (self.cipher(), Cipher::new(&master_key));
+        for entry in self.store.keys.values_mut() {
+            entry.encrypted_private_key = new.encrypt(&old.decrypt(&entry.encrypted_private_key)?)?;
+        }
+        self.master_key = Some(master_key);
+        if !self.store.keys.is_empty() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// A key for one purpose (e.g. encrypting graph storage), derived from the master key
+    /// with HKDF-SHA256 so that it never has to be stored. Different contexts give
+    /// unrelated keys. Fails until the key manager has a master key.
+    pub fn derive_key(&self, context: &str) -> Result<[u8; 32]> {
+        let master_key = self
+            .master_key
+            .ok_or_else(|| anyhow::anyhow!("The key manager has no master key; generate or supply one first"))?;
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &master_key)
+            .expand(context.as_bytes(), &mut key)
+            .map_err(|_| anyhow::anyhow!("Cannot derive a key of {} bytes", key.len()))?;
+        Ok(key)
+    }
+
+    fn cipher(&self) -> Cipher {
+        Cipher::new(self.master_key.as_ref().unwrap_or(&PLACEHOLDER_MASTER_KEY))
+    }
+
     fn save(&self) -> Result<()> {
         if let Some(parent) = self.store_path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -105,3 +179,33 @@ impl KeyManager {
     }
 }
 
+/// The master key lives next to the key store, e.g. `keys.master` for `keys.bin`.
+fn master_key_path(store_path: &Path) -> PathBuf {
+    store_path.with_extension("master")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_key() {
+        let dir = std::env::temp_dir().join(format!("athena-keys-{}", rand::random::<u64>()));
+        let store_path = dir.join("keys.bin");
+        let mut key_manager = KeyManager::new(&store_path).unwrap();
+        assert!(key_manager.derive_key("storage").is_err());
+        let public = key_manager.generate_key("default".to_string()).unwrap();
+
+        // Private keys written before the master key existed stay readable
+        key_manager.generate_master_key().unwrap();
+        assert!(key_manager.generate_master_key().is_err());
+        let key = key_manager.derive_key("storage").unwrap();
+        assert_ne!(key, key_manager.derive_key("storage/1").unwrap());
+
+        let reopened = KeyManager::new(&store_path).unwrap();
+        assert_eq!(reopened.derive_key("storage").unwrap(), key);
+        let private = reopened.get_private_key("default").unwrap().unwrap();
+        assert_eq!(private.public_key().to_bytes(), public.to_bytes());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -44,6 +44,20 @@ export const graphApi = {
     await apiClient.delete(`/nodes/${id}`)
   },
 
+  getNeighbors: async (
+    id: string,
+    direction: 'outgoing' | 'incoming' | 'both' = 'both',
+    label?: string,
+  ): Promise<Node[]> => {
+    const response = await apiClient.get(`/nodes/${id}/neighbors`, { params: { direction, label } })
+    return response.data.nodes
+  },
+
+  getNodeEdges: async (id: string): Promise<Edge[]> => {
+    const response = await apiClient.get(`/nodes/${id}/edges`)
+    return response.data.edges
+  },
+
   listEdges: async (): Promise<Edge[]> => {
     const response = await apiClient.get('/edges')
     return response.data.edges